@@ -0,0 +1,100 @@
+// makes a pretend gamepad through uinput, for trying out gamepad support without one. run the game
+// alongside it (it needs write access to /dev/uinput, so probably as root or in the input group):
+// it presses start every few seconds, which pauses the demo, and spins the left stick around in
+// between. rumble requests from the game get printed.
+
+#[cfg(target_os = "linux")]
+fn main() -> anyhow::Result<()> {
+    use std::os::fd::AsRawFd;
+    use std::time::{Duration, Instant};
+
+    use evdev::{
+        uinput::VirtualDeviceBuilder, AbsInfo, AbsoluteAxisType, AttributeSet, EventType,
+        FFEffectType, InputEvent, InputEventKind, Key, UinputAbsSetup,
+    };
+
+    let mut keys = AttributeSet::<Key>::new();
+    for key in [
+        Key::BTN_SOUTH,
+        Key::BTN_EAST,
+        Key::BTN_NORTH,
+        Key::BTN_WEST,
+        Key::BTN_TL,
+        Key::BTN_TR,
+        Key::BTN_SELECT,
+        Key::BTN_START,
+    ] {
+        keys.insert(key);
+    }
+    let mut effects = AttributeSet::<FFEffectType>::new();
+    effects.insert(FFEffectType::FF_RUMBLE);
+    let stick = AbsInfo::new(0, -32768, 32767, 16, 128, 0);
+
+    let mut device = VirtualDeviceBuilder::new()?
+        .name("virtual gamepad")
+        .with_keys(&keys)?
+        .with_absolute_axis(&UinputAbsSetup::new(AbsoluteAxisType::ABS_X, stick))?
+        .with_absolute_axis(&UinputAbsSetup::new(AbsoluteAxisType::ABS_Y, stick))?
+        .with_ff(&effects)?
+        .with_ff_effects_max(4)
+        .build()?;
+    // reading waits for the game to ask for something otherwise, and the stick has to keep moving.
+    // safe enough: it's only flipping a flag on a descriptor the device is holding onto.
+    unsafe {
+        let fd = device.as_raw_fd();
+        libc::fcntl(
+            fd,
+            libc::F_SETFL,
+            libc::fcntl(fd, libc::F_GETFL) | libc::O_NONBLOCK,
+        );
+    }
+    println!("virtual gamepad up; ctrl+c to unplug it.");
+
+    let start = Instant::now();
+    let mut last_press = start;
+    loop {
+        let angle = start.elapsed().as_secs_f32();
+        let (x, y) = (
+            (angle.cos() * 32767.0) as i32,
+            (angle.sin() * 32767.0) as i32,
+        );
+        let mut events = vec![
+            InputEvent::new(EventType::ABSOLUTE, AbsoluteAxisType::ABS_X.0, x),
+            InputEvent::new(EventType::ABSOLUTE, AbsoluteAxisType::ABS_Y.0, y),
+        ];
+        if last_press.elapsed() >= Duration::from_secs(3) {
+            last_press = Instant::now();
+            events.push(InputEvent::new(EventType::KEY, Key::BTN_START.code(), 1));
+        } else if last_press.elapsed() >= Duration::from_millis(100) {
+            events.push(InputEvent::new(EventType::KEY, Key::BTN_START.code(), 0));
+        }
+        device.emit(&events)?;
+
+        // the kernel won't finish an upload until uinput answers it.
+        let events = match device.fetch_events() {
+            Ok(events) => events.collect(),
+            Err(error) if error.kind() == std::io::ErrorKind::WouldBlock => Vec::new(),
+            Err(error) => return Err(error.into()),
+        };
+        for event in events {
+            match event.kind() {
+                InputEventKind::UInput(code) if code == evdev::UInputEventType::UI_FF_UPLOAD.0 => {
+                    let mut upload = device.process_ff_upload(event)?;
+                    println!("rumble: {:?}", upload.effect().kind);
+                    upload.set_retval(0);
+                }
+                InputEventKind::UInput(code) if code == evdev::UInputEventType::UI_FF_ERASE.0 => {
+                    let mut erase = device.process_ff_erase(event)?;
+                    erase.set_retval(0);
+                }
+                _ => {}
+            }
+        }
+        std::thread::sleep(Duration::from_millis(16));
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn main() {
+    eprintln!("uinput is linux only.");
+}
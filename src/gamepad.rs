@@ -0,0 +1,699 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::time::Duration;
+
+use crate::input::{Button, GamepadAxis, GamepadButton, Input};
+
+pub type GamepadId = usize;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum GamepadEvent {
+    Connected { id: GamepadId, name: String },
+    Disconnected(GamepadId),
+}
+
+// what the platform side hands over. axes are already scaled to the ranges input::GamepadAxis
+// describes, but nothing has been dead-zoned yet.
+enum Message {
+    Connected {
+        id: GamepadId,
+        name: String,
+        rumble: Option<Box<backend::Rumble>>,
+    },
+    Disconnected(GamepadId),
+    Button {
+        id: GamepadId,
+        button: GamepadButton,
+        down: bool,
+    },
+    Axis {
+        id: GamepadId,
+        axis: GamepadAxis,
+        value: f32,
+    },
+}
+
+pub struct Gamepad {
+    pub name: String,
+    buttons: HashSet<GamepadButton>,
+    axes: HashMap<GamepadAxis, f32>,
+    rumble: Option<Box<backend::Rumble>>,
+}
+
+impl Gamepad {
+    pub fn held(&self, button: GamepadButton) -> bool {
+        self.buttons.contains(&button)
+    }
+
+    pub fn can_rumble(&self) -> bool {
+        self.rumble.is_some()
+    }
+}
+
+// every connected gamepad. all of them feed the same buttons and axes in input::Input, so any
+// controller can play; the ones here are for telling them apart (local multiplayer, say).
+pub struct Gamepads {
+    backend: backend::Backend,
+    pads: BTreeMap<GamepadId, Gamepad>,
+    // sticks are dead-zoned by how far they're pushed in any direction, so diagonals don't snap
+    // to the axes; triggers just by how far they're pulled. what's left gets stretched back out to
+    // the full range.
+    pub stick_dead_zone: f32,
+    pub trigger_dead_zone: f32,
+    // what was last handed to input, so only changes get passed on.
+    buttons: HashSet<GamepadButton>,
+}
+
+impl Default for Gamepads {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Gamepads {
+    pub fn new() -> Self {
+        Self {
+            backend: backend::Backend::new(),
+            pads: BTreeMap::new(),
+            stick_dead_zone: 0.15,
+            trigger_dead_zone: 0.05,
+            buttons: HashSet::new(),
+        }
+    }
+
+    // takes in whatever the gamepads did since last time and passes it on to input. call this
+    // once per update, before input.tick().
+    pub fn poll(&mut self, input: &mut Input) -> Vec<GamepadEvent> {
+        let mut events = Vec::new();
+        for message in self.backend.poll() {
+            match message {
+                Message::Connected { id, name, rumble } => {
+                    log::info!("gamepad {} connected: {}", id, name);
+                    events.push(GamepadEvent::Connected {
+                        id,
+                        name: name.clone(),
+                    });
+                    self.pads.insert(
+                        id,
+                        Gamepad {
+                            name,
+                            buttons: HashSet::new(),
+                            axes: HashMap::new(),
+                            rumble,
+                        },
+                    );
+                }
+                Message::Disconnected(id) => {
+                    if let Some(pad) = self.pads.remove(&id) {
+                        log::info!("gamepad {} disconnected: {}", id, pad.name);
+                        events.push(GamepadEvent::Disconnected(id));
+                    }
+                }
+                Message::Button { id, button, down } => {
+                    if let Some(pad) = self.pads.get_mut(&id) {
+                        match down {
+                            true => pad.buttons.insert(button),
+                            false => pad.buttons.remove(&button),
+                        };
+                    }
+                }
+                Message::Axis { id, axis, value } => {
+                    if let Some(pad) = self.pads.get_mut(&id) {
+                        pad.axes.insert(axis, value);
+                    }
+                }
+            }
+        }
+
+        let buttons: HashSet<GamepadButton> = self
+            .pads
+            .values()
+            .flat_map(|pad| pad.buttons.iter().copied())
+            .collect();
+        for button in buttons.difference(&self.buttons) {
+            input.button(Button::Gamepad(*button), true);
+        }
+        for button in self.buttons.difference(&buttons) {
+            input.button(Button::Gamepad(*button), false);
+        }
+        self.buttons = buttons;
+
+        // whichever pad is pushing each axis the furthest wins it.
+        for axis in AXES {
+            let value = self
+                .pads
+                .keys()
+                .map(|id| self.axis(*id, axis))
+                .fold(0.0, |a: f32, b: f32| if b.abs() > a.abs() { b } else { a });
+            input.gamepad_axis(axis, value);
+        }
+
+        events
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (GamepadId, &Gamepad)> {
+        self.pads.iter().map(|(id, pad)| (*id, pad))
+    }
+
+    // dead-zoned; 0.0 for pads that aren't there.
+    pub fn axis(&self, id: GamepadId, axis: GamepadAxis) -> f32 {
+        let Some(pad) = self.pads.get(&id) else {
+            return 0.0;
+        };
+        let raw = |axis| pad.axes.get(&axis).copied().unwrap_or(0.0);
+        let rescale = |amount: f32, dead_zone: f32| {
+            ((amount - dead_zone) / (1.0 - dead_zone)).clamp(0.0, 1.0)
+        };
+        let stick = |x, y| {
+            let (x, y): (f32, f32) = (raw(x), raw(y));
+            let length = x.hypot(y);
+            match length > self.stick_dead_zone {
+                true => rescale(length, self.stick_dead_zone) / length,
+                false => 0.0,
+            }
+        };
+        match axis {
+            GamepadAxis::LeftStickX | GamepadAxis::LeftStickY => {
+                raw(axis) * stick(GamepadAxis::LeftStickX, GamepadAxis::LeftStickY)
+            }
+            GamepadAxis::RightStickX | GamepadAxis::RightStickY => {
+                raw(axis) * stick(GamepadAxis::RightStickX, GamepadAxis::RightStickY)
+            }
+            GamepadAxis::LeftTrigger | GamepadAxis::RightTrigger => {
+                rescale(raw(axis), self.trigger_dead_zone)
+            }
+        }
+    }
+
+    // shakes the pad, with `strong` being the heavy motor and `weak` the light one (0.0 to 1.0
+    // each). replaces whatever it was already doing. returns false if the pad can't rumble or
+    // isn't there.
+    pub fn rumble(&mut self, id: GamepadId, strong: f32, weak: f32, duration: Duration) -> bool {
+        let Some(rumble) = self.pads.get_mut(&id).and_then(|pad| pad.rumble.as_mut()) else {
+            return false;
+        };
+        match rumble.play(strong.clamp(0.0, 1.0), weak.clamp(0.0, 1.0), duration) {
+            Ok(()) => true,
+            Err(error) => {
+                log::warn!("gamepad {} couldn't rumble: {}", id, error);
+                false
+            }
+        }
+    }
+}
+
+const AXES: [GamepadAxis; 6] = [
+    GamepadAxis::LeftStickX,
+    GamepadAxis::LeftStickY,
+    GamepadAxis::RightStickX,
+    GamepadAxis::RightStickY,
+    GamepadAxis::LeftTrigger,
+    GamepadAxis::RightTrigger,
+];
+
+// straight from the kernel's evdev interface, so no libraries have to be installed and virtual
+// pads made through uinput show up like any other (see examples/virtual_gamepad.rs).
+#[cfg(target_os = "linux")]
+mod backend {
+    use std::collections::HashSet;
+    use std::ffi::OsStr;
+    use std::path::{Path, PathBuf};
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    use evdev::{AbsoluteAxisType, Device, FFEffectKind, FFEffectType, InputEventKind, Key};
+    use nix::errno::Errno;
+    use nix::sys::inotify::{AddWatchFlags, InitFlags, Inotify};
+
+    use super::{GamepadId, Message};
+    use crate::input::{GamepadAxis, GamepadButton};
+
+    const DIRECTORY: &str = "/dev/input";
+    // how often /dev/input gets looked through for new pads, if it can't be watched.
+    const SCAN_INTERVAL: Duration = Duration::from_secs(1);
+
+    pub struct Backend {
+        receiver: mpsc::Receiver<Message>,
+    }
+
+    impl Backend {
+        pub fn new() -> Self {
+            let (sender, receiver) = mpsc::channel();
+            std::thread::Builder::new()
+                .name("gamepad scanner".to_string())
+                .spawn(move || scan(sender))
+                .expect("couldn't start the gamepad scanner");
+            Self { receiver }
+        }
+
+        pub fn poll(&mut self) -> Vec<Message> {
+            self.receiver.try_iter().collect()
+        }
+    }
+
+    // the reading is done on a separate handle to the same device, so rumbling doesn't have to
+    // wait on a thread that's blocked reading.
+    pub struct Rumble {
+        device: Device,
+        effect: Option<evdev::FFEffect>,
+    }
+
+    impl Rumble {
+        pub fn play(&mut self, strong: f32, weak: f32, duration: Duration) -> Result<(), String> {
+            let data = evdev::FFEffectData {
+                direction: 0,
+                trigger: evdev::FFTrigger::default(),
+                replay: evdev::FFReplay {
+                    length: duration.as_millis().min(u16::MAX as u128) as u16,
+                    delay: 0,
+                },
+                kind: FFEffectKind::Rumble {
+                    strong_magnitude: (strong * u16::MAX as f32) as u16,
+                    weak_magnitude: (weak * u16::MAX as f32) as u16,
+                },
+            };
+            // dropping the old effect takes it off the device.
+            self.effect = None;
+            let mut effect = self
+                .device
+                .upload_ff_effect(data)
+                .map_err(|error| error.to_string())?;
+            effect.play(1).map_err(|error| error.to_string())?;
+            self.effect = Some(effect);
+            Ok(())
+        }
+    }
+
+    fn scan(sender: mpsc::Sender<Message>) {
+        // the watch goes on before the first look, so nothing plugged in between the two gets
+        // missed. without inotify, it's back to looking through the whole directory every so often.
+        let mut inotify = match watch() {
+            Ok(inotify) => Some(inotify),
+            Err(error) => {
+                log::warn!("can't watch {} for gamepads: {}", DIRECTORY, error);
+                None
+            }
+        };
+        // every node that's been opened and looked at, gamepad or not, so each one only gets
+        // opened the once.
+        let mut seen: HashSet<PathBuf> = HashSet::new();
+        let mut next_id: GamepadId = 0;
+        let mut paths = nodes();
+        loop {
+            for path in paths {
+                if seen.contains(&path) {
+                    continue;
+                }
+                // udev tends to make the node before it's readable; that shows up as a change of
+                // attributes later, which brings it back round here.
+                let Ok(device) = Device::open(&path) else {
+                    continue;
+                };
+                seen.insert(path.clone());
+                if !is_gamepad(&device) {
+                    continue;
+                }
+                let id = next_id;
+                next_id += 1;
+
+                let rumble = match device.supported_ff() {
+                    Some(effects) if effects.contains(FFEffectType::FF_RUMBLE) => {
+                        Device::open(&path).ok().map(|device| {
+                            Box::new(Rumble {
+                                device,
+                                effect: None,
+                            })
+                        })
+                    }
+                    _ => None,
+                };
+                let connected = Message::Connected {
+                    id,
+                    name: device.name().unwrap_or("unnamed gamepad").to_string(),
+                    rumble,
+                };
+                if sender.send(connected).is_err() {
+                    return; // nobody's listening anymore.
+                }
+
+                let sender = sender.clone();
+                let _ = std::thread::Builder::new()
+                    .name(format!("gamepad {}", id))
+                    .spawn(move || {
+                        read(id, device, &sender);
+                        let _ = sender.send(Message::Disconnected(id));
+                    });
+            }
+
+            paths = match inotify.map(|inotify| inotify.read_events()) {
+                Some(Ok(events)) => {
+                    let mut paths = Vec::new();
+                    for event in events {
+                        let Some(name) = event.name.filter(|name| is_node(name)) else {
+                            continue;
+                        };
+                        let path = Path::new(DIRECTORY).join(name);
+                        match event.mask.contains(AddWatchFlags::IN_DELETE) {
+                            true => {
+                                seen.remove(&path);
+                            }
+                            false => paths.push(path),
+                        }
+                    }
+                    paths
+                }
+                Some(Err(Errno::EINTR)) => Vec::new(),
+                Some(Err(error)) => {
+                    log::warn!("stopped watching {} for gamepads: {}", DIRECTORY, error);
+                    inotify = None;
+                    Vec::new()
+                }
+                None => {
+                    std::thread::sleep(SCAN_INTERVAL);
+                    let paths = nodes();
+                    seen.retain(|path| paths.contains(path));
+                    paths
+                }
+            };
+        }
+    }
+
+    fn watch() -> nix::Result<Inotify> {
+        let inotify = Inotify::init(InitFlags::IN_CLOEXEC)?;
+        inotify.add_watch(
+            DIRECTORY,
+            AddWatchFlags::IN_CREATE | AddWatchFlags::IN_ATTRIB | AddWatchFlags::IN_DELETE,
+        )?;
+        Ok(inotify)
+    }
+
+    // the event nodes in /dev/input, oldest first so ids come out in a sensible order.
+    fn nodes() -> Vec<PathBuf> {
+        let Ok(entries) = std::fs::read_dir(DIRECTORY) else {
+            return Vec::new();
+        };
+        let mut paths: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| is_node(&entry.file_name()))
+            .map(|entry| entry.path())
+            .collect();
+        paths.sort_by_key(|path| {
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            name["event".len()..].parse::<u32>().unwrap_or(u32::MAX)
+        });
+        paths
+    }
+
+    // the joystick api's js* nodes (and the by-id links and such) are the same devices again.
+    fn is_node(name: &OsStr) -> bool {
+        name.to_string_lossy().starts_with("event")
+    }
+
+    // keyboards and mice show up in /dev/input too; a gamepad is anything with a face button and
+    // a stick.
+    fn is_gamepad(device: &Device) -> bool {
+        let has_button = device
+            .supported_keys()
+            .is_some_and(|keys| keys.contains(Key::BTN_SOUTH));
+        let has_stick = device
+            .supported_absolute_axes()
+            .is_some_and(|axes| axes.contains(AbsoluteAxisType::ABS_X));
+        has_button && has_stick
+    }
+
+    // until the device goes away.
+    fn read(id: GamepadId, mut device: Device, sender: &mpsc::Sender<Message>) {
+        let Ok(ranges) = device.get_abs_state() else {
+            return;
+        };
+        loop {
+            let Ok(events) = device.fetch_events() else {
+                return;
+            };
+            for event in events {
+                let messages = match event.kind() {
+                    InputEventKind::Key(key) => button(key)
+                        .map(|button| Message::Button {
+                            id,
+                            button,
+                            down: event.value() != 0,
+                        })
+                        .into_iter()
+                        .collect(),
+                    // the d-pad is a hat on a lot of pads, which goes -1, 0 or 1.
+                    InputEventKind::AbsAxis(AbsoluteAxisType::ABS_HAT0X) => hat(
+                        id,
+                        event.value(),
+                        GamepadButton::DPadLeft,
+                        GamepadButton::DPadRight,
+                    ),
+                    InputEventKind::AbsAxis(AbsoluteAxisType::ABS_HAT0Y) => hat(
+                        id,
+                        event.value(),
+                        GamepadButton::DPadUp,
+                        GamepadButton::DPadDown,
+                    ),
+                    InputEventKind::AbsAxis(code) => match axis(code) {
+                        Some(axis) => {
+                            let range = ranges[code.0 as usize];
+                            let (min, max) = (range.minimum as f32, range.maximum as f32);
+                            let amount = match max > min {
+                                true => (event.value() as f32 - min) / (max - min),
+                                false => 0.0,
+                            };
+                            let value = match axis {
+                                GamepadAxis::LeftTrigger | GamepadAxis::RightTrigger => amount,
+                                // evdev has y going down.
+                                GamepadAxis::LeftStickY | GamepadAxis::RightStickY => {
+                                    1.0 - amount * 2.0
+                                }
+                                _ => amount * 2.0 - 1.0,
+                            };
+                            vec![Message::Axis { id, axis, value }]
+                        }
+                        None => Vec::new(),
+                    },
+                    _ => Vec::new(),
+                };
+                for message in messages {
+                    if sender.send(message).is_err() {
+                        return;
+                    }
+                }
+            }
+        }
+    }
+
+    fn hat(
+        id: GamepadId,
+        value: i32,
+        negative: GamepadButton,
+        positive: GamepadButton,
+    ) -> Vec<Message> {
+        vec![
+            Message::Button {
+                id,
+                button: negative,
+                down: value < 0,
+            },
+            Message::Button {
+                id,
+                button: positive,
+                down: value > 0,
+            },
+        ]
+    }
+
+    fn button(key: Key) -> Option<GamepadButton> {
+        Some(match key {
+            Key::BTN_SOUTH => GamepadButton::South,
+            Key::BTN_EAST => GamepadButton::East,
+            Key::BTN_NORTH => GamepadButton::North,
+            Key::BTN_WEST => GamepadButton::West,
+            Key::BTN_TL => GamepadButton::LeftBumper,
+            Key::BTN_TR => GamepadButton::RightBumper,
+            Key::BTN_TL2 => GamepadButton::LeftTrigger,
+            Key::BTN_TR2 => GamepadButton::RightTrigger,
+            Key::BTN_SELECT => GamepadButton::Select,
+            Key::BTN_START => GamepadButton::Start,
+            Key::BTN_MODE => GamepadButton::Mode,
+            Key::BTN_THUMBL => GamepadButton::LeftStick,
+            Key::BTN_THUMBR => GamepadButton::RightStick,
+            Key::BTN_DPAD_UP => GamepadButton::DPadUp,
+            Key::BTN_DPAD_DOWN => GamepadButton::DPadDown,
+            Key::BTN_DPAD_LEFT => GamepadButton::DPadLeft,
+            Key::BTN_DPAD_RIGHT => GamepadButton::DPadRight,
+            _ => return None,
+        })
+    }
+
+    fn axis(code: AbsoluteAxisType) -> Option<GamepadAxis> {
+        Some(match code {
+            AbsoluteAxisType::ABS_X => GamepadAxis::LeftStickX,
+            AbsoluteAxisType::ABS_Y => GamepadAxis::LeftStickY,
+            AbsoluteAxisType::ABS_RX => GamepadAxis::RightStickX,
+            AbsoluteAxisType::ABS_RY => GamepadAxis::RightStickY,
+            AbsoluteAxisType::ABS_Z => GamepadAxis::LeftTrigger,
+            AbsoluteAxisType::ABS_RZ => GamepadAxis::RightTrigger,
+            _ => return None,
+        })
+    }
+}
+
+// つづ: other platforms. until then, no gamepad ever connects.
+#[cfg(not(target_os = "linux"))]
+mod backend {
+    use std::time::Duration;
+
+    use super::Message;
+
+    pub struct Backend;
+
+    impl Backend {
+        pub fn new() -> Self {
+            Self
+        }
+
+        pub fn poll(&mut self) -> Vec<Message> {
+            Vec::new()
+        }
+    }
+
+    pub struct Rumble;
+
+    impl Rumble {
+        pub fn play(
+            &mut self,
+            _strong: f32,
+            _weak: f32,
+            _duration: Duration,
+        ) -> Result<(), String> {
+            Err("gamepads aren't supported on this platform".to_string())
+        }
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use evdev::{
+        uinput::{VirtualDevice, VirtualDeviceBuilder},
+        AbsInfo, AbsoluteAxisType, AttributeSet, EventType, InputEvent, Key, UinputAbsSetup,
+    };
+
+    use super::*;
+    use crate::input::Bindings;
+
+    const NAME: &str = "hekox test gamepad";
+
+    fn virtual_pad() -> std::io::Result<VirtualDevice> {
+        let mut keys = AttributeSet::<Key>::new();
+        keys.insert(Key::BTN_SOUTH);
+        keys.insert(Key::BTN_START);
+        let stick = AbsInfo::new(0, -32768, 32767, 16, 128, 0);
+        VirtualDeviceBuilder::new()?
+            .name(NAME)
+            .with_keys(&keys)?
+            .with_absolute_axis(&UinputAbsSetup::new(AbsoluteAxisType::ABS_X, stick))?
+            .with_absolute_axis(&UinputAbsSetup::new(AbsoluteAxisType::ABS_Y, stick))?
+            .build()
+    }
+
+    // polls (and ticks input) until `done` says so, or gives up after a few seconds.
+    fn wait_for(
+        gamepads: &mut Gamepads,
+        input: &mut Input,
+        mut done: impl FnMut(&Gamepads, &Input, &[GamepadEvent]) -> bool,
+    ) -> bool {
+        let start = Instant::now();
+        while start.elapsed() < Duration::from_secs(5) {
+            let events = gamepads.poll(input);
+            input.tick();
+            if done(gamepads, input, &events) {
+                return true;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        false
+    }
+
+    // plugs in a pad through uinput and follows it all the way through to input. needs write
+    // access to /dev/uinput (and a /dev/input to watch), so it's skipped wherever that isn't there.
+    #[test]
+    fn uinput_pad() {
+        let Ok(mut device) = virtual_pad() else {
+            eprintln!("skipping: can't make a virtual gamepad through /dev/uinput");
+            return;
+        };
+        let bindings = Bindings::from_toml(
+            r#"
+            [actions]
+            pause = [{ gamepad = "Start" }]
+            [axes]
+            move_x = [{ gamepad = "LeftStickX" }]
+            "#,
+        )
+        .unwrap();
+        let mut input = Input::new(bindings);
+        let mut gamepads = Gamepads::new();
+
+        // the scanner may well pick up real pads too; only the virtual one counts.
+        let id = |gamepads: &Gamepads| {
+            gamepads
+                .iter()
+                .find(|(_, pad)| pad.name == NAME)
+                .map(|(id, _)| id)
+        };
+        assert!(
+            wait_for(&mut gamepads, &mut input, |gamepads, _, _| id(gamepads)
+                .is_some()),
+            "the virtual gamepad never connected"
+        );
+        let pad = id(&gamepads).unwrap();
+        fn get(gamepads: &Gamepads, pad: GamepadId) -> Option<&Gamepad> {
+            gamepads
+                .iter()
+                .find(|(id, _)| *id == pad)
+                .map(|(_, gamepad)| gamepad)
+        }
+        assert!(!get(&gamepads, pad).unwrap().can_rumble());
+
+        device
+            .emit(&[
+                InputEvent::new(EventType::KEY, Key::BTN_START.code(), 1),
+                InputEvent::new(EventType::ABSOLUTE, AbsoluteAxisType::ABS_X.0, 32767),
+            ])
+            .unwrap();
+        assert!(
+            wait_for(&mut gamepads, &mut input, |gamepads, input, _| {
+                get(gamepads, pad).unwrap().held(GamepadButton::Start)
+                    && input.held("pause")
+                    && input.axis("move_x") > 0.99
+            }),
+            "start and the stick never came through"
+        );
+        assert!(gamepads.axis(pad, GamepadAxis::LeftStickX) > 0.99);
+
+        device
+            .emit(&[
+                InputEvent::new(EventType::KEY, Key::BTN_START.code(), 0),
+                InputEvent::new(EventType::ABSOLUTE, AbsoluteAxisType::ABS_X.0, 0),
+            ])
+            .unwrap();
+        assert!(
+            wait_for(&mut gamepads, &mut input, |_, input, _| {
+                !input.button_held(Button::Gamepad(GamepadButton::Start))
+                    && input.axis("move_x") == 0.0
+            }),
+            "start was never let go of"
+        );
+
+        drop(device);
+        assert!(
+            wait_for(&mut gamepads, &mut input, |_, _, events| events
+                .contains(&GamepadEvent::Disconnected(pad))),
+            "the virtual gamepad never disconnected"
+        );
+        assert!(get(&gamepads, pad).is_none());
+    }
+}
@@ -0,0 +1,445 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+use winit::event::{
+    DeviceEvent, ElementState, Event, KeyboardInput, MouseButton, MouseScrollDelta, Touch,
+    TouchPhase, VirtualKeyCode, WindowEvent,
+};
+
+// anything that's either down or up.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Button {
+    Key(VirtualKeyCode),
+    Mouse(MouseButton),
+    Gamepad(GamepadButton),
+}
+
+// named by where they are rather than what's printed on them, since every brand prints something
+// different.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum GamepadButton {
+    South,
+    East,
+    North,
+    West,
+    LeftBumper,
+    RightBumper,
+    LeftTrigger,
+    RightTrigger,
+    Select,
+    Start,
+    Mode,
+    LeftStick,
+    RightStick,
+    DPadUp,
+    DPadDown,
+    DPadLeft,
+    DPadRight,
+}
+
+// sticks go from -1.0 to 1.0 with up and right being positive; triggers go from 0.0 to 1.0.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum GamepadAxis {
+    LeftStickX,
+    LeftStickY,
+    RightStickX,
+    RightStickY,
+    LeftTrigger,
+    RightTrigger,
+}
+
+// how far the mouse went over the tick. these aren't clamped, since there's no natural limit.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum MouseAxis {
+    X,
+    Y,     // down is positive, same as the cursor.
+    Wheel, // in lines; up is positive.
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AxisBinding {
+    Buttons { negative: Button, positive: Button },
+    Gamepad(GamepadAxis),
+    Mouse(MouseAxis),
+}
+
+// which physical inputs make up each action and axis. loaded from (and saved back to) toml like:
+//
+// [actions]
+// jump = [{ key = "Space" }, { gamepad = "South" }]
+//
+// [axes]
+// move_x = [{ buttons = { negative = { key = "A" }, positive = { key = "D" } } }, { gamepad = "LeftStickX" }]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Bindings {
+    #[serde(default)]
+    pub actions: BTreeMap<String, Vec<Button>>,
+    #[serde(default)]
+    pub axes: BTreeMap<String, Vec<AxisBinding>>,
+}
+
+impl Bindings {
+    pub fn from_toml(text: &str) -> Result<Self, String> {
+        toml::from_str(text).map_err(|error| format!("couldn't read the bindings: {}", error))
+    }
+
+    pub fn to_toml(&self) -> Result<String, String> {
+        toml::to_string_pretty(self)
+            .map_err(|error| format!("couldn't write the bindings: {}", error))
+    }
+}
+
+// everything input did over one tick, which is all an update gets to see of it. kept in a fixed
+// order (and without the empty bits) so the same tick always writes out the same way.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Tick {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub down: Vec<Button>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub pressed: Vec<Button>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub released: Vec<Button>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub gamepad_axes: Vec<(GamepadAxis, f32)>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mouse: Vec<(MouseAxis, f32)>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cursor: Option<(f32, f32)>,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub text: String,
+}
+
+fn sorted_buttons(buttons: &HashSet<Button>) -> Vec<Button> {
+    let mut buttons: Vec<Button> = buttons.iter().copied().collect();
+    // there's no Ord on winit's enums, but the debug names are stable enough to sort by.
+    buttons.sort_by_cached_key(|button| format!("{:?}", button));
+    buttons
+}
+
+fn sorted_axes<A: Copy + std::fmt::Debug>(axes: &HashMap<A, f32>) -> Vec<(A, f32)> {
+    let mut axes: Vec<(A, f32)> = axes
+        .iter()
+        .filter(|(_, value)| **value != 0.0)
+        .map(|(axis, value)| (*axis, *value))
+        .collect();
+    axes.sort_by_cached_key(|(axis, _)| format!("{:?}", axis));
+    axes
+}
+
+// everything the game can ask about input, as of the current tick. winit events come in whenever,
+// so they're held back until tick() is called at the start of an update; that way a press is seen
+// by exactly one update no matter how many updates a frame gets.
+#[derive(Default)]
+pub struct Input {
+    pub bindings: Bindings,
+    // what's down right now, as opposed to as of the tick.
+    live: HashSet<Button>,
+    live_gamepad_axes: HashMap<GamepadAxis, f32>,
+    down: HashSet<Button>,
+    pressed: HashSet<Button>,
+    released: HashSet<Button>,
+    pending_pressed: HashSet<Button>,
+    pending_released: HashSet<Button>,
+    gamepad_axes: HashMap<GamepadAxis, f32>,
+    mouse: HashMap<MouseAxis, f32>,
+    pending_mouse: HashMap<MouseAxis, f32>,
+    // in physical pixels from the window's top left corner; None while it's outside.
+    live_cursor: Option<(f32, f32)>,
+    cursor: Option<(f32, f32)>,
+    // the finger standing in for the mouse, if any; see handle_event().
+    touch: Option<u64>,
+    pending_text: String,
+    text: String,
+    listening: Option<String>,
+    // the keys have gone to something else this tick, like a text field; see take_keyboard().
+    keyboard_taken: bool,
+}
+
+impl Input {
+    pub fn new(bindings: Bindings) -> Self {
+        Self {
+            bindings,
+            ..Default::default()
+        }
+    }
+
+    // call this at the start of every update.
+    pub fn tick(&mut self) {
+        self.pressed = std::mem::take(&mut self.pending_pressed);
+        self.released = std::mem::take(&mut self.pending_released);
+        self.mouse = std::mem::take(&mut self.pending_mouse);
+        self.down.clone_from(&self.live);
+        self.gamepad_axes.clone_from(&self.live_gamepad_axes);
+        self.cursor = self.live_cursor;
+        self.text = std::mem::take(&mut self.pending_text);
+        self.keyboard_taken = false;
+        self.listen_for_binding();
+    }
+
+    // what this tick's input was, for recording.
+    pub fn snapshot(&self) -> Tick {
+        Tick {
+            down: sorted_buttons(&self.down),
+            pressed: sorted_buttons(&self.pressed),
+            released: sorted_buttons(&self.released),
+            gamepad_axes: sorted_axes(&self.gamepad_axes),
+            mouse: sorted_axes(&self.mouse),
+            cursor: self.cursor,
+            text: self.text.clone(),
+        }
+    }
+
+    // call this instead of tick() to play back a recorded tick. whatever came in live since the
+    // last tick is thrown away so it can't leak into the next one.
+    pub fn replay(&mut self, tick: &Tick) {
+        self.pending_pressed.clear();
+        self.pending_released.clear();
+        self.pending_mouse.clear();
+        self.pending_text.clear();
+        self.down = tick.down.iter().copied().collect();
+        self.pressed = tick.pressed.iter().copied().collect();
+        self.released = tick.released.iter().copied().collect();
+        self.gamepad_axes = tick.gamepad_axes.iter().copied().collect();
+        self.mouse = tick.mouse.iter().copied().collect();
+        self.cursor = tick.cursor;
+        self.text.clone_from(&tick.text);
+        self.keyboard_taken = false;
+        self.listen_for_binding();
+    }
+
+    pub fn handle_event(&mut self, event: &Event<()>) {
+        match event {
+            Event::WindowEvent { event, .. } => match event {
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            state,
+                            virtual_keycode: Some(key),
+                            ..
+                        },
+                    ..
+                } => self.button(Button::Key(*key), *state == ElementState::Pressed),
+                WindowEvent::MouseInput { state, button, .. } => {
+                    self.button(Button::Mouse(*button), *state == ElementState::Pressed)
+                }
+                WindowEvent::MouseWheel { delta, .. } => {
+                    let lines = match delta {
+                        MouseScrollDelta::LineDelta(_, y) => *y,
+                        MouseScrollDelta::PixelDelta(position) => position.y as f32 / 20.0,
+                    };
+                    *self.pending_mouse.entry(MouseAxis::Wheel).or_default() += lines;
+                }
+                WindowEvent::CursorMoved { position, .. } => {
+                    self.live_cursor = Some((position.x as f32, position.y as f32));
+                }
+                WindowEvent::CursorLeft { .. } => self.live_cursor = None,
+                // control characters (backspace, enter and the like) are left to the keys.
+                WindowEvent::ReceivedCharacter(character) if !character.is_control() => {
+                    self.pending_text.push(*character);
+                }
+                // the first finger down acts as the left mouse button, so anything that works
+                // with a mouse works with a touch screen.
+                WindowEvent::Touch(Touch {
+                    phase,
+                    location,
+                    id,
+                    ..
+                }) => {
+                    if self.touch.is_none() && *phase == TouchPhase::Started {
+                        self.touch = Some(*id);
+                    }
+                    if self.touch == Some(*id) {
+                        self.live_cursor = Some((location.x as f32, location.y as f32));
+                        let down = matches!(phase, TouchPhase::Started | TouchPhase::Moved);
+                        self.button(Button::Mouse(MouseButton::Left), down);
+                        if !down {
+                            self.touch = None;
+                        }
+                    }
+                }
+                // whatever was held won't get its release while the window isn't focused.
+                WindowEvent::Focused(false) => {
+                    for button in self.live.drain() {
+                        self.pending_released.insert(button);
+                    }
+                }
+                _ => {}
+            },
+            Event::DeviceEvent {
+                event: DeviceEvent::MouseMotion { delta },
+                ..
+            } => {
+                *self.pending_mouse.entry(MouseAxis::X).or_default() += delta.0 as f32;
+                *self.pending_mouse.entry(MouseAxis::Y).or_default() += delta.1 as f32;
+            }
+            _ => {}
+        }
+    }
+
+    // for things that don't come through winit, like gamepads.
+    pub fn button(&mut self, button: Button, down: bool) {
+        if down {
+            // key repeat sends more presses without any releases in between.
+            if self.live.insert(button) {
+                self.pending_pressed.insert(button);
+            }
+        } else if self.live.remove(&button) {
+            self.pending_released.insert(button);
+        }
+    }
+
+    pub fn gamepad_axis(&mut self, axis: GamepadAxis, value: f32) {
+        self.live_gamepad_axes.insert(axis, value);
+    }
+
+    // where the cursor (or finger) is, in physical pixels from the window's top left corner.
+    pub fn cursor(&self) -> Option<(f32, f32)> {
+        self.cursor
+    }
+
+    // whatever was typed over the tick.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    pub fn mouse(&self, axis: MouseAxis) -> f32 {
+        self.mouse.get(&axis).copied().unwrap_or(0.0)
+    }
+
+    // for the rest of the tick, keys read as neither down, pressed nor released, so whatever asks
+    // after this doesn't act on them too. the mouse, gamepads and text() are left alone.
+    pub fn take_keyboard(&mut self) {
+        self.keyboard_taken = true;
+    }
+
+    // whether something has had the keys this tick.
+    pub fn keyboard_handled(&self) -> bool {
+        self.keyboard_taken
+    }
+
+    fn unhandled(&self, button: Button) -> bool {
+        !(self.keyboard_taken && matches!(button, Button::Key(_)))
+    }
+
+    // whether the button went down this tick.
+    pub fn button_pressed(&self, button: Button) -> bool {
+        self.unhandled(button) && self.pressed.contains(&button)
+    }
+
+    pub fn button_held(&self, button: Button) -> bool {
+        self.unhandled(button) && self.down.contains(&button)
+    }
+
+    pub fn button_released(&self, button: Button) -> bool {
+        self.unhandled(button) && self.released.contains(&button)
+    }
+
+    // an action is pressed when any of its buttons is; unknown actions are never anything.
+    pub fn pressed(&self, action: &str) -> bool {
+        self.action_buttons(action)
+            .any(|button| self.button_pressed(*button))
+    }
+
+    pub fn held(&self, action: &str) -> bool {
+        self.action_buttons(action)
+            .any(|button| self.button_held(*button))
+    }
+
+    pub fn released(&self, action: &str) -> bool {
+        self.action_buttons(action)
+            .any(|button| self.button_released(*button))
+    }
+
+    // the sum of everything bound to the axis. buttons and gamepads are kept within -1.0 to 1.0
+    // together so a key and a stick can't add up to double speed; the mouse is added on top.
+    pub fn axis(&self, axis: &str) -> f32 {
+        let Some(bindings) = self.bindings.axes.get(axis) else {
+            return 0.0;
+        };
+        let mut bounded = 0.0;
+        let mut mouse = 0.0;
+        for binding in bindings {
+            match binding {
+                AxisBinding::Buttons { negative, positive } => {
+                    bounded += self.button_held(*positive) as i32 as f32
+                        - self.button_held(*negative) as i32 as f32;
+                }
+                AxisBinding::Gamepad(axis) => {
+                    bounded += self.gamepad_axes.get(axis).copied().unwrap_or(0.0);
+                }
+                AxisBinding::Mouse(axis) => mouse += self.mouse.get(axis).copied().unwrap_or(0.0),
+            }
+        }
+        bounded.clamp(-1.0, 1.0) + mouse
+    }
+
+    // the next button pressed gets bound to the action, replacing whatever it had.
+    pub fn listen(&mut self, action: &str) {
+        self.listening = Some(action.to_string());
+    }
+
+    pub fn listening(&self) -> Option<&str> {
+        self.listening.as_deref()
+    }
+
+    // the next button pressed after listen() replaces the action's bindings. with more than one
+    // pressed in the same tick, the first in snapshot order wins so a replay picks the same one.
+    fn listen_for_binding(&mut self) {
+        if let Some(action) = &self.listening {
+            if let Some(button) = sorted_buttons(&self.pressed).first() {
+                self.bindings.actions.insert(action.clone(), vec![*button]);
+                self.listening = None;
+            }
+        }
+    }
+
+    fn action_buttons(&self, action: &str) -> impl Iterator<Item = &Button> {
+        self.bindings.actions.get(action).into_iter().flatten()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn taking_the_keyboard() {
+        let bindings = Bindings::from_toml(
+            r#"
+            [actions]
+            pause = [{ key = "P" }, { gamepad = "Start" }]
+
+            [axes]
+            move_x = [{ buttons = { negative = { key = "A" }, positive = { key = "D" } } }, { mouse = "X" }]
+            "#,
+        )
+        .unwrap();
+        let mut input = Input::new(bindings);
+        input.button(Button::Key(VirtualKeyCode::P), true);
+        input.button(Button::Key(VirtualKeyCode::D), true);
+        input.button(Button::Gamepad(GamepadButton::Start), true);
+        input.tick();
+        assert!(input.pressed("pause") && input.axis("move_x") == 1.0);
+        assert!(!input.keyboard_handled());
+
+        // the keys go quiet, but the gamepad still gets through.
+        input.take_keyboard();
+        assert!(input.keyboard_handled());
+        assert!(!input.button_pressed(Button::Key(VirtualKeyCode::P)));
+        assert!(!input.button_held(Button::Key(VirtualKeyCode::D)));
+        assert!(input.pressed("pause") && input.axis("move_x") == 0.0);
+        // and the snapshot still has everything, so a replay sees the same tick.
+        let tick = input.snapshot();
+        assert!(tick.pressed.contains(&Button::Key(VirtualKeyCode::P)));
+
+        // only for the one tick.
+        input.button(Button::Key(VirtualKeyCode::D), false);
+        input.tick();
+        assert!(!input.keyboard_handled());
+        assert!(input.button_released(Button::Key(VirtualKeyCode::D)));
+        input.replay(&tick);
+        assert!(!input.keyboard_handled() && input.axis("move_x") == 1.0);
+    }
+}
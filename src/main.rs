@@ -1,14 +1,31 @@
+mod gamepad;
+mod input;
 mod renderer;
+mod replay;
+mod rng;
+mod widget;
+use renderer::atlas;
+use renderer::camera;
+use renderer::controller;
+use renderer::instance;
 use renderer::object;
 use renderer::resource;
+use renderer::sprite;
+use renderer::text;
 use renderer::texture;
+use renderer::tilemap;
 use renderer::ui;
-use std::{sync::Arc, time::Instant};
+use std::{
+    collections::VecDeque,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Instant,
+};
 
-use cgmath::num_traits::ToPrimitive;
 use game_loop::game_loop;
 use winit::{
-    event::{self, Event, WindowEvent},
+    dpi::{PhysicalPosition, PhysicalSize},
+    event::{Event, WindowEvent},
     event_loop::EventLoop,
     window::Window,
     window::WindowBuilder,
@@ -16,18 +33,160 @@ use winit::{
 
 const UPDATES_PER_SECOND: u32 = 96;
 const MAX_FRAME_TIME: f64 = 0.1;
+// layers for the demo's objects; see renderer::camera::Camera::layers.
+const BIG_JUNK: u32 = 1;
+const SMALL_JUNK: u32 = 2;
+
+// where each update's input comes from (and maybe goes to).
+enum Session {
+    Live,
+    Recording(replay::Recorder),
+    Replaying(replay::Replay),
+}
+
+impl Session {
+    // --record <file> or --replay <file>; anything else just plays. the window's made here too,
+    // since a recording goes by the window it's made in and a replay needs the same one back. it
+    // can't be resized while either's going.
+    fn from_args(event_loop: &EventLoop<()>) -> Result<(Self, u64, Window), String> {
+        let build = |builder: WindowBuilder| {
+            builder
+                .build(event_loop)
+                .map_err(|error| format!("couldn't make the window: {}", error))
+        };
+        let mut args = std::env::args().skip(1);
+        match (args.next().as_deref(), args.next()) {
+            (Some("--record"), Some(path)) => {
+                let window = build(WindowBuilder::new().with_resizable(false))?;
+                let seed = rng::Rng::seed_from_time();
+                let recorder = replay::Recorder::create(
+                    &PathBuf::from(path),
+                    seed,
+                    UPDATES_PER_SECOND,
+                    replay::Window {
+                        size: window.inner_size().into(),
+                        scale_factor: window.scale_factor(),
+                    },
+                )?;
+                Ok((Session::Recording(recorder), seed, window))
+            }
+            (Some("--replay"), Some(path)) => {
+                let replay = replay::Replay::open(&PathBuf::from(path), UPDATES_PER_SECOND)?;
+                let window = build(
+                    WindowBuilder::new()
+                        .with_resizable(false)
+                        .with_inner_size(PhysicalSize::<u32>::from(replay.window.size)),
+                )?;
+                let seed = replay.seed;
+                Ok((Session::Replaying(replay), seed, window))
+            }
+            (None, _) => Ok((
+                Session::Live,
+                rng::Rng::seed_from_time(),
+                build(WindowBuilder::new())?,
+            )),
+            _ => Err("usage: hekox [--record <file> | --replay <file>]".to_string()),
+        }
+    }
+
+    // whether what happens is really happening, rather than being played back. anything that
+    // reaches outside the game (rumble, say) should only happen then.
+    fn is_live(&self) -> bool {
+        !matches!(self, Session::Replaying(_))
+    }
+}
+
+// what the main camera's controller is; camera_mode goes round them in this order.
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum CameraMode {
+    Orbit,
+    Fly,
+    Follow,
+}
+
+impl CameraMode {
+    fn next(self) -> Self {
+        match self {
+            CameraMode::Orbit => CameraMode::Fly,
+            CameraMode::Fly => CameraMode::Follow,
+            CameraMode::Follow => CameraMode::Orbit,
+        }
+    }
+}
+
+// the demo's widgets, down the top left.
+#[derive(Default)]
+struct Panel {
+    pause: widget::WidgetId,
+    rebind: widget::WidgetId,
+    small_junk: widget::WidgetId, // whether the overhead view shows the littlest junk.
+    trail: widget::WidgetId,      // how long junk5's trail is, in fifths of a second.
+    name: widget::WidgetId,       // what junk5's called.
+    follow: widget::WidgetId,     // what the follow camera follows when nothing's picked.
+}
 
 struct Game {
     renderer_state: renderer::State,
+    input: input::Input,
+    gamepads: gamepad::Gamepads,
+    session: Session,
+    rng: rng::Rng,
+    widgets: widget::Widgets,
+    panel: Panel,
+    rebinding: bool, // until whatever's pressed next gets bound to pause.
+    paused: bool,
+    paused_card: usize, // into the ui elements; only shows while paused.
+    spin: sprite::Animation,
+    rock_speed: f32, // how fast the rocker goes back and forth.
+    // what was last right clicked on, and the ray that found it.
+    picked: Option<(renderer::Pick, camera::Ray)>,
+    camera_mode: CameraMode,
+    trail: VecDeque<cgmath::Point3<f32>>, // where junk5's been lately, newest last.
     do_bricks_have_an_inherent_colour: u64,
 }
 
 impl Game {
-    pub fn new(window: Arc<Window>) -> Self {
+    pub fn new(window: Arc<Window>, session: Session, seed: u64) -> Self {
+        use cgmath::prelude::*;
+
+        let mut renderer_state = pollster::block_on(renderer::State::new(window.clone()));
+        let font = text::Font::from_bytes(
+            "dejavu sans",
+            include_bytes!("./fonts/DejaVuSans.ttf").to_vec(),
+        )
+        .unwrap();
+        let font = renderer_state.fonts.add(font);
+        renderer_state.debug_draw.font = Some(font);
+        let theme =
+            widget::Theme::new(&renderer_state.device, &renderer_state.queue, font).unwrap();
         let mut tree_under_fire = Game {
-            renderer_state: pollster::block_on(renderer::State::new(window.clone())),
+            renderer_state,
+            input: input::Input::new(
+                input::Bindings::from_toml(include_str!("./input.toml")).unwrap(),
+            ),
+            gamepads: gamepad::Gamepads::new(),
+            session,
+            rng: rng::Rng::new(seed),
+            widgets: widget::Widgets::new(theme),
+            panel: Panel::default(),
+            rebinding: false,
+            paused: false,
+            paused_card: 0,
+            spin: sprite::Animation {
+                frames: vec![0, 1, 3, 2],
+                frame_time: 0.25,
+                looping: true,
+            },
+            rock_speed: 0.0,
+            picked: None,
+            camera_mode: CameraMode::Orbit,
+            trail: VecDeque::new(),
             do_bricks_have_an_inherent_colour: 0,
         };
+        // start somewhere different each run; a replay starts where its recording did.
+        let start = tree_under_fire.rng.below(960).unwrap();
+        tree_under_fire.do_bricks_have_an_inherent_colour = start;
+        tree_under_fire.rock_speed = tree_under_fire.rng.range(2.0, 4.0);
 
         let device = &tree_under_fire.renderer_state.device;
         let queue = &tree_under_fire.renderer_state.queue;
@@ -37,90 +196,467 @@ impl Game {
         let model_bytes = include_bytes!("./models/junk.glb");
         let model =
             resource::load_model_bytes("junk", model_bytes, device, queue, &tex_layout).unwrap();
-        objects.push(object::Object::new(
-            "junk".to_string(),
-            device,
-            model,
-            None,
-            None,
-            Some((1.5, 1.5, 1.5)),
-            None,
-        ));
+        objects.push(
+            object::Object::builder("junk", model)
+                .scale((1.5, 1.5, 1.5))
+                .build(device)
+                .unwrap(),
+        );
         let model_bytes = include_bytes!("./models/junk.glb");
         let model =
             resource::load_model_bytes("junk", model_bytes, device, queue, &tex_layout).unwrap();
-        objects.push(object::Object::new(
-            "junk2".to_string(),
-            device,
-            model,
-            None,
-            None,
-            Some((0.5, 0.5, 0.5)),
-            None,
-        ));
+        objects.push(
+            object::Object::builder("junk2", model)
+                .scale((0.5, 0.5, 0.5))
+                .build(device)
+                .unwrap(),
+        );
         let model_bytes = include_bytes!("./models/junk.glb");
         let model =
             resource::load_model_bytes("junk", model_bytes, device, queue, &tex_layout).unwrap();
-        objects.push(object::Object::new(
-            "junk3".to_string(),
-            device,
-            model,
-            None,
-            None,
-            Some((0.75, 0.75, 0.75)),
-            None,
-        ));
+        objects.push(
+            object::Object::builder("junk3", model)
+                .scale((0.75, 0.75, 0.75))
+                .build(device)
+                .unwrap(),
+        );
         let model_bytes = include_bytes!("./models/junk.glb");
         let model =
             resource::load_model_bytes("junk", model_bytes, device, queue, &tex_layout).unwrap();
-        objects.push(object::Object::new(
-            "junk4".to_string(),
-            device,
-            model,
-            None,
-            None,
-            Some((0.25, 0.25, 0.25)),
-            None,
-        ));
+        objects.push(
+            object::Object::builder("junk4", model)
+                .scale((0.25, 0.25, 0.25))
+                .layers(SMALL_JUNK)
+                .build(device)
+                .unwrap(),
+        );
         let model_bytes = include_bytes!("./models/junk.glb");
         let model =
             resource::load_model_bytes("junk", model_bytes, device, queue, &tex_layout).unwrap();
-        objects.push(object::Object::new(
-            "junk5".to_string(),
+        objects.push(
+            object::Object::builder("junk5", model)
+                .scale((0.4, 0.4, 0.4))
+                .build(device)
+                .unwrap(),
+        );
+
+        // a ring of it round the floor: loaded as a scene this time, so the face stays where the
+        // file puts it, with the one object drawn over and over as instances.
+        let ring = || {
+            (0..8)
+                .map(|i| {
+                    let angle = cgmath::Deg(i as f32 * 45.0);
+                    let (sin, cos) = cgmath::Rad::from(angle).0.sin_cos();
+                    instance::Instance {
+                        position: (sin * 6.0, -2.5, cos * 6.0).into(),
+                        rotation: cgmath::Quaternion::from_angle_y(angle),
+                    }
+                })
+                .collect()
+        };
+        let model_bytes = include_bytes!("./models/junk.glb");
+        let options = resource::LoadOptions::default();
+        let scene =
+            resource::load_scene_bytes("ring", model_bytes, device, queue, &tex_layout, &options)
+                .unwrap();
+        for builder in scene {
+            objects.push(builder.instances(ring()).build(device).unwrap());
+        }
+
+        // and a big one way off in the distance, which fades over to a simplified version of
+        // itself while it's small on screen.
+        let mut lods = resource::load_model_lods_bytes(
+            "far junk",
+            model_bytes,
             device,
-            model,
-            None,
-            None,
-            Some((0.4, 0.4, 0.4)),
-            None,
-        ));
+            queue,
+            &tex_layout,
+            &options,
+            &[1.0, 0.1],
+        )
+        .unwrap();
+        let simplified = lods.pop().unwrap();
+        objects.push(
+            object::Object::builder("far junk", lods.pop().unwrap())
+                .position((0.0, 4.0, -40.0))
+                .rotation(cgmath::Quaternion::from_angle_y(cgmath::Deg(200.0)))
+                .scale((6.0, 6.0, 6.0))
+                .lod(simplified, 0.15)
+                .lod_fade_time(0.5)
+                .build(device)
+                .unwrap(),
+        );
         tree_under_fire.renderer_state.objects = objects;
 
-        let mut ui_elements: Vec<ui::Element> = Vec::new();
-        let test_image = include_bytes!("./textures/test.png");
-        let test_texture = texture::Texture::from_image_bytes(
-            Some(&"test texture".to_string()),
-            &device,
-            &queue,
-            test_image,
+        // a picture in picture in the bottom right corner, looking down on everything from above.
+        // there's no far plane to worry about fitting it all in, and it leaves out the littlest
+        // bit of junk, which is on a layer of its own, unless the panel says otherwise.
+        let mut overhead = camera::Camera::builder("overhead")
+            .eye((0.0, 9.0, 0.0))
+            .up((0.0, 0.0, -1.0))
+            .projection(camera::Projection::InfinitePerspective(45.0))
+            .layers(BIG_JUNK)
+            .viewport(camera::Viewport {
+                x: 0.74,
+                y: 0.7,
+                width: 0.24,
+                height: 0.27,
+            })
+            .order(1)
+            .clear_colour(wgpu::Color {
+                r: 0.05,
+                g: 0.05,
+                b: 0.08,
+                a: 1.0,
+            })
+            .build(device)
+            .unwrap();
+        let size = tree_under_fire.renderer_state.size();
+        overhead.fit(size.width, size.height);
+        tree_under_fire.renderer_state.cameras.push(overhead);
+
+        // and one looking back the other way, drawn into a texture that's shown along the top.
+        let rear_view = camera::Camera::builder("rear view")
+            .eye((0.0, 1.0, -6.0))
+            .target((0.0, 0.0, 0.0))
+            .render_target((320, 180), tree_under_fire.renderer_state.surface_format())
+            .build(device)
+            .unwrap();
+        let rear_view_texture = rear_view.render_target.as_ref().unwrap().texture.clone();
+        tree_under_fire.renderer_state.cameras.push(rear_view);
+
+        // the ui's and the sprites' images all go in the one atlas, so they batch together.
+        let atlas = atlas::Atlas::builder("demo")
+            .page_size(256)
+            .padding(1)
+            .image_bytes("test", include_bytes!("./textures/test.png"))
+            .unwrap()
+            .image(
+                "test flipped",
+                &image::load_from_memory(include_bytes!("./textures/test.png"))
+                    .unwrap()
+                    .fliph(),
+            )
+            .build(device, queue)
+            .unwrap();
+        let test = atlas.region("test").unwrap();
+
+        tree_under_fire.renderer_state.ui_elements.push(
+            ui::Element::from_region("test", test)
+                .anchor(ui::Anchor::BOTTOM_LEFT)
+                .pivot(ui::Anchor::CENTRE)
+                .build(device)
+                .unwrap(),
+        );
+
+        // the rear view sits in a frame cut out of the same picture, which stretches without its
+        // corners going out of shape. anything poking out of it gets cut off.
+        tree_under_fire.renderer_state.ui_elements.push(
+            ui::Element::from_region("rear view frame", test)
+                .anchor(ui::Anchor::TOP)
+                .pivot(ui::Anchor::TOP)
+                .position((0.0, 8.0))
+                .size(ui::Size::pixels(256.0, 151.0))
+                .slice(ui::Slice {
+                    left: 8.0,
+                    right: 8.0,
+                    top: 8.0,
+                    bottom: 8.0,
+                })
+                .clip(true)
+                .z(-1)
+                .build(device)
+                .unwrap(),
+        );
+        tree_under_fire.renderer_state.ui_elements.push(
+            ui::Element::builder("rear view", rear_view_texture)
+                .anchor(ui::Anchor::CENTRE)
+                .pivot(ui::Anchor::CENTRE)
+                .size(ui::Size::pixels(240.0, 135.0))
+                .parent(1)
+                .build(device)
+                .unwrap(),
+        );
+
+        // tipped over in the middle while paused; scaled down to nothing the rest of the time,
+        // which also keeps it from getting in the way of clicks.
+        tree_under_fire.paused_card = tree_under_fire.renderer_state.ui_elements.len();
+        tree_under_fire.renderer_state.ui_elements.push(
+            ui::Element::from_region("paused card", test)
+                .anchor(ui::Anchor::CENTRE)
+                .pivot(ui::Anchor::CENTRE)
+                .scale(0.0)
+                .rotation(cgmath::Deg(-8.0))
+                .tint([1.0, 0.85, 0.85, 1.0])
+                .opacity(0.9)
+                .build(device)
+                .unwrap(),
+        );
+
+        // the same picture cut into quarters, going round and round down the bottom of the 2d
+        // world.
+        let quarters = sprite::Sheet::grid_in(device, "quarters", test, (32, 32), 0, 0).unwrap();
+        let mut spinner = sprite::Sprite::builder("spinner", quarters)
+            .position((0.0, 200.0))
+            .size(64.0, 64.0)
+            .build()
+            .unwrap();
+        spinner.play(&tree_under_fire.spin);
+        tree_under_fire.renderer_state.sprites.push(spinner);
+
+        // and the whole of it next to that, rocking back and forth on its bottom edge, along with
+        // a mirrored copy to show every other second.
+        let both_ways = sprite::Sheet::from_regions(
+            device,
+            "both ways",
+            &[test, atlas.region("test flipped").unwrap()],
         )
         .unwrap();
-        ui_elements.push(ui::Element::new(
-            "test".to_string(),
-            &device,
-            test_texture,
-            None,
-            None,
-        ));
-        tree_under_fire.renderer_state.ui_elements = ui_elements;
+        tree_under_fire.renderer_state.sprites.push(
+            sprite::Sprite::builder("rocker", both_ways)
+                .frame(1)
+                .position((96.0, 232.0))
+                .pivot(ui::Anchor::BOTTOM)
+                .rotation(cgmath::Deg(-10.0))
+                .tint([0.85, 0.85, 1.0, 1.0])
+                .z(1)
+                .build()
+                .unwrap(),
+        );
+
+        // for them to stand on. the map's layers go at z -2 and -1, under both of them.
+        let mut map = tilemap::Tilemap::load_bytes(
+            "demo map",
+            Path::new("maps/demo.tmx"),
+            include_bytes!("./maps/demo.tmx"),
+            device,
+            queue,
+            |path| match path.file_name().and_then(|name| name.to_str()) {
+                Some("test.png") => Ok(include_bytes!("./textures/test.png").to_vec()),
+                _ => Err(format!("{} isn't in the demo", path.display())),
+            },
+        )
+        .unwrap();
+        // eight tiles across and three down, with the ground along the bottom row.
+        let (tile_width, tile_height) = map.tile_size();
+        map.position = (-4.0 * tile_width as f32, 232.0 - 2.0 * tile_height as f32).into();
+        map.z = -2;
+        map.snap();
+        tree_under_fire.renderer_state.tilemaps.push(map);
+
+        tree_under_fire.renderer_state.texts.push(
+            text::Text::builder("status", font)
+                .anchor(ui::Anchor::TOP_RIGHT)
+                .pivot(ui::Anchor::TOP_RIGHT)
+                .position((-12.0, 8.0))
+                .size(20.0)
+                .build()
+                .unwrap(),
+        );
+        tree_under_fire.renderer_state.texts.push(
+            text::Text::builder("hint", font)
+                .text(
+                    "right click on something to pick it out. c switches the camera between \
+                     going round, flying and following whatever's picked. middle drag and - \
+                     and = move round the 2d bits.",
+                )
+                .anchor(ui::Anchor::BOTTOM)
+                .pivot(ui::Anchor::BOTTOM)
+                .position((0.0, -8.0))
+                .align(text::Align::Centre)
+                .wrap(480.0)
+                .z(1)
+                .build()
+                .unwrap(),
+        );
+        tree_under_fire.renderer_state.texts.push(
+            text::Text::builder("picked", font)
+                .anchor(ui::Anchor::BOTTOM_RIGHT)
+                .pivot(ui::Anchor::BOTTOM_RIGHT)
+                .position((-12.0, -8.0))
+                .align(text::Align::Right)
+                .build()
+                .unwrap(),
+        );
+        tree_under_fire.add_panel().unwrap();
+
+        let orbit = controller::Orbit::new(&tree_under_fire.renderer_state.cameras[0]);
+        tree_under_fire
+            .renderer_state
+            .set_camera_controller(0, Some(Box::new(orbit)));
 
         tree_under_fire
     }
 
-    pub fn update(&mut self) {
+    // a column of one of each widget down the top left, to poke at.
+    fn add_panel(&mut self) -> Result<(), String> {
+        let state = &mut self.renderer_state;
+        let widgets = &mut self.widgets;
+        let row = |row: f32| widget::Placement {
+            position: (12.0, 12.0 + row * 40.0).into(),
+            ..Default::default()
+        };
+        let panel = &mut self.panel;
+        panel.pause = widgets.button(state, row(0.0), "pause")?;
+        panel.rebind = widgets.button(
+            state,
+            widget::Placement {
+                position: (180.0, 12.0).into(),
+                ..Default::default()
+            },
+            "rebind pause",
+        )?;
+        panel.small_junk = widgets.toggle(
+            state,
+            widget::Placement {
+                size: ui::Size::pixels(24.0, 24.0),
+                ..row(1.0)
+            },
+            "small junk from above",
+            false,
+        )?;
+        panel.trail = widgets.slider(
+            state,
+            widget::Placement {
+                size: ui::Size::pixels(160.0, 16.0),
+                ..row(2.0)
+            },
+            (0.0, 10.0),
+            5.0,
+            1.0,
+        )?;
+        panel.name = widgets.text_field(state, row(3.0), "junk5")?;
+        let items = ["junk", "junk2", "junk3", "junk4", "junk5"];
+        panel.follow = widgets.list(
+            state,
+            row(4.0),
+            items.iter().map(|item| item.to_string()).collect(),
+            3,
+        )?;
+        Ok(())
+    }
+
+    // everything in here has to depend only on the input, the rng and the window's size, or
+    // replays won't match. returns false once a replay has run out (or stopped matching).
+    pub fn update(&mut self) -> bool {
         use cgmath::prelude::*;
 
-        self.do_bricks_have_an_inherent_colour += 1;
+        self.renderer_state.start_tick();
+        self.gamepads.poll(&mut self.input);
+        let window = replay::Window {
+            size: self.renderer_state.size().into(),
+            scale_factor: self.renderer_state.scale_factor(),
+        };
+        match &mut self.session {
+            Session::Live => self.input.tick(),
+            Session::Recording(recorder) => {
+                self.input.tick();
+                if let Err(error) = recorder.record(&self.input.snapshot(), window) {
+                    // what's there so far still plays back; the game just carries on unrecorded.
+                    log::error!("{}", error);
+                    self.finish();
+                    self.session = Session::Live;
+                } else if recorder.ticks() % UPDATES_PER_SECOND as u64 == 0 {
+                    // every second or so, so a crash doesn't lose the lot.
+                    if let Err(error) = recorder.flush() {
+                        log::error!("{}", error);
+                    }
+                }
+            }
+            Session::Replaying(replay) => match replay.next_tick(window) {
+                Ok(Some(tick)) => self.input.replay(&tick),
+                Ok(None) => {
+                    log::info!("replay finished after {} ticks", replay.played());
+                    return false;
+                }
+                Err(error) => {
+                    log::error!("{}", error);
+                    return false;
+                }
+            },
+        }
+        let events = self
+            .widgets
+            .update(&mut self.input, &mut self.renderer_state);
+        let mut pause = self.input.pressed("pause");
+        for event in events {
+            match event {
+                widget::Event::Clicked(widget) if widget == self.panel.pause => pause = true,
+                widget::Event::Clicked(widget) if widget == self.panel.rebind => {
+                    self.input.listen("pause");
+                    self.rebinding = true;
+                }
+                event => log::info!("{:?}", event),
+            }
+        }
+        // the text field gets the keyboard on /, like a chat box. the / itself has already gone
+        // by, so it doesn't end up in there.
+        if self.input.pressed("ui_type") && self.widgets.focus() != Some(self.panel.name) {
+            self.widgets.set_focus(Some(self.panel.name));
+        }
+        let small_junk = match self.widgets.is_on(self.panel.small_junk) {
+            Some(true) => SMALL_JUNK,
+            _ => 0,
+        };
+        self.renderer_state.cameras[1].layers = BIG_JUNK | small_junk;
+        // right clicking picks out whatever's under the cursor, unless that's the ui.
+        if let Some((x, y)) = self.input.cursor().filter(|_| self.input.pressed("pick")) {
+            let scale_factor = self.renderer_state.scale_factor() as f32;
+            let over_ui = self
+                .renderer_state
+                .ui_layout()
+                .element_at((x / scale_factor, y / scale_factor))
+                .is_some();
+            if !over_ui {
+                let size = self.renderer_state.size();
+                self.picked = self
+                    .renderer_state
+                    .pick(PhysicalPosition::new(x as f64, y as f64))
+                    .and_then(|pick| {
+                        let camera = &self.renderer_state.cameras[pick.camera];
+                        Some((pick, camera.ray((x, y), (size.width, size.height))?))
+                    });
+            }
+        }
+        if self.input.pressed("camera_mode") {
+            self.camera_mode = self.camera_mode.next();
+            let camera = &self.renderer_state.cameras[0];
+            let controller: Box<dyn controller::CameraController> = match self.camera_mode {
+                CameraMode::Orbit => Box::new(controller::Orbit::new(camera)),
+                CameraMode::Fly => Box::new(controller::Fly::new(camera)),
+                // whatever's chosen in the list if nothing's picked, or junk5, since it gets about
+                // the most.
+                CameraMode::Follow => Box::new(controller::Follow::new(self.picked.map_or(
+                    self.widgets.selected(self.panel.follow).unwrap_or(4),
+                    |(pick, _)| pick.object,
+                ))),
+            };
+            self.renderer_state
+                .set_camera_controller(0, Some(controller));
+        }
+        #[cfg(feature = "debug-ui")]
+        if self.input.pressed("debug_ui") {
+            self.renderer_state.toggle_debug_ui();
+        }
+        if pause {
+            self.paused = !self.paused;
+            // a little bump for whichever controller did the pausing, so it can be felt.
+            if self.session.is_live() {
+                let pads: Vec<gamepad::GamepadId> = self
+                    .gamepads
+                    .iter()
+                    .filter(|(_, pad)| pad.can_rumble() && pad.held(input::GamepadButton::Start))
+                    .map(|(id, _)| id)
+                    .collect();
+                for id in pads {
+                    self.gamepads
+                        .rumble(id, 0.0, 0.5, std::time::Duration::from_millis(100));
+                }
+            }
+        }
+        if !self.paused {
+            self.do_bricks_have_an_inherent_colour += 1;
+        }
         let tweaked_bricks = self.do_bricks_have_an_inherent_colour as f32 / 90.0; //* ((self.do_bricks_have_an_inherent_colour as f32 / 180.0).sin() / 3.0 + 2.0);
 
         self.renderer_state.objects[0].rotation =
@@ -156,28 +692,186 @@ impl Game {
             cgmath::Quaternion::from_angle_z(cgmath::Deg(tweaked_bricks * 750.0))
                 * cgmath::Quaternion::from_angle_y(cgmath::Deg(tweaked_bricks * 200.0));
 
-        self.renderer_state.ui_elements[0].position.x = (tweaked_bricks * 2.0).sin() * 0.5 - 0.75;
-        self.renderer_state.ui_elements[0].position.y = (tweaked_bricks * 1.5).cos() * 0.5 + 1.0;
-        self.renderer_state.ui_elements[0].scale.0 = ((tweaked_bricks).sin() * 0.5 + 1.0) * 0.5;
-        self.renderer_state.ui_elements[0].scale.1 =
-            ((tweaked_bricks * 1.25).cos() * 0.5 + 1.0) * 0.5;
+        // what the bindings have become gets logged, so it can be pasted into input.toml.
+        if self.rebinding && self.input.listening().is_none() {
+            self.rebinding = false;
+            match self.input.bindings.to_toml() {
+                Ok(bindings) => log::info!("pause is rebound:\n{}", bindings),
+                Err(error) => log::error!("{}", error),
+            }
+        }
+        self.renderer_state.texts[0].set_text(match (self.input.listening(), self.paused) {
+            (Some(action), _) => format!("press something for {}", action),
+            // the keys are all going in the text field, so p and c won't do anything.
+            (None, _) if self.input.keyboard_handled() => "typing (escape to stop)".to_string(),
+            (None, true) => "paused".to_string(),
+            (None, false) => format!("tick {}", self.do_bricks_have_an_inherent_colour),
+        });
+        self.renderer_state.texts[2].set_text(match &self.picked {
+            Some((pick, _)) => format!(
+                "{}\n{:.1} away",
+                self.renderer_state.objects[pick.object].label, pick.distance
+            ),
+            None => String::new(),
+        });
+
+        self.renderer_state.ui_elements[0].position.x =
+            (tweaked_bricks * 2.0).sin() * 100.0 + 200.0;
+        self.renderer_state.ui_elements[0].position.y =
+            (tweaked_bricks * 1.5).cos() * 100.0 - 200.0;
+        self.renderer_state.ui_elements[0].size = ui::Size::pixels(
+            ((tweaked_bricks).sin() * 0.5 + 1.0) * 160.0,
+            ((tweaked_bricks * 1.25).cos() * 0.5 + 1.0) * 160.0,
+        );
+        self.renderer_state.ui_elements[0].rotation = cgmath::Deg(tweaked_bricks * 20.0);
+        self.renderer_state.ui_elements[0].tint = [
+            1.0,
+            (tweaked_bricks * 0.5).sin() * 0.25 + 0.75,
+            (tweaked_bricks * 0.5).cos() * 0.25 + 0.75,
+            1.0,
+        ];
+        self.renderer_state.ui_elements[self.paused_card].scale = match self.paused {
+            true => 3.0,
+            false => 0.0,
+        };
+
+        // the 2d world stops along with everything else.
+        let spinner = &mut self.renderer_state.sprites[0];
+        if self.paused {
+            spinner.stop();
+        } else if !spinner.is_playing() {
+            spinner.play(&self.spin);
+        }
+        let rocker = &mut self.renderer_state.sprites[1];
+        rocker.rotation = cgmath::Deg((tweaked_bricks * self.rock_speed).sin() * 10.0);
+        rocker.frame =
+            (self.do_bricks_have_an_inherent_colour / UPDATES_PER_SECOND as u64 % 2) as usize;
+        for layer in &mut self.renderer_state.tilemaps[0].layers {
+            if layer.name == "decoration" {
+                layer.visible = !self.paused;
+            }
+        }
+
+        // middle dragging moves round the 2d world, and - and = zoom in on wherever the cursor
+        // is (or the middle, if it's not on the window).
+        let scale_factor = self.renderer_state.scale_factor() as f32;
+        let size = self.renderer_state.size();
+        let window = (
+            size.width as f32 / scale_factor,
+            size.height as f32 / scale_factor,
+        );
+        let camera_2d = &mut self.renderer_state.camera_2d;
+        if self.input.held("pan_2d") {
+            camera_2d.pan((
+                self.input.mouse(input::MouseAxis::X) / scale_factor,
+                self.input.mouse(input::MouseAxis::Y) / scale_factor,
+            ));
+        }
+        let zoom = self.input.axis("zoom_2d");
+        if zoom != 0.0 {
+            let cursor = self
+                .input
+                .cursor()
+                .map_or((window.0 / 2.0, window.1 / 2.0), |(x, y)| {
+                    (x / scale_factor, y / scale_factor)
+                });
+            camera_2d.zoom_about(1.0 + zoom * 0.02, cursor, window);
+        }
+
+        // the hint keeps a little way in from the sides of narrow windows.
+        self.renderer_state.texts[1].set_wrap(Some((window.0 - 24.0).min(480.0)));
+
+        // the floor, the space the junk keeps to, and where the one flying round the furthest is
+        // (and has been).
+        let debug = &mut self.renderer_state.debug_draw;
+        debug.depth_test = true;
+        debug.grid((0.0, -3.0, 0.0), 1.0, 12, [0.5, 0.5, 0.5, 1.0]);
+        debug.aabb((-4.0, -3.0, -4.0), (4.0, 3.0, 4.0), [0.5, 0.5, 0.5, 0.5]);
+        debug.depth_test = false;
+        let wanderer = self.renderer_state.objects[4].position;
+        self.trail.push_back(wanderer);
+        let trail = self.widgets.value(self.panel.trail).unwrap_or(5.0) / 5.0;
+        while self.trail.len() > (trail * UPDATES_PER_SECOND as f32) as usize {
+            self.trail.pop_front();
+        }
+        debug.path(self.trail.iter().copied(), [1.0, 0.8, 0.2, 0.5]);
+        debug.arrow(
+            (wanderer.x, -3.0, wanderer.z).into(),
+            wanderer,
+            [1.0, 0.8, 0.2, 1.0],
+        );
+        debug.text(
+            wanderer + cgmath::vec3(0.0, 0.75, 0.0),
+            self.widgets
+                .text_of(self.panel.name)
+                .filter(|name| !name.is_empty())
+                .unwrap_or("junk5"),
+            [1.0, 0.8, 0.2, 1.0],
+        );
+        // what's picked, which way it's facing, and where it was hit from.
+        if let Some((pick, ray)) = &self.picked {
+            let object = &self.renderer_state.objects[pick.object];
+            let transform = object.matrix() * object.instances[pick.instance].matrix();
+            let bounds = &object.model().bounds;
+            let colour = [0.3, 1.0, 0.5, 1.0];
+            debug.cuboid(bounds.min, bounds.max, transform, colour);
+            debug.axes(transform, 1.0);
+            debug.ray(ray, pick.distance, colour);
+            debug.sphere(pick.point, 0.05, colour);
+        }
+
+        // after the widgets, which get first go at the pointer.
+        self.renderer_state
+            .update_cameras(&self.input, 1.0 / UPDATES_PER_SECOND as f32);
+        true
+    }
+
+    // for anything that has to happen before the event loop exits the process.
+    pub fn finish(&mut self) {
+        if let Session::Recording(recorder) = &mut self.session {
+            match recorder.flush() {
+                Ok(()) => log::info!("recorded {} ticks", recorder.ticks()),
+                Err(error) => log::error!("{}", error),
+            }
+        }
     }
 
     pub fn handle_event(&mut self, event: &Event<()>) -> bool {
+        // the debug overlay goes first, so typing into it doesn't also move things.
+        #[cfg(feature = "debug-ui")]
+        if let Event::WindowEvent { event, window_id } = event {
+            if *window_id == self.renderer_state.window().id()
+                && self.renderer_state.debug_ui_input(event)
+            {
+                return true;
+            }
+        }
+        self.input.handle_event(event);
         match event {
             Event::WindowEvent {
                 ref event,
                 window_id,
             } if *window_id == self.renderer_state.window().id() => {
+                if self.renderer_state.input(event) {
+                    return true;
+                }
                 match event {
-                    WindowEvent::CloseRequested => return false,
+                    WindowEvent::CloseRequested => {
+                        self.finish();
+                        return false;
+                    }
                     WindowEvent::Resized(physical_size) => {
                         self.renderer_state.resize(*physical_size);
                     }
                     // つづ: re-evaluate whether new_inner_size (the second variable) or the 'field
                     // ignorer' should go last.
                     // okay turns out you have to put the 'field ignorer' last.
-                    WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
+                    WindowEvent::ScaleFactorChanged {
+                        scale_factor,
+                        new_inner_size,
+                        ..
+                    } => {
+                        self.renderer_state.set_scale_factor(*scale_factor);
                         self.renderer_state.resize(**new_inner_size);
                     }
                     _ => {}
@@ -187,9 +881,10 @@ impl Game {
         }
         true
     }
-    pub fn render(&mut self, instant: &Instant) -> Result<(), wgpu::SurfaceError> {
+    // `blend` is game_loop's blending factor: how far the frame is on its way to the next tick.
+    pub fn render(&mut self, instant: &Instant, blend: f32) -> Result<(), wgpu::SurfaceError> {
         let elapsed = instant.elapsed().as_secs_f32();
-        self.renderer_state.update(elapsed);
+        self.renderer_state.update(elapsed, blend);
         self.renderer_state.render()
     }
 }
@@ -197,11 +892,17 @@ impl Game {
 fn main() {
     env_logger::init();
     let event_loop = EventLoop::new();
-    let window = WindowBuilder::new().build(&event_loop).unwrap();
+    let (session, seed, window) = match Session::from_args(&event_loop) {
+        Ok(session) => session,
+        Err(error) => {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        }
+    };
     let window = Arc::new(window);
     let boot = Instant::now();
 
-    let game = Game::new(window.clone());
+    let game = Game::new(window.clone(), session, seed);
 
     game_loop(
         event_loop,
@@ -210,18 +911,24 @@ fn main() {
         UPDATES_PER_SECOND,
         MAX_FRAME_TIME,
         |g| {
-            g.game.update();
+            if !g.game.update() {
+                g.exit();
+            }
         },
         move |g| {
-            match g.game.render(&boot) {
+            let blend = g.blending_factor() as f32;
+            match g.game.render(&boot, blend) {
                 Ok(_) => {}
                 //Err(wgpu::SurfaceError::Lost) => g.game.renderer_state.resize(g.game.renderer_state.get_size()), // reconfigure if lost
-                Err(wgpu::SurfaceError::OutOfMemory) => g.exit(),
+                Err(wgpu::SurfaceError::OutOfMemory) => {
+                    g.game.finish();
+                    g.exit();
+                }
                 Err(e) => eprintln!("{:?}", e),
             }
         },
         |g, event| {
-            if g.game.handle_event(event) == false {
+            if !g.game.handle_event(event) {
                 g.exit()
             };
         },
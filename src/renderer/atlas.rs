@@ -0,0 +1,353 @@
+use std::collections::HashMap;
+
+use super::{texture, ui};
+
+// lots of little images packed into a few big textures when they're loaded, so the ui and sprites
+// that use them can be batched together (see batch::Batch). models keep their own textures, since
+// gltf uvs can repeat past the edges, and text has its own atlas that fills up as it goes.
+//
+// each image gets `padding` pixels around it, filled in by stretching its edges outwards, so
+// anything that samples a little past the edge (filtering, scaled or turned elements) gets more
+// of the same image rather than its neighbour.
+pub struct Atlas {
+    pub label: String,
+    regions: HashMap<String, Region>,
+}
+
+// where an image ended up. the texture is the whole page; clone it into an element with the rect
+// as its region, or cut a sprite sheet out of it.
+#[derive(Clone, Debug)]
+pub struct Region {
+    pub texture: texture::Texture,
+    pub page: usize,
+    pub rect: ui::Rect, // in texels, without the padding.
+}
+
+pub struct AtlasBuilder {
+    label: String,
+    page_size: u32,
+    padding: u32,
+    images: Vec<(String, image::RgbaImage)>,
+}
+
+impl AtlasBuilder {
+    // how big each page is, across and down. more images than fit on one get more pages.
+    pub fn page_size(mut self, page_size: u32) -> Self {
+        self.page_size = page_size;
+        self
+    }
+
+    pub fn padding(mut self, padding: u32) -> Self {
+        self.padding = padding;
+        self
+    }
+
+    pub fn image<N: Into<String>>(mut self, name: N, image: &image::DynamicImage) -> Self {
+        self.images.push((name.into(), image.to_rgba8()));
+        self
+    }
+
+    pub fn image_bytes<N: Into<String>>(self, name: N, bytes: &[u8]) -> Result<Self, String> {
+        let name = name.into();
+        let image = image::load_from_memory(bytes).map_err(|e| format!("{}: {}", name, e))?;
+        Ok(self.image(name, &image))
+    }
+
+    pub fn build(self, device: &wgpu::Device, queue: &wgpu::Queue) -> Result<Atlas, String> {
+        let limit = device.limits().max_texture_dimension_2d;
+        if self.page_size == 0 || self.page_size > limit {
+            return Err(format!(
+                "{}: pages have to be between 1 and {} pixels across",
+                self.label, limit
+            ));
+        }
+        let Packed {
+            label,
+            pages,
+            regions,
+        } = self.pack()?;
+        let pages = pages
+            .iter()
+            .enumerate()
+            .map(|(i, pixels)| {
+                texture::Texture::from_raw_data(
+                    Some(&format!("{} page {}", label, i)),
+                    device,
+                    queue,
+                    pixels,
+                    pixels.dimensions(),
+                )
+                .map_err(|e| format!("{}: {}", label, e))
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        let regions = regions
+            .into_iter()
+            .map(|(name, page, rect)| {
+                let region = Region {
+                    texture: pages[page].clone(),
+                    page,
+                    rect,
+                };
+                (name, region)
+            })
+            .collect();
+        Ok(Atlas { label, regions })
+    }
+
+    // everything build() does short of the gpu: works out where each image goes and draws the
+    // pages.
+    fn pack(self) -> Result<Packed, String> {
+        let AtlasBuilder {
+            label,
+            page_size,
+            padding,
+            mut images,
+        } = self;
+        let mut names = std::collections::HashSet::new();
+        if let Some((name, _)) = images.iter().find(|(name, _)| !names.insert(name)) {
+            return Err(format!("{}: there are two images called {}", label, name));
+        }
+
+        // tallest first packs tighter.
+        images.sort_by_key(|(_, image)| std::cmp::Reverse((image.height(), image.width())));
+        let mut allocators: Vec<etagere::AtlasAllocator> = Vec::new();
+        let mut placed = Vec::with_capacity(images.len());
+        for (name, image) in &images {
+            if image.width() == 0 || image.height() == 0 {
+                return Err(format!("{}: {} is empty", label, name));
+            }
+            let (width, height) = (image.width() + padding * 2, image.height() + padding * 2);
+            if width > page_size || height > page_size {
+                return Err(format!(
+                    "{}: {} ({}x{} with padding) doesn't fit on a {}x{} page",
+                    label, name, width, height, page_size, page_size
+                ));
+            }
+            let size = etagere::size2(width as i32, height as i32);
+            let found = allocators
+                .iter_mut()
+                .enumerate()
+                .find_map(|(page, allocator)| Some((page, allocator.allocate(size)?)));
+            let (page, allocation) = match found {
+                Some(found) => found,
+                None => {
+                    let side = page_size as i32;
+                    let mut allocator = etagere::AtlasAllocator::new(etagere::size2(side, side));
+                    let allocation = allocator
+                        .allocate(size)
+                        .ok_or_else(|| format!("{}: couldn't place {}", label, name))?;
+                    allocators.push(allocator);
+                    (allocators.len() - 1, allocation)
+                }
+            };
+            let corner = allocation.rectangle.min;
+            placed.push((page, corner.x as u32 + padding, corner.y as u32 + padding));
+        }
+
+        let mut pages = vec![image::RgbaImage::new(page_size, page_size); allocators.len()];
+        for ((_, image), &(page, x, y)) in images.iter().zip(&placed) {
+            bleed(&mut pages[page], image, (x, y), padding);
+        }
+        let regions = images
+            .into_iter()
+            .zip(placed)
+            .map(|((name, image), (page, x, y))| {
+                let rect = ui::Rect {
+                    x: x as f32,
+                    y: y as f32,
+                    width: image.width() as f32,
+                    height: image.height() as f32,
+                };
+                (name, page, rect)
+            })
+            .collect();
+        Ok(Packed {
+            label,
+            pages,
+            regions,
+        })
+    }
+}
+
+struct Packed {
+    label: String,
+    pages: Vec<image::RgbaImage>,
+    regions: Vec<(String, usize, ui::Rect)>,
+}
+
+impl Atlas {
+    pub fn builder<L: Into<String>>(label: L) -> AtlasBuilder {
+        AtlasBuilder {
+            label: label.into(),
+            page_size: 2048,
+            padding: 2,
+            images: Vec::new(),
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Region> {
+        self.regions.get(name)
+    }
+
+    // same as get(), but with an error that says what's missing.
+    pub fn region(&self, name: &str) -> Result<&Region, String> {
+        self.get(name)
+            .ok_or_else(|| format!("{}: there's no image called {}", self.label, name))
+    }
+}
+
+// copies the image in with its top left corner at (x, y), and smears its outermost pixels across
+// the padding around it, corners included.
+fn bleed(page: &mut image::RgbaImage, image: &image::RgbaImage, (x, y): (u32, u32), padding: u32) {
+    let (width, height) = image.dimensions();
+    let padding = padding as i64;
+    for row in -padding..height as i64 + padding {
+        for column in -padding..width as i64 + padding {
+            let source = image.get_pixel(
+                column.clamp(0, width as i64 - 1) as u32,
+                row.clamp(0, height as i64 - 1) as u32,
+            );
+            page.put_pixel((x as i64 + column) as u32, (y as i64 + row) as u32, *source);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solid(width: u32, height: u32, colour: [u8; 4]) -> image::DynamicImage {
+        image::RgbaImage::from_pixel(width, height, image::Rgba(colour)).into()
+    }
+
+    fn region<'a>(packed: &'a Packed, name: &str) -> &'a (String, usize, ui::Rect) {
+        packed.regions.iter().find(|(n, _, _)| n == name).unwrap()
+    }
+
+    #[test]
+    fn overflows_onto_more_pages() {
+        // only one 10x10 fits on a 16x16 page.
+        let packed = Atlas::builder("test")
+            .page_size(16)
+            .padding(0)
+            .image("a", &solid(10, 10, [255, 0, 0, 255]))
+            .image("b", &solid(10, 10, [0, 255, 0, 255]))
+            .image("c", &solid(10, 10, [0, 0, 255, 255]))
+            .pack()
+            .unwrap();
+        assert_eq!(packed.pages.len(), 3);
+        let mut pages: Vec<usize> = packed.regions.iter().map(|(_, page, _)| *page).collect();
+        pages.sort();
+        assert_eq!(pages, [0, 1, 2]);
+        for (name, page, rect) in &packed.regions {
+            let expected = match name.as_str() {
+                "a" => [255, 0, 0, 255],
+                "b" => [0, 255, 0, 255],
+                _ => [0, 0, 255, 255],
+            };
+            let pixel = packed.pages[*page].get_pixel(rect.x as u32, rect.y as u32);
+            assert_eq!(pixel.0, expected, "{} drew over something", name);
+        }
+    }
+
+    #[test]
+    fn small_images_share_a_page() {
+        let packed = Atlas::builder("test")
+            .page_size(64)
+            .padding(0)
+            .image("a", &solid(8, 8, [255; 4]))
+            .image("b", &solid(8, 8, [255; 4]))
+            .pack()
+            .unwrap();
+        assert_eq!(packed.pages.len(), 1);
+        let (a, b) = (region(&packed, "a").2, region(&packed, "b").2);
+        assert_eq!(a.intersection(&b).width * a.intersection(&b).height, 0.0);
+    }
+
+    #[test]
+    fn duplicate_names() {
+        let error = Atlas::builder("test")
+            .image("a", &solid(4, 4, [255; 4]))
+            .image("a", &solid(8, 8, [255; 4]))
+            .pack()
+            .err()
+            .unwrap();
+        assert_eq!(error, "test: there are two images called a");
+    }
+
+    #[test]
+    fn oversize_images() {
+        let error = Atlas::builder("test")
+            .page_size(16)
+            .padding(0)
+            .image("big", &solid(17, 4, [255; 4]))
+            .pack()
+            .err()
+            .unwrap();
+        assert!(error.contains("doesn't fit"), "{}", error);
+
+        // fits on its own, but not with the padding around it.
+        let error = Atlas::builder("test")
+            .page_size(16)
+            .padding(2)
+            .image("big", &solid(14, 14, [255; 4]))
+            .pack()
+            .err()
+            .unwrap();
+        assert!(error.contains("18x18 with padding"), "{}", error);
+        assert!(Atlas::builder("test")
+            .page_size(16)
+            .padding(1)
+            .image("big", &solid(14, 14, [255; 4]))
+            .pack()
+            .is_ok());
+    }
+
+    #[test]
+    fn empty_images() {
+        let error = Atlas::builder("test")
+            .image("nothing", &solid(0, 4, [255; 4]))
+            .pack()
+            .err()
+            .unwrap();
+        assert_eq!(error, "test: nothing is empty");
+    }
+
+    #[test]
+    fn padding_bleeds_the_edges() {
+        // a different colour in each corner of a 2x2.
+        let mut image = image::RgbaImage::new(2, 2);
+        image.put_pixel(0, 0, image::Rgba([1, 0, 0, 255]));
+        image.put_pixel(1, 0, image::Rgba([2, 0, 0, 255]));
+        image.put_pixel(0, 1, image::Rgba([3, 0, 0, 255]));
+        image.put_pixel(1, 1, image::Rgba([4, 0, 0, 255]));
+        let packed = Atlas::builder("test")
+            .page_size(16)
+            .padding(2)
+            .image("corners", &image.into())
+            .pack()
+            .unwrap();
+        let (_, page, rect) = region(&packed, "corners");
+        let page = &packed.pages[*page];
+        // the rect is the image itself, not the padding.
+        assert_eq!((rect.width, rect.height), (2.0, 2.0));
+        let (x, y) = (rect.x as i64, rect.y as i64);
+        assert!(x >= 2 && y >= 2);
+        let red = |dx: i64, dy: i64| page.get_pixel((x + dx) as u32, (y + dy) as u32).0[0];
+
+        assert_eq!([red(0, 0), red(1, 0), red(0, 1), red(1, 1)], [1, 2, 3, 4]);
+        // straight out from the edges, and diagonally out from the corners, all the way across.
+        for out in 1..=2 {
+            assert_eq!(red(-out, 0), 1);
+            assert_eq!(red(0, -out), 1);
+            assert_eq!(red(1 + out, 1), 4);
+            assert_eq!(red(1, 1 + out), 4);
+            assert_eq!(red(-out, -out), 1);
+            assert_eq!(red(1 + out, -out), 2);
+            assert_eq!(red(-out, 1 + out), 3);
+            assert_eq!(red(1 + out, 1 + out), 4);
+        }
+        // and no further.
+        assert_eq!(page.get_pixel((x + 4) as u32, y as u32).0, [0; 4]);
+    }
+}
@@ -0,0 +1,199 @@
+use std::mem::size_of;
+use std::ops::Range;
+use std::sync::Arc;
+
+use wgpu::util::DeviceExt;
+
+use super::{instance, model, object};
+
+// lots of small textured things (ui elements, text, sprites) drawn with a handful of draw calls.
+// everything for a frame goes into a Batch, already in place on the screen, and the Batcher puts
+// it all in one vertex buffer. neighbours that share a texture and a scissor rectangle are drawn
+// with one call, so packing things into atlases is what keeps the calls down.
+pub struct Batcher {
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    // the pipelines want an instance and a transformation, so these are the do-nothing ones.
+    instance_buffer: wgpu::Buffer,
+    _transformation_buffer: wgpu::Buffer, // only held so it lasts as long as its bind group.
+    transformation_bind_group: wgpu::BindGroup,
+}
+
+// a frame's worth of triangles, front to back in the order they were pushed.
+#[derive(Default)]
+pub struct Batch<'a> {
+    vertices: Vec<model::ModelVertex>,
+    indices: Vec<u32>,
+    runs: Vec<Run<'a>>,
+    split: bool, // whether the next push has to start a new run.
+}
+
+struct Run<'a> {
+    material: &'a model::Material,
+    scissor: (u32, u32, u32, u32),
+    indices: Range<u32>,
+}
+
+impl<'a> Batch<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // `indices` count from the first of `vertices`. `scissor` is in physical pixels.
+    pub fn push(
+        &mut self,
+        material: &'a model::Material,
+        scissor: (u32, u32, u32, u32),
+        vertices: &[model::ModelVertex],
+        indices: &[u32],
+    ) {
+        if indices.is_empty() {
+            return;
+        }
+        let first = self.vertices.len() as u32;
+        let start = self.indices.len() as u32;
+        self.vertices.extend_from_slice(vertices);
+        self.indices.extend(indices.iter().map(|i| first + i));
+        let end = self.indices.len() as u32;
+
+        // materials with the same texture bind the same thing, whichever one's used.
+        let split = std::mem::take(&mut self.split);
+        match self.runs.last_mut() {
+            Some(run)
+                if !split
+                    && run.scissor == scissor
+                    && Arc::ptr_eq(&run.material.texture.view, &material.texture.view) =>
+            {
+                run.indices.end = end;
+            }
+            _ => self.runs.push(Run {
+                material,
+                scissor,
+                indices: start..end,
+            }),
+        }
+    }
+
+    pub fn draw_calls(&self) -> usize {
+        self.runs.len()
+    }
+
+    // keeps what's pushed next out of the last run, so something else can be drawn in between;
+    // see Batcher::draw(). returns how many runs there are so far.
+    pub fn split(&mut self) -> usize {
+        self.split = true;
+        self.runs.len()
+    }
+}
+
+impl Batcher {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let instance_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("batch instance buffer"),
+            contents: bytemuck::cast_slice(&[instance::Instance {
+                position: (0.0, 0.0, 0.0).into(),
+                rotation: (0.0, 0.0, 0.0, 1.0).into(),
+            }
+            .to_raw()]),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+        let transformation_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("batch transformation buffer"),
+            contents: bytemuck::cast_slice(&[object::TransformationUniform::default()]),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+        let transformation_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("batch transformation bind group"),
+            layout: &object::Object::layout(device),
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: transformation_buffer.as_entire_binding(),
+            }],
+        });
+        Self {
+            vertex_buffer: create_buffer(
+                device,
+                "batch vertex buffer",
+                0,
+                wgpu::BufferUsages::VERTEX,
+            ),
+            index_buffer: create_buffer(device, "batch index buffer", 0, wgpu::BufferUsages::INDEX),
+            instance_buffer,
+            _transformation_buffer: transformation_buffer,
+            transformation_bind_group,
+        }
+    }
+
+    // has to happen before the render pass starts, since the buffers might have to grow.
+    pub fn upload(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, batch: &Batch) {
+        if batch.runs.is_empty() {
+            return;
+        }
+        let vertices: &[u8] = bytemuck::cast_slice(&batch.vertices);
+        let indices: &[u8] = bytemuck::cast_slice(&batch.indices);
+        // doubling, so a frame with a few more sprites than the last doesn't mean a new buffer.
+        if vertices.len() as u64 > self.vertex_buffer.size() {
+            let size = (vertices.len() as u64).next_power_of_two();
+            self.vertex_buffer = create_buffer(
+                device,
+                "batch vertex buffer",
+                size,
+                wgpu::BufferUsages::VERTEX,
+            );
+        }
+        if indices.len() as u64 > self.index_buffer.size() {
+            let size = (indices.len() as u64).next_power_of_two();
+            self.index_buffer = create_buffer(
+                device,
+                "batch index buffer",
+                size,
+                wgpu::BufferUsages::INDEX,
+            );
+        }
+        queue.write_buffer(&self.vertex_buffer, 0, vertices);
+        queue.write_buffer(&self.index_buffer, 0, indices);
+    }
+
+    // draws some of the runs (0..batch.draw_calls() for all of them) after upload(). the
+    // pipeline has to be set already; see pipeline::Key.
+    pub fn draw<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        batch: &Batch<'a>,
+        runs: Range<usize>,
+        camera_bind_group: &'a wgpu::BindGroup,
+    ) {
+        let Some(runs) = batch.runs.get(runs) else {
+            return;
+        };
+        if runs.is_empty() {
+            return;
+        }
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+        render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        render_pass.set_bind_group(1, camera_bind_group, &[]);
+        render_pass.set_bind_group(2, &self.transformation_bind_group, &[]);
+        for run in runs {
+            let (x, y, width, height) = run.scissor;
+            render_pass.set_scissor_rect(x, y, width, height);
+            render_pass.set_bind_group(0, &run.material.bind_group, &[]);
+            render_pass.draw_indexed(run.indices.clone(), 0, 0..1);
+        }
+    }
+}
+
+// wgpu won't make an empty buffer that can be bound, so there's always something.
+fn create_buffer(
+    device: &wgpu::Device,
+    label: &str,
+    size: u64,
+    usage: wgpu::BufferUsages,
+) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some(label),
+        size: size.max(size_of::<model::ModelVertex>() as u64),
+        usage: usage | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
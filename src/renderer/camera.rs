@@ -1,20 +1,90 @@
 use std::mem::size_of;
 
 use cgmath::{prelude::*, Matrix4, Point3, Vector3};
-use wgpu::util::DeviceExt;
+
+use super::{texture, ui};
 
 #[rustfmt::skip]
 // why was this pub in the first place???
 const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
     1.0, 0.0, 0.0, 0.0,
     0.0, 1.0, 0.0, 0.0,
-    0.0, 0.0, 0.5, 0.5,
-    0.0, 0.0, 0.0, 1.0,
+    0.0, 0.0, 0.5, 0.0,
+    0.0, 0.0, 0.5, 1.0,
 );
 
 pub enum Projection {
-    Perspective(f32),
-    Orthographic,
+    Perspective(f32), // vertical fov in degrees.
+    // same fov, but with no far plane (zfar is ignored) and depth going from 1.0 at znear down to
+    // 0.0 at infinity. floats are much more precise near 0.0, so this spreads depth precision
+    // out evenly instead of spending it all right in front of the camera. it needs the depth
+    // buffer cleared to 0.0 and compared the other way round; see reverse_z().
+    InfinitePerspective(f32),
+    // how many units tall the view is, centred on the camera. the width follows the aspect.
+    Orthographic(f32),
+    // the exact edges of the view, relative to the camera; the aspect is left out of it. left
+    // can be bigger than right (and bottom bigger than top) to flip the view.
+    OrthographicBounds {
+        left: f32,
+        right: f32,
+        bottom: f32,
+        top: f32,
+    },
+}
+
+// where on its target a camera draws, as shares of the target's size from the top left corner.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Viewport {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Default for Viewport {
+    fn default() -> Self {
+        Self {
+            x: 0.0,
+            y: 0.0,
+            width: 1.0,
+            height: 1.0,
+        }
+    }
+}
+
+impl Viewport {
+    // in pixels, for a target of the given size; never less than one pixel across.
+    pub fn rect(&self, width: u32, height: u32) -> (f32, f32, f32, f32) {
+        let (width, height) = (width as f32, height as f32);
+        (
+            self.x * width,
+            self.y * height,
+            (self.width * width).max(1.0),
+            (self.height * height).max(1.0),
+        )
+    }
+}
+
+// a half-line through the world; direction is always normalized, so distances along it are in
+// world units.
+#[derive(Copy, Clone, Debug)]
+pub struct Ray {
+    pub origin: Point3<f32>,
+    pub direction: Vector3<f32>,
+}
+
+impl Ray {
+    pub fn at(&self, distance: f32) -> Point3<f32> {
+        self.origin + self.direction * distance
+    }
+}
+
+// a texture for a camera to draw into instead of the window.
+pub struct RenderTarget {
+    // clone this into a material or an element to show what the camera sees.
+    pub texture: texture::Texture,
+    pub depth_texture: texture::Texture,
+    pub size: (u32, u32),
 }
 
 pub struct Camera {
@@ -26,23 +96,197 @@ pub struct Camera {
     pub projection: Projection,
     pub znear: f32,
     pub zfar: f32,
+    pub viewport: Viewport,
+    pub order: i32, // lower goes first; later cameras draw over earlier ones.
+    // objects are only drawn if they share at least one layer with the camera.
+    pub layers: u32,
+    pub render_target: Option<RenderTarget>, // None draws to the window.
+    // what its viewport gets filled with before anything's drawn in it. None leaves whatever
+    // earlier cameras drew there, which is what overlays want; a minimap or picture in picture
+    // wants its own background. the whole target is always cleared to State::clear_colour first.
+    pub clear_colour: Option<wgpu::Color>,
+    // where it was as of the last tick, for blending frames in between; see snap().
+    previous_eye: Point3<f32>,
+    previous_target: Point3<f32>,
+    previous_up: Vector3<f32>,
     pub uniform: [[f32; 4]; 4],
     pub buffer: wgpu::Buffer,
     pub bind_group: wgpu::BindGroup,
 }
 
-impl Camera {
-    pub fn new<E: Into<Point3<f32>>, T: Into<Point3<f32>>, U: Into<Vector3<f32>>>(
-        label: String,
-        device: &wgpu::Device,
-        eye: E,
-        target: T,
-        up: U,
-        aspect: f32,
-        projection: Projection,
-        znear: f32,
-        zfar: f32,
-    ) -> Self {
+pub struct CameraBuilder {
+    label: String,
+    eye: Point3<f32>,
+    target: Point3<f32>,
+    up: Vector3<f32>,
+    aspect: f32,
+    projection: Projection,
+    znear: f32,
+    zfar: f32,
+    viewport: Viewport,
+    order: i32,
+    layers: u32,
+    render_target: Option<((u32, u32), wgpu::TextureFormat)>,
+    clear_colour: Option<wgpu::Color>,
+}
+
+impl CameraBuilder {
+    pub fn eye<E: Into<Point3<f32>>>(mut self, eye: E) -> Self {
+        self.eye = eye.into();
+        self
+    }
+
+    pub fn target<T: Into<Point3<f32>>>(mut self, target: T) -> Self {
+        self.target = target.into();
+        self
+    }
+
+    pub fn up<U: Into<Vector3<f32>>>(mut self, up: U) -> Self {
+        self.up = up.into();
+        self
+    }
+
+    pub fn aspect(mut self, aspect: f32) -> Self {
+        self.aspect = aspect;
+        self
+    }
+
+    pub fn projection(mut self, projection: Projection) -> Self {
+        self.projection = projection;
+        self
+    }
+
+    pub fn znear(mut self, znear: f32) -> Self {
+        self.znear = znear;
+        self
+    }
+
+    pub fn zfar(mut self, zfar: f32) -> Self {
+        self.zfar = zfar;
+        self
+    }
+
+    pub fn viewport(mut self, viewport: Viewport) -> Self {
+        self.viewport = viewport;
+        self
+    }
+
+    pub fn order(mut self, order: i32) -> Self {
+        self.order = order;
+        self
+    }
+
+    pub fn layers(mut self, layers: u32) -> Self {
+        self.layers = layers;
+        self
+    }
+
+    pub fn clear_colour(mut self, colour: wgpu::Color) -> Self {
+        self.clear_colour = Some(colour);
+        self
+    }
+
+    // draws into a texture of the given size instead of the window. the format has to be the
+    // surface's (see State::surface_format()), since that's what the pipelines are made for. the
+    // aspect is taken from the viewport on the texture, so there's no need to set it as well.
+    pub fn render_target(mut self, size: (u32, u32), format: wgpu::TextureFormat) -> Self {
+        self.render_target = Some((size, format));
+        self
+    }
+
+    pub fn build(self, device: &wgpu::Device) -> Result<Camera, String> {
+        let label = self.label;
+        // look_at_rh falls apart if it can't tell which way it's looking.
+        if self.eye == self.target {
+            return Err(format!("{}: eye and target can't be the same point", label));
+        }
+        if self.up.cross(self.target - self.eye).magnitude2() == 0.0 {
+            return Err(format!(
+                "{}: up can't be parallel to the view direction",
+                label
+            ));
+        }
+        if self.aspect <= 0.0 || !self.aspect.is_finite() {
+            return Err(format!("{}: aspect has to be positive", label));
+        }
+        if self.znear >= self.zfar {
+            return Err(format!("{}: znear has to be closer than zfar", label));
+        }
+        let Viewport {
+            x,
+            y,
+            width,
+            height,
+        } = self.viewport;
+        if width <= 0.0
+            || height <= 0.0
+            || x < 0.0
+            || y < 0.0
+            || x + width > 1.0
+            || y + height > 1.0
+        {
+            return Err(format!(
+                "{}: the viewport has to fit inside its target",
+                label
+            ));
+        }
+        match self.projection {
+            Projection::Perspective(fov) | Projection::InfinitePerspective(fov) => {
+                if fov <= 0.0 || fov >= 180.0 {
+                    return Err(format!(
+                        "{}: fov has to be between 0 and 180 degrees",
+                        label
+                    ));
+                }
+                if self.znear <= 0.0 {
+                    return Err(format!("{}: znear has to be positive", label));
+                }
+            }
+            Projection::Orthographic(height) => {
+                if height <= 0.0 || !height.is_finite() {
+                    return Err(format!("{}: orthographic height has to be positive", label));
+                }
+            }
+            Projection::OrthographicBounds {
+                left,
+                right,
+                bottom,
+                top,
+            } => {
+                if left == right || bottom == top {
+                    return Err(format!("{}: orthographic bounds can't be empty", label));
+                }
+            }
+        }
+
+        let render_target = match self.render_target {
+            Some(((0, _) | (_, 0), _)) => {
+                return Err(format!("{}: the target can't be empty", label));
+            }
+            Some((size, format)) => Some(RenderTarget {
+                texture: texture::Texture::create_render_target(
+                    device,
+                    size,
+                    format,
+                    &format!("{} target", label),
+                ),
+                depth_texture: texture::Texture::create_depth_texture(
+                    device,
+                    size,
+                    &format!("{} target depth texture", label),
+                ),
+                size,
+            }),
+            None => None,
+        };
+        let aspect = match &render_target {
+            Some(target) => {
+                let (_, _, width, height) = self.viewport.rect(target.size.0, target.size.1);
+                width / height
+            }
+            None => self.aspect,
+        };
+
         let uniform = Matrix4::identity().into();
 
         let buffer = device.create_buffer(&wgpu::BufferDescriptor {
@@ -54,25 +298,53 @@ impl Camera {
 
         let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
             label: Some(&format!("{} camera bind group", label)),
-            layout: &Camera::layout(&device),
+            layout: &Camera::layout(device),
             entries: &[wgpu::BindGroupEntry {
                 binding: 0,
                 resource: buffer.as_entire_binding(),
             }],
         });
 
-        Self {
+        Ok(Camera {
             label,
-            eye: eye.into(),
-            target: target.into(),
-            up: up.into(),
+            eye: self.eye,
+            target: self.target,
+            up: self.up,
             aspect,
-            projection,
-            znear,
-            zfar,
+            projection: self.projection,
+            znear: self.znear,
+            zfar: self.zfar,
+            viewport: self.viewport,
+            order: self.order,
+            layers: self.layers,
+            render_target,
+            clear_colour: self.clear_colour,
+            previous_eye: self.eye,
+            previous_target: self.target,
+            previous_up: self.up,
             uniform,
             buffer,
             bind_group,
+        })
+    }
+}
+
+impl Camera {
+    pub fn builder<L: Into<String>>(label: L) -> CameraBuilder {
+        CameraBuilder {
+            label: label.into(),
+            eye: (0.0, 0.0, 1.0).into(),
+            target: (0.0, 0.0, 0.0).into(),
+            up: Vector3::unit_y(),
+            aspect: 1.0,
+            projection: Projection::Perspective(45.0),
+            znear: 0.1,
+            zfar: 100.0,
+            viewport: Viewport::default(),
+            order: 0,
+            layers: u32::MAX, // sees everything.
+            render_target: None,
+            clear_colour: None,
         }
     }
 
@@ -92,23 +364,319 @@ impl Camera {
         })
     }
 
-    pub fn update(&mut self, queue: &wgpu::Queue) {
-        // this is kept as a separate function in case other things have to be updated; keeps
-        // things clean.
-        self.uniform = self.build_view_projection_matrix().into();
+    // makes where the camera is now the place frames blend from. State::start_tick() does this
+    // for every camera; doing it after moving one makes it jump there instead of sliding.
+    pub fn snap(&mut self) {
+        self.previous_eye = self.eye;
+        self.previous_target = self.target;
+        self.previous_up = self.up;
+    }
+
+    // `blend` goes from 0.0 (where it was as of the last tick) to 1.0 (where it is now).
+    pub fn update(&mut self, queue: &wgpu::Queue, blend: f32) {
+        let eye = self.previous_eye + (self.eye - self.previous_eye) * blend;
+        let target = self.previous_target + (self.target - self.previous_target) * blend;
+        let up = self.previous_up.lerp(self.up, blend);
+        self.uniform = self.view_projection_matrix(eye, target, up).into();
         queue.write_buffer(&self.buffer, 0, bytemuck::cast_slice(&[self.uniform]));
     }
 
+    // roughly how much of the screen's height a sphere takes up, from 0.0 to 1.0 (and beyond,
+    // when it's bigger than the screen).
+    pub fn screen_size(&self, center: Point3<f32>, radius: f32) -> f32 {
+        match self.projection {
+            Projection::Perspective(fov) | Projection::InfinitePerspective(fov) => {
+                let distance = self.eye.distance(center);
+                if distance <= radius {
+                    return f32::INFINITY; // the camera is inside it.
+                }
+                radius / (distance * (cgmath::Deg(fov) / 2.0).tan())
+            }
+            Projection::Orthographic(height) => radius * 2.0 / height,
+            Projection::OrthographicBounds { bottom, top, .. } => {
+                radius * 2.0 / (top - bottom).abs()
+            }
+        }
+    }
+
+    // matches the aspect to the viewport's shape on a target of the given size.
+    pub fn fit(&mut self, width: u32, height: u32) {
+        let (_, _, width, height) = self.viewport.rect(width, height);
+        self.aspect = width / height;
+    }
+
+    pub fn sees(&self, layers: u32) -> bool {
+        self.layers & layers != 0
+    }
+
+    // the ray going from the camera through a point on its target, in pixels from the target's
+    // top left corner (which is how winit gives cursor positions). None if the point is outside
+    // the viewport.
+    pub fn ray(&self, point: (f32, f32), size: (u32, u32)) -> Option<Ray> {
+        ray(
+            self.build_view_projection_matrix(),
+            self.reverse_z(),
+            self.viewport,
+            point,
+            size,
+        )
+    }
+
+    // whether depth is flipped, with 0.0 being the far end.
+    pub fn reverse_z(&self) -> bool {
+        self.projection.reverse_z()
+    }
+
+    // what the depth buffer has to be cleared to before drawing with this camera.
+    pub fn depth_clear(&self) -> f32 {
+        match self.reverse_z() {
+            true => 0.0,
+            false => 1.0,
+        }
+    }
+
     fn build_view_projection_matrix(&self) -> cgmath::Matrix4<f32> {
-        let view = cgmath::Matrix4::look_at_rh(self.eye, self.target, self.up);
-        let proj = match self.projection {
+        self.view_projection_matrix(self.eye, self.target, self.up)
+    }
+
+    fn view_projection_matrix(
+        &self,
+        eye: Point3<f32>,
+        target: Point3<f32>,
+        up: Vector3<f32>,
+    ) -> cgmath::Matrix4<f32> {
+        let view = cgmath::Matrix4::look_at_rh(eye, target, up);
+        self.projection.matrix(self.aspect, self.znear, self.zfar) * view
+    }
+}
+
+impl Projection {
+    pub fn reverse_z(&self) -> bool {
+        matches!(self, Projection::InfinitePerspective(_))
+    }
+
+    // from view space to wgpu's clip space, depth and all.
+    fn matrix(&self, aspect: f32, znear: f32, zfar: f32) -> cgmath::Matrix4<f32> {
+        if let Projection::InfinitePerspective(fov) = *self {
+            // already in wgpu's 0.0 to 1.0 depth range, so it skips the conversion below.
+            // clip z is always znear and w is the distance, so depth is znear / distance.
+            let f = 1.0 / (cgmath::Deg(fov) / 2.0).tan();
+            #[rustfmt::skip]
+            let proj = Matrix4::new(
+                f / aspect, 0.0, 0.0, 0.0,
+                0.0, f, 0.0, 0.0,
+                0.0, 0.0, 0.0, -1.0,
+                0.0, 0.0, znear, 0.0,
+            );
+            return proj;
+        }
+        let proj = match *self {
             Projection::Perspective(fov) => {
-                cgmath::perspective(cgmath::Deg(fov), self.aspect, self.znear, self.zfar)
+                cgmath::perspective(cgmath::Deg(fov), aspect, znear, zfar)
             } // つづ: consider renaming to 'fovy'.
-            // つづ: look into how the ortho function actually works.
-            Projection::Orthographic => cgmath::ortho(0.0, 1.0, 1.0, 0.0, -1.0, 1.0),
+            Projection::InfinitePerspective(_) => unreachable!(),
+            // ortho maps the box between the bounds (and znear to zfar in front of the camera)
+            // onto the screen, so the half-width comes from the height and aspect.
+            Projection::Orthographic(height) => {
+                let half_height = height / 2.0;
+                let half_width = half_height * aspect;
+                cgmath::ortho(
+                    -half_width,
+                    half_width,
+                    -half_height,
+                    half_height,
+                    znear,
+                    zfar,
+                )
+            }
+            Projection::OrthographicBounds {
+                left,
+                right,
+                bottom,
+                top,
+            } => cgmath::ortho(left, right, bottom, top, znear, zfar),
         };
 
-        return OPENGL_TO_WGPU_MATRIX * proj * view;
+        OPENGL_TO_WGPU_MATRIX * proj
+    }
+}
+
+// what Camera::ray() does, without the camera (or its buffers) around it.
+fn ray(
+    view_projection: Matrix4<f32>,
+    reverse_z: bool,
+    viewport: Viewport,
+    (x, y): (f32, f32),
+    (width, height): (u32, u32),
+) -> Option<Ray> {
+    let (left, top, width, height) = viewport.rect(width, height);
+    let x = (x - left) / width;
+    let y = (y - top) / height;
+    if !(0.0..=1.0).contains(&x) || !(0.0..=1.0).contains(&y) {
+        return None;
+    }
+    let (x, y) = (x * 2.0 - 1.0, 1.0 - y * 2.0); // pixels go down, clip space goes up.
+
+    let inverse = view_projection.invert()?;
+    let unproject = |depth: f32| Point3::from_homogeneous(inverse * cgmath::vec4(x, y, depth, 1.0));
+    // the near plane and somewhere past it. 0.5 stays finite either way round, whereas reverse-z
+    // puts infinity at 0.0.
+    let near = unproject(match reverse_z {
+        true => 1.0,
+        false => 0.0,
+    });
+    let further = unproject(0.5);
+    Some(Ray {
+        origin: near,
+        direction: (further - near).normalize(),
+    })
+}
+
+// looks at sprites and tilemaps. the world is in pixels (logical ones at a zoom of 1.0) with y
+// going down, same as the ui and tiled, and the camera doesn't turn.
+#[derive(Copy, Clone, Debug)]
+pub struct Camera2d {
+    pub position: cgmath::Point2<f32>, // what's in the middle of the window.
+    pub zoom: f32,                     // 2.0 makes everything twice as big.
+    previous_position: cgmath::Point2<f32>,
+    previous_zoom: f32,
+}
+
+impl Default for Camera2d {
+    fn default() -> Self {
+        Self::new((0.0, 0.0), 1.0)
+    }
+}
+
+impl Camera2d {
+    pub fn new<P: Into<cgmath::Point2<f32>>>(position: P, zoom: f32) -> Self {
+        let position = position.into();
+        Self {
+            position,
+            zoom,
+            previous_position: position,
+            previous_zoom: zoom,
+        }
+    }
+
+    // same as Camera::snap().
+    pub fn snap(&mut self) {
+        self.previous_position = self.position;
+        self.previous_zoom = self.zoom;
+    }
+
+    // the part of the world that's showing on a window of the given size (in logical pixels).
+    pub fn view(&self, (width, height): (f32, f32), blend: f32) -> ui::Rect {
+        let position = self.previous_position + (self.position - self.previous_position) * blend;
+        // zooming by steps should look even, so it's blended as a ratio.
+        let zoom = self.previous_zoom * (self.zoom / self.previous_zoom).powf(blend);
+        let (width, height) = (width / zoom, height / zoom);
+        ui::Rect {
+            x: position.x - width / 2.0,
+            y: position.y - height / 2.0,
+            width,
+            height,
+        }
+    }
+
+    // a point on the window (in logical pixels) in the world, as of the last tick.
+    pub fn world_at(&self, (x, y): (f32, f32), window: (f32, f32)) -> cgmath::Point2<f32> {
+        let view = self.view(window, 1.0);
+        (view.x + x / self.zoom, view.y + y / self.zoom).into()
+    }
+
+    // moves by a distance on the window, so dragging by a pixel moves the world by one.
+    pub fn pan(&mut self, (x, y): (f32, f32)) {
+        self.position.x -= x / self.zoom;
+        self.position.y -= y / self.zoom;
+    }
+
+    // zooms by `factor`, keeping whatever's under a point on the window where it is (the
+    // cursor, usually).
+    pub fn zoom_about(&mut self, factor: f32, point: (f32, f32), window: (f32, f32)) {
+        let before = self.world_at(point, window);
+        self.zoom = (self.zoom * factor).clamp(1.0 / 64.0, 64.0);
+        let after = self.world_at(point, window);
+        self.position += before - after;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIZE: (u32, u32) = (200, 100);
+
+    // from (0, 0, 5) looking down -z, the way the demo's cameras start off.
+    fn ray_with(projection: Projection, viewport: Viewport, point: (f32, f32)) -> Option<Ray> {
+        let view = Matrix4::look_at_rh(
+            Point3::new(0.0, 0.0, 5.0),
+            Point3::new(0.0, 0.0, 0.0),
+            Vector3::unit_y(),
+        );
+        let matrix = projection.matrix(2.0, 0.1, 100.0) * view;
+        ray(matrix, projection.reverse_z(), viewport, point, SIZE)
+    }
+
+    fn close(a: impl Into<[f32; 3]>, b: impl Into<[f32; 3]>) -> bool {
+        let (a, b) = (a.into(), b.into());
+        a.iter().zip(b).all(|(a, b)| (a - b).abs() < 1e-3)
+    }
+
+    #[test]
+    fn through_the_middle() {
+        for projection in [
+            Projection::Perspective(90.0),
+            Projection::InfinitePerspective(90.0),
+            Projection::Orthographic(10.0),
+        ] {
+            let ray = ray_with(projection, Viewport::default(), (100.0, 50.0)).unwrap();
+            // starting on the near plane.
+            assert!(close(ray.origin, [0.0, 0.0, 4.9]), "{:?}", ray);
+            assert!(close(ray.direction, [0.0, 0.0, -1.0]), "{:?}", ray);
+        }
+    }
+
+    #[test]
+    fn through_the_corners() {
+        // a 90 degree fov goes out one for every one forward, and twice that across at 2:1.
+        let expected = Vector3::new(2.0, -1.0, -1.0).normalize();
+        for projection in [
+            Projection::Perspective(90.0),
+            Projection::InfinitePerspective(90.0),
+        ] {
+            let ray = ray_with(projection, Viewport::default(), (200.0, 100.0)).unwrap();
+            assert!(close(ray.direction, expected), "{:?}", ray);
+            // the corner of the view, five units out.
+            assert!(
+                close(ray.at(4.9 / -expected.z), [10.0, -5.0, 0.0]),
+                "{:?}",
+                ray
+            );
+        }
+
+        // orthographic rays are all parallel, just further over.
+        let ray = ray_with(
+            Projection::Orthographic(10.0),
+            Viewport::default(),
+            (0.0, 0.0),
+        )
+        .unwrap();
+        assert!(close(ray.origin, [-10.0, 5.0, 4.9]), "{:?}", ray);
+        assert!(close(ray.direction, [0.0, 0.0, -1.0]), "{:?}", ray);
+    }
+
+    #[test]
+    fn viewports() {
+        // the right half of the window.
+        let right = Viewport {
+            x: 0.5,
+            width: 0.5,
+            ..Default::default()
+        };
+        assert!(ray_with(Projection::Perspective(90.0), right, (50.0, 50.0)).is_none());
+        assert!(ray_with(Projection::Perspective(90.0), right, (250.0, 50.0)).is_none());
+        let ray = ray_with(Projection::Perspective(90.0), right, (150.0, 50.0)).unwrap();
+        assert!(close(ray.direction, [0.0, 0.0, -1.0]), "{:?}", ray);
     }
 }
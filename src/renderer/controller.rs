@@ -0,0 +1,242 @@
+use cgmath::{prelude::*, Point3, Quaternion, Vector3};
+use winit::event::WindowEvent;
+
+use super::camera;
+use super::object;
+use crate::input;
+
+// something that moves a camera around. it goes by the actions and axes in input.toml (the
+// camera_* ones), so it only sees input as of the tick, and only from update(), which runs on the
+// fixed tick.
+pub trait CameraController {
+    // for window events that aren't input as such, like losing focus. returns whether the event
+    // was used up.
+    fn input(&mut self, _event: &WindowEvent) -> bool {
+        false
+    }
+
+    // `over_ui` is whether the cursor is over a ui element (or widget) as of the start of the
+    // tick (see ui::Layout), in which case clicks and scrolling are theirs, not the camera's.
+    fn update(
+        &mut self,
+        input: &input::Input,
+        over_ui: bool,
+        camera: &mut camera::Camera,
+        objects: &[object::Object],
+        delta: f32,
+    );
+
+    fn wants_cursor_grab(&self) -> bool {
+        false
+    }
+}
+
+// pitch stops just short of straight up or down, where look_at_rh can't tell which way is up.
+const PITCH_LIMIT: f32 = std::f32::consts::FRAC_PI_2 - 0.01;
+
+fn direction(yaw: f32, pitch: f32) -> Vector3<f32> {
+    Vector3::new(
+        yaw.sin() * pitch.cos(),
+        pitch.sin(),
+        -yaw.cos() * pitch.cos(),
+    )
+}
+
+// camera_move_* to move, camera_look_* to look around. camera_grab grabs the cursor (as long as
+// it isn't over the ui) and camera_release lets go of it.
+pub struct Fly {
+    pub speed: f32,       // units per second.
+    pub sensitivity: f32, // radians per unit of mouse motion.
+    yaw: f32,
+    pitch: f32,
+    grabbed: bool,
+}
+
+impl Fly {
+    // starts off looking the same way as the camera.
+    pub fn new(camera: &camera::Camera) -> Self {
+        let forward = (camera.target - camera.eye).normalize();
+        Self {
+            speed: 4.0,
+            sensitivity: 0.003,
+            yaw: forward.x.atan2(-forward.z),
+            pitch: forward.y.asin(),
+            grabbed: false,
+        }
+    }
+}
+
+impl CameraController for Fly {
+    fn input(&mut self, event: &WindowEvent) -> bool {
+        if let WindowEvent::Focused(false) = event {
+            self.grabbed = false;
+        }
+        false
+    }
+
+    fn update(
+        &mut self,
+        input: &input::Input,
+        over_ui: bool,
+        camera: &mut camera::Camera,
+        _objects: &[object::Object],
+        delta: f32,
+    ) {
+        if self.grabbed && input.pressed("camera_release") {
+            self.grabbed = false;
+        } else if !self.grabbed && !over_ui && input.pressed("camera_grab") {
+            self.grabbed = true;
+        }
+
+        if self.grabbed {
+            self.yaw += input.axis("camera_look_x") * self.sensitivity;
+            self.pitch = (self.pitch - input.axis("camera_look_y") * self.sensitivity)
+                .clamp(-PITCH_LIMIT, PITCH_LIMIT);
+        }
+
+        let forward = direction(self.yaw, self.pitch);
+        let right = forward.cross(Vector3::unit_y()).normalize();
+        let movement = forward * input.axis("camera_move_z")
+            + right * input.axis("camera_move_x")
+            + Vector3::unit_y() * input.axis("camera_move_y");
+        // going diagonally isn't any faster, but a stick pushed halfway still goes at half speed.
+        let movement = match movement.magnitude2() > 1.0 {
+            true => movement.normalize(),
+            false => movement,
+        };
+        camera.eye += movement * self.speed * delta;
+
+        camera.target = camera.eye + forward;
+        camera.up = Vector3::unit_y();
+    }
+
+    fn wants_cursor_grab(&self) -> bool {
+        self.grabbed
+    }
+}
+
+// circles a point: hold camera_drag and move camera_look_* to go around it, and camera_zoom to get
+// closer or further away. drags and zooms that start over the ui are left to it.
+pub struct Orbit {
+    pub target: Point3<f32>,
+    pub distance: f32,
+    pub min_distance: f32,
+    pub sensitivity: f32, // radians per unit of mouse motion.
+    pub zoom_speed: f32,  // share of the distance per line scrolled.
+    yaw: f32,
+    pitch: f32,
+    dragging: bool,
+}
+
+impl Orbit {
+    // starts wherever the camera already is, going around whatever it's looking at. a camera
+    // that's sitting on its target (or closer than min_distance) gets backed off to min_distance,
+    // looking down -z if there's no way to tell which way it was looking.
+    pub fn new(camera: &camera::Camera) -> Self {
+        let min_distance = 0.1;
+        let offset = camera.eye - camera.target;
+        let distance = offset.magnitude();
+        let offset = match distance > 0.0 && distance.is_finite() {
+            true => offset / distance,
+            false => Vector3::unit_z(),
+        };
+        Self {
+            target: camera.target,
+            distance: distance.max(min_distance),
+            min_distance,
+            sensitivity: 0.005,
+            zoom_speed: 0.1,
+            yaw: offset.x.atan2(-offset.z),
+            pitch: offset.y.asin().clamp(-PITCH_LIMIT, PITCH_LIMIT),
+            dragging: false,
+        }
+    }
+}
+
+impl CameraController for Orbit {
+    fn update(
+        &mut self,
+        input: &input::Input,
+        over_ui: bool,
+        camera: &mut camera::Camera,
+        _objects: &[object::Object],
+        _delta: f32,
+    ) {
+        // a drag that's already going carries on over the ui.
+        if input.pressed("camera_drag") && !over_ui {
+            self.dragging = true;
+        } else if input.released("camera_drag") {
+            self.dragging = false;
+        }
+
+        if self.dragging {
+            // dragging right spins the scene right, which means moving the camera left.
+            self.yaw -= input.axis("camera_look_x") * self.sensitivity;
+            self.pitch = (self.pitch + input.axis("camera_look_y") * self.sensitivity)
+                .clamp(-PITCH_LIMIT, PITCH_LIMIT);
+        }
+
+        if !over_ui {
+            let zoom = input.axis("camera_zoom");
+            self.distance =
+                (self.distance * (1.0 - self.zoom_speed).powf(zoom)).max(self.min_distance);
+        }
+
+        camera.eye = self.target + direction(self.yaw, self.pitch) * self.distance;
+        camera.target = self.target;
+        camera.up = Vector3::unit_y();
+    }
+}
+
+// stays behind an object and eases towards where it should be instead of snapping there, so the
+// object's movement reads as movement. doesn't take any input of its own.
+pub struct Follow {
+    pub object: usize,         // index into the state's objects.
+    pub offset: Vector3<f32>,  // from the object, in its own space (so -z is behind it).
+    pub look_at: Vector3<f32>, // from the object, also in its own space.
+    pub stiffness: f32,        // higher is snappier; around 5.0 takes half a second or so.
+    snapped: bool,
+}
+
+impl Follow {
+    pub fn new(object: usize) -> Self {
+        Self {
+            object,
+            offset: Vector3::new(0.0, 1.5, -4.0),
+            look_at: Vector3::new(0.0, 0.5, 0.0),
+            stiffness: 5.0,
+            snapped: false,
+        }
+    }
+}
+
+impl CameraController for Follow {
+    fn update(
+        &mut self,
+        _input: &input::Input,
+        _over_ui: bool,
+        camera: &mut camera::Camera,
+        objects: &[object::Object],
+        delta: f32,
+    ) {
+        let Some(object) = objects.get(self.object) else {
+            return;
+        };
+        // only the heading; following the object's roll and pitch makes people sick.
+        let forward = object.rotation.rotate_vector(Vector3::unit_z());
+        let heading = Quaternion::from_angle_y(cgmath::Rad(forward.x.atan2(forward.z)));
+
+        let eye = object.position + heading.rotate_vector(self.offset);
+        let target = object.position + heading.rotate_vector(self.look_at);
+
+        // frame rate independent smoothing; the first update jumps straight there.
+        let blend = match self.snapped {
+            true => 1.0 - (-self.stiffness * delta).exp(),
+            false => 1.0,
+        };
+        self.snapped = true;
+        camera.eye = camera.eye + (eye - camera.eye) * blend;
+        camera.target = camera.target + (target - camera.target) * blend;
+        camera.up = Vector3::unit_y();
+    }
+}
@@ -0,0 +1,224 @@
+use cgmath::{prelude::*, Matrix4, Point3, Vector3};
+
+use super::{camera, model, resource, text, texture};
+
+const SPHERE_SEGMENTS: usize = 32; // per circle.
+
+// lines (and bits of text) for seeing what the game's thinking: bounds, rays, paths and so on.
+// shapes are added during an update and stay up until the next one starts (see
+// State::start_tick()), so there's no need to keep track of them. they're drawn by every camera
+// on the window, after its objects.
+pub struct DebugDraw {
+    // whether the shapes added from here on can be hidden behind things. false shows them through
+    // everything.
+    pub depth_test: bool,
+    // what text() is written in. None leaves it out.
+    pub font: Option<text::FontId>,
+    pub text_size: f32,
+    hidden: Vec<model::ModelVertex>, // pairs of ends, depth tested.
+    shown: Vec<model::ModelVertex>,  // pairs of ends, drawn over everything.
+    labels: Vec<Label>,
+    pub(super) material: model::Material, // plain white; the colour's in the vertices.
+}
+
+pub struct Label {
+    pub position: Point3<f32>,
+    pub text: String,
+    pub colour: [f32; 4],
+}
+
+impl DebugDraw {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Result<Self, String> {
+        let texture =
+            texture::Texture::from_raw_data(Some("debug draw"), device, queue, &[255; 4], (1, 1))
+                .map_err(|e| format!("debug draw: {}", e))?;
+        Ok(Self {
+            depth_test: true,
+            font: None,
+            text_size: 14.0,
+            hidden: Vec::new(),
+            shown: Vec::new(),
+            labels: Vec::new(),
+            material: resource::create_material(
+                "debug draw material".to_string(),
+                texture,
+                device,
+                &texture::Texture::layout(device),
+            ),
+        })
+    }
+
+    pub fn clear(&mut self) {
+        self.hidden.clear();
+        self.shown.clear();
+        self.labels.clear();
+    }
+
+    pub fn line<P: Into<Point3<f32>>>(&mut self, from: P, to: P, colour: [f32; 4]) {
+        let lines = match self.depth_test {
+            true => &mut self.hidden,
+            false => &mut self.shown,
+        };
+        for position in [from.into(), to.into()] {
+            lines.push(model::ModelVertex {
+                position: position.into(),
+                colour,
+                ..Default::default()
+            });
+        }
+    }
+
+    // lines from each point to the next.
+    pub fn path<I: IntoIterator<Item = Point3<f32>>>(&mut self, points: I, colour: [f32; 4]) {
+        let mut points = points.into_iter();
+        let Some(mut previous) = points.next() else {
+            return;
+        };
+        for point in points {
+            self.line(previous, point, colour);
+            previous = point;
+        }
+    }
+
+    // a box lined up with the axes, like model::Bounds.
+    pub fn aabb<P: Into<Point3<f32>>>(&mut self, min: P, max: P, colour: [f32; 4]) {
+        self.cuboid(min, max, Matrix4::identity(), colour);
+    }
+
+    // a box from min to max, then moved by `transform`; an object's bounds in the world, say.
+    pub fn cuboid<P: Into<Point3<f32>>>(
+        &mut self,
+        min: P,
+        max: P,
+        transform: Matrix4<f32>,
+        colour: [f32; 4],
+    ) {
+        let (min, max) = (min.into(), max.into());
+        // corner i takes x from max if bit 0 is set, y if bit 1 is and z if bit 2 is.
+        let corners: [Point3<f32>; 8] = std::array::from_fn(|i| {
+            let corner = Point3::new(
+                [min.x, max.x][i & 1],
+                [min.y, max.y][i >> 1 & 1],
+                [min.z, max.z][i >> 2 & 1],
+            );
+            transform.transform_point(corner)
+        });
+        // each edge joins two corners that differ by one bit.
+        for i in 0..8 {
+            for bit in [1, 2, 4] {
+                if i & bit == 0 {
+                    self.line(corners[i], corners[i | bit], colour);
+                }
+            }
+        }
+    }
+
+    // three circles around the axes.
+    pub fn sphere<P: Into<Point3<f32>>>(&mut self, centre: P, radius: f32, colour: [f32; 4]) {
+        let centre = centre.into();
+        let axes = [Vector3::unit_x(), Vector3::unit_y(), Vector3::unit_z()];
+        for i in 0..3 {
+            let (u, v) = (axes[(i + 1) % 3] * radius, axes[(i + 2) % 3] * radius);
+            self.path(
+                (0..=SPHERE_SEGMENTS).map(|segment| {
+                    let angle = cgmath::Rad::full_turn() * segment as f32 / SPHERE_SEGMENTS as f32;
+                    let (sin, cos) = angle.0.sin_cos();
+                    centre + u * cos + v * sin
+                }),
+                colour,
+            );
+        }
+    }
+
+    // a line with a head on the `to` end.
+    pub fn arrow<P: Into<Point3<f32>>>(&mut self, from: P, to: P, colour: [f32; 4]) {
+        let (from, to) = (from.into(), to.into());
+        self.line(from, to, colour);
+        let direction = to - from;
+        let length = direction.magnitude();
+        if length <= f32::EPSILON {
+            return;
+        }
+        let direction = direction / length;
+        // anything that isn't (nearly) along the arrow gives a side to spread the head out to.
+        let other = match direction.x.abs() < 0.9 {
+            true => Vector3::unit_x(),
+            false => Vector3::unit_y(),
+        };
+        let side = direction.cross(other).normalize();
+        let up = direction.cross(side);
+        let head = (length * 0.2).min(0.25);
+        let back = to - direction * head;
+        for spread in [side, -side, up, -up] {
+            self.line(to, back + spread * head * 0.5, colour);
+        }
+    }
+
+    pub fn ray(&mut self, ray: &camera::Ray, length: f32, colour: [f32; 4]) {
+        self.arrow(ray.origin, ray.at(length), colour);
+    }
+
+    // red, green and blue arrows along x, y and z of `transform`.
+    pub fn axes(&mut self, transform: Matrix4<f32>, length: f32) {
+        let origin = transform.transform_point(Point3::origin());
+        let colours = [
+            [1.0, 0.2, 0.2, 1.0],
+            [0.2, 1.0, 0.2, 1.0],
+            [0.3, 0.5, 1.0, 1.0],
+        ];
+        let axes = [Vector3::unit_x(), Vector3::unit_y(), Vector3::unit_z()];
+        for (axis, colour) in axes.into_iter().zip(colours) {
+            let end = transform.transform_point(Point3::from_vec(axis * length));
+            self.arrow(origin, end, colour);
+        }
+    }
+
+    // `cells` squares of `spacing` each way on the xz plane, centred on `centre`.
+    pub fn grid<P: Into<Point3<f32>>>(
+        &mut self,
+        centre: P,
+        spacing: f32,
+        cells: u32,
+        colour: [f32; 4],
+    ) {
+        let centre = centre.into();
+        let half = spacing * cells as f32 / 2.0;
+        for i in 0..=cells {
+            let offset = spacing * i as f32 - half;
+            self.line(
+                centre + Vector3::new(offset, 0.0, -half),
+                centre + Vector3::new(offset, 0.0, half),
+                colour,
+            );
+            self.line(
+                centre + Vector3::new(-half, 0.0, offset),
+                centre + Vector3::new(half, 0.0, offset),
+                colour,
+            );
+        }
+    }
+
+    // text centred on a point in the world, in front of the scene but behind the ui. it doesn't
+    // get hidden behind things, whatever depth_test says.
+    pub fn text<P: Into<Point3<f32>>, T: Into<String>>(
+        &mut self,
+        position: P,
+        text: T,
+        colour: [f32; 4],
+    ) {
+        self.labels.push(Label {
+            position: position.into(),
+            text: text.into(),
+            colour,
+        });
+    }
+
+    pub fn labels(&self) -> &[Label] {
+        &self.labels
+    }
+
+    // the lines that get depth tested and the ones that don't.
+    pub(super) fn lines(&self) -> (&[model::ModelVertex], &[model::ModelVertex]) {
+        (&self.hidden, &self.shown)
+    }
+}
@@ -0,0 +1,298 @@
+use cgmath::{Euler, Quaternion};
+use winit::{event::WindowEvent, window::Window};
+
+use super::{camera, object};
+
+// an egui overlay for poking at things while the game runs. it's drawn over everything, ui
+// included, and only exists with the debug-ui feature, so none of it ends up in a release build.
+pub struct DebugUi {
+    pub visible: bool,
+    context: egui::Context,
+    winit: egui_winit::State,
+    renderer: egui_wgpu::Renderer,
+    object: usize, // which one the objects window is showing.
+    camera: usize,
+    // what the last frame left to draw.
+    primitives: Vec<egui::ClippedPrimitive>,
+    textures: egui::TexturesDelta,
+}
+
+// the things in State that aren't objects or cameras but can still be changed on the fly.
+pub struct Settings<'a> {
+    pub clear_colour: &'a mut wgpu::Color,
+    pub vsync: bool,
+    pub camera_2d: &'a mut camera::Camera2d,
+    pub frame_time: f32, // seconds, as of the last update.
+}
+
+impl DebugUi {
+    pub fn new(device: &wgpu::Device, window: &Window, format: wgpu::TextureFormat) -> Self {
+        let mut winit = egui_winit::State::new(window);
+        winit.set_max_texture_side(device.limits().max_texture_dimension_2d as usize);
+        Self {
+            visible: false,
+            context: egui::Context::default(),
+            winit,
+            renderer: egui_wgpu::Renderer::new(device, format, None, 1),
+            object: 0,
+            camera: 0,
+            primitives: Vec::new(),
+            textures: Default::default(),
+        }
+    }
+
+    // egui always gets told what happened, so it's up to date when it's shown again, but only
+    // uses events up while it's showing.
+    pub fn input(&mut self, event: &WindowEvent) -> bool {
+        let response = self.winit.on_event(&self.context, event);
+        self.visible && response.consumed
+    }
+
+    // lays the windows out for this frame; paint() draws them.
+    pub fn run(
+        &mut self,
+        window: &Window,
+        objects: &mut [object::Object],
+        cameras: &mut [camera::Camera],
+        settings: &mut Settings,
+    ) {
+        self.winit
+            .set_pixels_per_point(egui_winit::native_pixels_per_point(window));
+        let input = self.winit.take_egui_input(window);
+        let (selected_object, selected_camera) = (&mut self.object, &mut self.camera);
+        let output = self.context.run(input, |context| {
+            objects_window(context, objects, selected_object);
+            cameras_window(context, cameras, selected_camera);
+            settings_window(context, settings);
+        });
+        self.winit
+            .handle_platform_output(window, &self.context, output.platform_output);
+        self.primitives = self.context.tessellate(output.shapes);
+        self.textures.append(output.textures_delta);
+    }
+
+    // returns egui's uploads, which have to be submitted before `encoder`.
+    pub fn paint(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        size: (u32, u32),
+    ) -> Vec<wgpu::CommandBuffer> {
+        let screen = egui_wgpu::renderer::ScreenDescriptor {
+            size_in_pixels: [size.0, size.1],
+            pixels_per_point: self.winit.pixels_per_point(),
+        };
+        let textures = std::mem::take(&mut self.textures);
+        for (id, delta) in &textures.set {
+            self.renderer.update_texture(device, queue, *id, delta);
+        }
+        let commands =
+            self.renderer
+                .update_buffers(device, queue, encoder, &self.primitives, &screen);
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("debug ui render pass"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: None,
+            });
+            self.renderer
+                .render(&mut render_pass, &self.primitives, &screen);
+        }
+        for id in &textures.free {
+            self.renderer.free_texture(id);
+        }
+        commands
+    }
+}
+
+fn vector(ui: &mut egui::Ui, label: &str, values: [&mut f32; 3], speed: f32) -> bool {
+    ui.horizontal(|ui| {
+        ui.label(label);
+        let mut changed = false;
+        for value in values {
+            changed |= ui.add(egui::DragValue::new(value).speed(speed)).changed();
+        }
+        changed
+    })
+    .inner
+}
+
+fn objects_window(context: &egui::Context, objects: &mut [object::Object], selected: &mut usize) {
+    egui::Window::new("objects").show(context, |ui| {
+        if objects.is_empty() {
+            ui.label("there aren't any.");
+            return;
+        }
+        *selected = (*selected).min(objects.len() - 1);
+        egui::ComboBox::from_label("object")
+            .show_index(ui, selected, objects.len(), |i| objects[i].label.clone());
+        let object = &mut objects[*selected];
+
+        let position = &mut object.position;
+        let mut changed = vector(
+            ui,
+            "position",
+            [&mut position.x, &mut position.y, &mut position.z],
+            0.01,
+        );
+        // quaternions aren't much fun to type in.
+        let euler = Euler::from(object.rotation);
+        let mut degrees = [euler.x, euler.y, euler.z].map(|angle| cgmath::Deg::from(angle).0);
+        let [x, y, z] = &mut degrees;
+        if vector(ui, "rotation", [x, y, z], 1.0) {
+            let [x, y, z] = degrees.map(cgmath::Deg);
+            object.rotation = Quaternion::from(Euler::new(x, y, z));
+            changed = true;
+        }
+        let scale = &mut object.scale;
+        changed |= vector(
+            ui,
+            "scale",
+            [&mut scale.0, &mut scale.1, &mut scale.2],
+            0.01,
+        );
+        // no point blending towards where it's just been dragged to.
+        if changed {
+            object.snap();
+        }
+
+        ui.label(format!(
+            "level of detail {} of {}, {} instances",
+            object.lod,
+            object.lods.len(),
+            object.instances.len()
+        ));
+        ui.horizontal(|ui| {
+            ui.label("layers");
+            ui.add(egui::DragValue::new(&mut object.layers).hexadecimal(8, false, true));
+        });
+    });
+}
+
+fn cameras_window(context: &egui::Context, cameras: &mut [camera::Camera], selected: &mut usize) {
+    egui::Window::new("cameras").show(context, |ui| {
+        if cameras.is_empty() {
+            ui.label("there aren't any.");
+            return;
+        }
+        *selected = (*selected).min(cameras.len() - 1);
+        egui::ComboBox::from_label("camera")
+            .show_index(ui, selected, cameras.len(), |i| cameras[i].label.clone());
+        let camera = &mut cameras[*selected];
+
+        let mut changed = false;
+        let eye = &mut camera.eye;
+        changed |= vector(ui, "eye", [&mut eye.x, &mut eye.y, &mut eye.z], 0.01);
+        let target = &mut camera.target;
+        changed |= vector(
+            ui,
+            "target",
+            [&mut target.x, &mut target.y, &mut target.z],
+            0.01,
+        );
+        let up = &mut camera.up;
+        changed |= vector(ui, "up", [&mut up.x, &mut up.y, &mut up.z], 0.01);
+        if changed {
+            camera.snap();
+        }
+
+        ui.horizontal(|ui| match &mut camera.projection {
+            camera::Projection::Perspective(fov) | camera::Projection::InfinitePerspective(fov) => {
+                ui.label("fov");
+                ui.add(egui::Slider::new(fov, 1.0..=179.0).suffix("°"));
+            }
+            camera::Projection::Orthographic(height) => {
+                ui.label("height");
+                ui.add(egui::DragValue::new(height).speed(0.01));
+            }
+            camera::Projection::OrthographicBounds {
+                left,
+                right,
+                bottom,
+                top,
+            } => {
+                ui.label("bounds");
+                for value in [left, right, bottom, top] {
+                    ui.add(egui::DragValue::new(value).speed(0.01));
+                }
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label("near and far");
+            ui.add(
+                egui::DragValue::new(&mut camera.znear)
+                    .speed(0.01)
+                    .clamp_range(0.0..=f32::MAX),
+            );
+            ui.add(egui::DragValue::new(&mut camera.zfar).speed(0.1));
+        });
+        let viewport = &mut camera.viewport;
+        ui.horizontal(|ui| {
+            ui.label("viewport");
+            for value in [
+                &mut viewport.x,
+                &mut viewport.y,
+                &mut viewport.width,
+                &mut viewport.height,
+            ] {
+                ui.add(
+                    egui::DragValue::new(value)
+                        .speed(0.01)
+                        .clamp_range(0.0..=1.0),
+                );
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label("order");
+            ui.add(egui::DragValue::new(&mut camera.order));
+        });
+    });
+}
+
+fn settings_window(context: &egui::Context, settings: &mut Settings) {
+    egui::Window::new("render settings").show(context, |ui| {
+        ui.label(format!(
+            "{:.2} ms a frame ({:.0} fps)",
+            settings.frame_time * 1000.0,
+            1.0 / settings.frame_time.max(f32::EPSILON)
+        ));
+        ui.checkbox(&mut settings.vsync, "vsync");
+        ui.horizontal(|ui| {
+            ui.label("clear colour");
+            let colour = &mut settings.clear_colour;
+            let mut rgb = [colour.r, colour.g, colour.b].map(|channel| channel as f32);
+            if ui.color_edit_button_rgb(&mut rgb).changed() {
+                [colour.r, colour.g, colour.b] = rgb.map(|channel| channel as f64);
+            }
+        });
+        let camera_2d = &mut settings.camera_2d;
+        let changed = ui
+            .horizontal(|ui| {
+                ui.label("2d camera");
+                let position = &mut camera_2d.position;
+                ui.add(egui::DragValue::new(&mut position.x)).changed()
+                    | ui.add(egui::DragValue::new(&mut position.y)).changed()
+                    | ui.add(
+                        egui::DragValue::new(&mut camera_2d.zoom)
+                            .speed(0.01)
+                            .clamp_range(1.0 / 64.0..=64.0)
+                            .prefix("x"),
+                    )
+                    .changed()
+            })
+            .inner;
+        // same as with objects and cameras.
+        if changed {
+            camera_2d.snap();
+        }
+    });
+}
@@ -1,23 +1,26 @@
-use cgmath::Rotation3;
-use model::Vertex;
-use std::{ops::Mul, sync::Arc};
-use wgpu::util::DeviceExt;
+use std::{collections::BTreeMap, sync::Arc};
+
 // つづ: see if game_loop's winit should be used.
-use winit::{
-    event::*,
-    event_loop::{ControlFlow, EventLoop},
-    window::Window,
-    window::WindowBuilder,
-};
+use winit::{event::*, window::Window};
 
 use self::model::DrawObject; // this is a trait (which might be deprecated lol).
 
-mod camera;
-mod instance;
+pub mod atlas;
+mod batch;
+pub mod camera;
+pub mod controller;
+pub mod debug_draw;
+#[cfg(feature = "debug-ui")]
+mod debug_ui;
+pub mod instance;
 mod model;
 pub mod object;
+mod pipeline;
 pub mod resource;
+pub mod sprite;
+pub mod text;
 pub mod texture;
+pub mod tilemap;
 pub mod ui;
 
 const NUM_INSTANCES_PER_ROW: u32 = 2;
@@ -27,6 +30,23 @@ static mut INSTANCE_DISPLACEMENT: cgmath::Vector3<f32> = cgmath::Vector3::new(
     NUM_INSTANCES_PER_ROW as f32 * 0.5,
 );
 
+const CLEAR_COLOUR: wgpu::Color = wgpu::Color {
+    r: 0.1,
+    g: 0.2,
+    b: 0.3,
+    a: 1.0,
+};
+
+// what's under a point on the window; see State::pick().
+#[derive(Copy, Clone, Debug)]
+pub struct Pick {
+    pub camera: usize,   // index into cameras.
+    pub object: usize,   // index into objects.
+    pub instance: usize, // index into that object's instances.
+    pub distance: f32,   // from the camera's near plane.
+    pub point: cgmath::Point3<f32>,
+}
+
 pub struct State {
     window: Arc<Window>,
     size: winit::dpi::PhysicalSize<u32>,
@@ -34,12 +54,36 @@ pub struct State {
     pub device: wgpu::Device,
     pub queue: wgpu::Queue,
     config: wgpu::SurfaceConfiguration,
-    camera: camera::Camera,
+    scale_factor: f64, // physical pixels per logical one, which is what the ui is laid out in.
+    pub cameras: Vec<camera::Camera>, // drawn in order of their order field, not this one.
+    // keyed by index into cameras.
+    camera_controllers: BTreeMap<usize, Box<dyn controller::CameraController>>,
+    cursor_grabbed: bool,
     ui_camera: camera::Camera,
+    batcher: batch::Batcher, // for the ui.
+    // the 2d world, drawn over the cameras and under the ui. sprite_camera follows camera_2d.
+    pub camera_2d: camera::Camera2d,
+    pub sprites: Vec<sprite::Sprite>,
+    pub tilemaps: Vec<tilemap::Tilemap>,
+    sprite_camera: camera::Camera,
+    sprite_batcher: batch::Batcher,
+    view_2d: ui::Rect, // what camera_2d showed as of the last update.
     depth_texture: texture::Texture,
     pub objects: Vec<object::Object>,
     pub ui_elements: Vec<ui::Element>,
-    render_pipeline: wgpu::RenderPipeline,
+    ui_layout: ui::Layout, // as of the start of the tick.
+    pub fonts: text::Fonts,
+    pub texts: Vec<text::Text>, // drawn over the ui elements.
+    render_pipelines: pipeline::Pipelines,
+    pub debug_draw: debug_draw::DebugDraw,
+    line_batcher: batch::Batcher,  // for debug_draw's lines.
+    clear_batcher: batch::Batcher, // for cameras' clear colours.
+    debug_labels: Vec<text::Text>, // and its text, reused from frame to frame.
+    pub clear_colour: wgpu::Color, // what's behind everything.
+    elapsed: f32,                  // as of the last update.
+    delta: f32,                    // between the last two updates.
+    #[cfg(feature = "debug-ui")]
+    debug_ui: debug_ui::DebugUi,
 }
 
 impl State {
@@ -93,33 +137,41 @@ impl State {
         };
         surface.configure(&device, &config);
 
-        let camera = camera::Camera::new(
-            "the".to_string(), // the camera uniform, the camera buffer, etc.
-            &device,
-            (0.0, 0.0, 6.0),
-            (0.0, 0.0, 0.0),
-            cgmath::Vector3::unit_y(),
-            config.width as f32 / config.height as f32,
-            camera::Projection::Perspective(45.0),
-            0.1,
-            100.0,
-        );
+        let cameras = vec![
+            camera::Camera::builder("the") // the camera uniform, the camera buffer, etc.
+                .eye((0.0, 0.0, 6.0))
+                .aspect(config.width as f32 / config.height as f32)
+                .build(&device)
+                .unwrap(),
+        ];
+
+        let scale_factor = window.scale_factor();
+        // one unit to a logical pixel, with y going down; see ui_bounds().
+        let ui_camera = camera::Camera::builder("ui")
+            .projection(ui_bounds((config.width, config.height), scale_factor))
+            // elements sit at z = 0, one unit in front of the camera.
+            .znear(0.0)
+            .zfar(10.0)
+            .build(&device)
+            .unwrap();
+        let batcher = batch::Batcher::new(&device);
+        let sprite_camera = camera::Camera::builder("2d")
+            .projection(camera::Projection::Orthographic(1.0)) // set every update.
+            .znear(0.0)
+            .zfar(10.0)
+            .build(&device)
+            .unwrap();
+        let sprite_batcher = batch::Batcher::new(&device);
+        let debug_draw = debug_draw::DebugDraw::new(&device, &queue).unwrap();
+        let line_batcher = batch::Batcher::new(&device);
+        let clear_batcher = batch::Batcher::new(&device);
 
-        let ui_camera = camera::Camera::new(
-            "ui".to_string(),
+        let depth_texture = texture::Texture::create_depth_texture(
             &device,
-            (0.0, 0.0, -1.0),
-            (0.0, 0.0, 0.0),
-            cgmath::Vector3::unit_y(),
-            config.width as f32 / config.height as f32,
-            camera::Projection::Orthographic,
-            0.1,
-            100.0,
+            (config.width, config.height),
+            "depth texture",
         );
 
-        let depth_texture =
-            texture::Texture::create_depth_texture(&device, &config, "depth texture");
-
         let objects: Vec<object::Object> = Vec::new();
 
         /*for i in &mut objects {
@@ -127,12 +179,8 @@ impl State {
         }*/
 
         let ui_elements: Vec<ui::Element> = Vec::new();
+        let fonts = text::Fonts::new(&device);
 
-        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: Some("shader"),
-            source: wgpu::ShaderSource::Wgsl(include_str!("shader.wgsl").into()),
-            // look into using the include_wgsl! macro.
-        });
         let render_pipeline_layout =
             device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
                 label: Some("render pipeline layout"),
@@ -143,46 +191,10 @@ impl State {
                 ],
                 push_constant_ranges: &[],
             });
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("render pipeline"),
-            layout: Some(&render_pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: "vs_main",
-                buffers: &[model::ModelVertex::desc(), instance::InstanceRaw::desc()],
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &shader,
-                entry_point: "fs_main",
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: config.format,
-                    blend: Some(wgpu::BlendState::ALPHA_BLENDING), // no linear!
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
-            }),
-            primitive: wgpu::PrimitiveState {
-                topology: wgpu::PrimitiveTopology::TriangleList,
-                strip_index_format: None,
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Back),
-                polygon_mode: wgpu::PolygonMode::Fill,
-                unclipped_depth: false,
-                conservative: false,
-            },
-            depth_stencil: Some(wgpu::DepthStencilState {
-                format: texture::Texture::DEPTH_FORMAT,
-                depth_write_enabled: true,
-                depth_compare: wgpu::CompareFunction::LessEqual,
-                stencil: wgpu::StencilState::default(),
-                bias: wgpu::DepthBiasState::default(),
-            }),
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
-            },
-            multiview: None,
-        });
+        let render_pipelines = pipeline::Pipelines::new(render_pipeline_layout, config.format);
+
+        #[cfg(feature = "debug-ui")]
+        let debug_ui = debug_ui::DebugUi::new(&device, &window, config.format);
 
         Self {
             window,
@@ -191,12 +203,34 @@ impl State {
             device,
             queue,
             config,
-            camera,
+            scale_factor,
+            cameras,
+            camera_controllers: BTreeMap::new(),
+            cursor_grabbed: false,
             ui_camera,
+            batcher,
+            camera_2d: camera::Camera2d::default(),
+            sprites: Vec::new(),
+            tilemaps: Vec::new(),
+            sprite_camera,
+            sprite_batcher,
+            view_2d: ui::Rect::default(),
+            ui_layout: ui::Layout::default(),
             depth_texture,
             objects,
             ui_elements,
-            render_pipeline,
+            fonts,
+            texts: Vec::new(),
+            render_pipelines,
+            debug_draw,
+            line_batcher,
+            clear_batcher,
+            debug_labels: Vec::new(),
+            clear_colour: CLEAR_COLOUR,
+            elapsed: 0.0,
+            delta: 0.0,
+            #[cfg(feature = "debug-ui")]
+            debug_ui,
         }
     }
 
@@ -205,22 +239,199 @@ impl State {
         &self.window
     }
 
+    pub fn size(&self) -> winit::dpi::PhysicalSize<u32> {
+        self.size
+    }
+
     pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
         //if new_size.width > 0 && new_size.height > 0
         self.size = new_size;
         self.config.width = new_size.width;
         self.config.height = new_size.height;
         self.surface.configure(&self.device, &self.config);
-        self.depth_texture =
-            texture::Texture::create_depth_texture(&self.device, &self.config, "depth texture");
-        self.camera.aspect = self.config.width as f32 / self.config.height as f32;
+        self.depth_texture = texture::Texture::create_depth_texture(
+            &self.device,
+            (self.config.width, self.config.height),
+            "depth texture",
+        );
+        // cameras drawing into textures keep the shape of their textures.
+        for i in self
+            .cameras
+            .iter_mut()
+            .filter(|camera| camera.render_target.is_none())
+        {
+            i.fit(self.config.width, self.config.height);
+        }
+        self.ui_camera.projection =
+            ui_bounds((self.config.width, self.config.height), self.scale_factor);
+    }
+
+    // from winit's ScaleFactorChanged, which comes with a resize of its own.
+    pub fn set_scale_factor(&mut self, scale_factor: f64) {
+        self.scale_factor = scale_factor;
+        self.ui_camera.projection =
+            ui_bounds((self.config.width, self.config.height), self.scale_factor);
     }
 
-    fn input(&mut self, _event: &WindowEvent) -> bool {
-        false
+    pub fn scale_factor(&self) -> f64 {
+        self.scale_factor
     }
 
-    pub fn update(&mut self, elapsed: f32) {
+    // the whole window, in the logical pixels the ui is laid out in.
+    pub fn ui_rect(&self) -> ui::Rect {
+        ui::Rect {
+            width: (self.config.width as f64 / self.scale_factor) as f32,
+            height: (self.config.height as f64 / self.scale_factor) as f32,
+            ..Default::default()
+        }
+    }
+
+    // the ui elements where they were when the tick started, for checking what the pointer's
+    // over; see ui::Layout.
+    pub fn ui_layout(&self) -> &ui::Layout {
+        &self.ui_layout
+    }
+
+    // the closest object under a point on the window, in pixels from the top left corner (the
+    // way cursor positions come in). when cameras overlap, the one drawn last is the one looked
+    // through. objects are tested on the cpu against the triangles of whichever level of detail
+    // they're drawing, as of the last update.
+    pub fn pick(&self, position: winit::dpi::PhysicalPosition<f64>) -> Option<Pick> {
+        let size = (self.config.width, self.config.height);
+        let position = (position.x as f32, position.y as f32);
+        let (camera, ray) = self
+            .cameras
+            .iter()
+            .enumerate()
+            .filter(|(_, camera)| camera.render_target.is_none())
+            // max_by_key keeps the last of equals, which matches the drawing order.
+            .filter_map(|(i, camera)| Some((i, camera, camera.ray(position, size)?)))
+            .max_by_key(|(_, camera, _)| camera.order)
+            .map(|(i, _, ray)| (i, ray))?;
+
+        self.objects
+            .iter()
+            .enumerate()
+            .filter(|(_, object)| self.cameras[camera].sees(object.layers))
+            .filter_map(|(i, object)| {
+                let (instance, distance) = object.raycast(&ray)?;
+                Some((i, instance, distance))
+            })
+            .min_by(|a, b| a.2.total_cmp(&b.2))
+            .map(|(object, instance, distance)| Pick {
+                camera,
+                object,
+                instance,
+                distance,
+                point: ray.at(distance),
+            })
+    }
+
+    // what render targets have to be made with; see camera::CameraBuilder::render_target().
+    pub fn surface_format(&self) -> wgpu::TextureFormat {
+        self.config.format
+    }
+
+    // hands the camera at `camera` (an index into cameras) over to a controller. None leaves it
+    // wherever it was put.
+    pub fn set_camera_controller(
+        &mut self,
+        camera: usize,
+        controller: Option<Box<dyn controller::CameraController>>,
+    ) {
+        match controller {
+            Some(controller) => self.camera_controllers.insert(camera, controller),
+            None => self.camera_controllers.remove(&camera),
+        };
+        self.grab_cursor();
+    }
+
+    // returns whether the event was used up.
+    pub fn input(&mut self, event: &WindowEvent) -> bool {
+        let mut used = false;
+        for controller in self.camera_controllers.values_mut() {
+            used |= controller.input(event);
+        }
+        self.grab_cursor();
+        used
+    }
+
+    // moves the cameras along; meant to be called from the fixed update, after input.tick(), with
+    // `delta` being the length of a tick in seconds.
+    pub fn update_cameras(&mut self, input: &crate::input::Input, delta: f32) {
+        let scale_factor = self.scale_factor as f32;
+        let over_ui = input.cursor().is_some_and(|(x, y)| {
+            self.ui_layout
+                .element_at((x / scale_factor, y / scale_factor))
+                .is_some()
+        });
+        for (i, controller) in &mut self.camera_controllers {
+            if let Some(camera) = self.cameras.get_mut(*i) {
+                controller.update(input, over_ui, camera, &self.objects, delta);
+            }
+        }
+        self.grab_cursor();
+    }
+
+    fn grab_cursor(&mut self) {
+        let grab = self
+            .camera_controllers
+            .values()
+            .any(|controller| controller.wants_cursor_grab());
+        if grab == self.cursor_grabbed {
+            return;
+        }
+        self.cursor_grabbed = grab;
+        if grab {
+            // not every platform can lock the cursor in place; confining it is close enough,
+            // since the controllers go by raw mouse motion anyway.
+            if self
+                .window
+                .set_cursor_grab(winit::window::CursorGrabMode::Locked)
+                .is_err()
+            {
+                let _ = self
+                    .window
+                    .set_cursor_grab(winit::window::CursorGrabMode::Confined);
+            }
+        } else {
+            let _ = self
+                .window
+                .set_cursor_grab(winit::window::CursorGrabMode::None);
+        }
+        self.window.set_cursor_visible(!grab);
+    }
+
+    // call this at the start of every update, before anything gets moved, so the frames drawn
+    // until the next one can blend from where things were to where they end up.
+    pub fn start_tick(&mut self) {
+        for camera in &mut self.cameras {
+            camera.snap();
+        }
+        for object in &mut self.objects {
+            object.snap();
+        }
+        for element in &mut self.ui_elements {
+            element.snap();
+        }
+        self.ui_layout = ui::Layout::new(&self.ui_elements, self.ui_rect());
+        for text in &mut self.texts {
+            text.snap();
+        }
+        self.camera_2d.snap();
+        for sprite in &mut self.sprites {
+            sprite.snap();
+        }
+        for tilemap in &mut self.tilemaps {
+            tilemap.snap();
+        }
+        self.debug_draw.clear();
+    }
+
+    // `blend` is how far the frame is between the last tick and the next, from 0.0 to 1.0; what
+    // gets drawn is that far between where things were before the last tick and after it. that
+    // keeps motion smooth whatever the frame rate, at the cost of being a tick behind.
+    pub fn update(&mut self, elapsed: f32, blend: f32) {
         /*use cgmath::InnerSpace;
         let forwards = self.camera.target - self.camera.eye;
         let forwards_norm = forwards.normalize();
@@ -299,17 +510,128 @@ impl State {
             cgmath::Quaternion::from_angle_y(cgmath::Deg((COUNTER * 3.0) % 360.0));
         self.objects[0].scale = (1.0, (COUNTER * 0.25).sin() * 0.5 + 0.75, 1.0);
         self.objects[0].update(&self.queue);*/
-        self.camera.update(&self.queue);
-        self.ui_camera.update(&self.queue);
+        let delta = elapsed - self.elapsed;
+        self.elapsed = elapsed;
+        self.delta = delta;
+
+        for i in &mut self.cameras {
+            i.update(&self.queue, blend);
+        }
+        self.ui_camera.update(&self.queue, 1.0); // never moves.
         for i in &mut self.objects {
-            i.update(&self.queue);
+            i.select_lod(&self.cameras, delta);
+            i.update(&self.queue, blend);
+        }
+
+        // world pixels are logical ones at a zoom of 1, so pixel art stays crisp on any display.
+        let window = self.ui_rect();
+        let view = self.camera_2d.view((window.width, window.height), blend);
+        self.view_2d = view;
+        self.sprite_camera.projection = camera::Projection::OrthographicBounds {
+            left: view.x,
+            right: view.x + view.width,
+            bottom: view.y + view.height,
+            top: view.y,
+        };
+        self.sprite_camera.update(&self.queue, 1.0);
+        for i in &mut self.sprites {
+            i.advance(delta);
+            i.update(blend);
+        }
+        for i in &mut self.tilemaps {
+            i.update(&self.queue, blend);
+        }
+
+        // parents come first, so each element's is already laid out by the time it gets there.
+        for i in 0..self.ui_elements.len() {
+            let (earlier, rest) = self.ui_elements.split_at_mut(i);
+            let parent = ui::inherited(earlier, rest[0].parent, window);
+            rest[0].update(blend, parent);
         }
-        for i in &mut self.ui_elements {
-            i.update(&self.queue);
+
+        self.place_debug_labels();
+
+        let scale_factor = self.scale_factor as f32;
+        // if the atlas fills up, it's emptied and everything gets laid out again into the room
+        // that frees up. once is enough; if that still isn't, there's just too much text.
+        let fits = self
+            .texts
+            .iter_mut()
+            .chain(&mut self.debug_labels)
+            .all(|text| text.lay_out(&self.queue, &mut self.fonts, scale_factor));
+        if !fits {
+            self.fonts.clear();
+            for text in self.texts.iter_mut().chain(&mut self.debug_labels) {
+                if !text.lay_out(&self.queue, &mut self.fonts, scale_factor) {
+                    log::warn!("{}: the glyph atlas is full", text.label);
+                }
+            }
+        }
+        for text in &mut self.texts {
+            let parent = ui::inherited(&self.ui_elements, text.parent, window);
+            text.update(blend, parent, scale_factor);
+        }
+        for label in &mut self.debug_labels {
+            label.update(blend, ui::Inherited::window(window), scale_factor);
+        }
+    }
+
+    // puts debug_draw's text where its points are on the window, as seen by the last camera
+    // drawn over all of it. the text goes over every viewport, so going by a minimap's camera
+    // would scatter it across the window.
+    fn place_debug_labels(&mut self) {
+        let size = (self.config.width, self.config.height);
+        let camera = self
+            .cameras
+            .iter()
+            .filter(|camera| {
+                camera.render_target.is_none() && camera.viewport == camera::Viewport::default()
+            })
+            .max_by_key(|camera| camera.order);
+        let (Some(camera), Some(font)) = (camera, self.debug_draw.font) else {
+            self.debug_labels.clear();
+            return;
+        };
+        let matrix = cgmath::Matrix4::from(camera.uniform); // blended already.
+        let (left, top, width, height) = camera.viewport.rect(size.0, size.1);
+        let scale_factor = self.scale_factor as f32;
+        let mut placed = 0;
+        for label in self.debug_draw.labels() {
+            let clip = matrix * label.position.to_homogeneous();
+            if clip.w <= 0.0 {
+                continue; // behind the camera.
+            }
+            let (x, y) = (clip.x / clip.w, clip.y / clip.w);
+            let position = (
+                (left + (x + 1.0) / 2.0 * width) / scale_factor,
+                (top + (1.0 - y) / 2.0 * height) / scale_factor,
+            );
+            if placed == self.debug_labels.len() {
+                let text = text::Text::builder("debug label", font)
+                    .pivot(ui::Anchor::CENTRE)
+                    .build();
+                match text {
+                    Ok(text) => self.debug_labels.push(text),
+                    Err(error) => {
+                        log::warn!("{}", error);
+                        break;
+                    }
+                }
+            }
+            let text = &mut self.debug_labels[placed];
+            text.set_font(font);
+            text.set_size(self.debug_draw.text_size);
+            text.set_text(&label.text);
+            text.set_colour(label.colour);
+            text.position = position.into();
+            text.snap(); // it's already where it is this frame.
+            placed += 1;
         }
+        self.debug_labels.truncate(placed);
     }
 
     pub fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
+        self.prepare_pipelines();
         let output = self.surface.get_current_texture()?;
         let view = output
             .texture
@@ -321,57 +643,573 @@ impl State {
                 label: Some("encoder"),
             });
 
+        let size = (self.config.width, self.config.height);
+        // the debug lines go in first, so each camera can draw them after its objects.
+        let (hidden, shown) = self.debug_draw.lines();
+        let indices: Vec<u32> = (0..hidden.len().max(shown.len()) as u32).collect();
+        let material = &self.debug_draw.material;
+        let mut line_batch = batch::Batch::new();
+        line_batch.push(
+            material,
+            (0, 0, size.0, size.1),
+            hidden,
+            &indices[..hidden.len()],
+        );
+        let split = line_batch.split();
+        line_batch.push(
+            material,
+            (0, 0, size.0, size.1),
+            shown,
+            &indices[..shown.len()],
+        );
+        self.line_batcher
+            .upload(&self.device, &self.queue, &line_batch);
+        let lines = Lines {
+            batcher: &self.line_batcher,
+            batch: &line_batch,
+            split,
+        };
+
+        let mut cameras: Vec<&camera::Camera> = self.cameras.iter().collect();
+        cameras.sort_by_key(|camera| camera.order); // stable, so ties keep their places.
+
+        // a quad for each camera with a clear colour, in the same order as `cameras`. each one
+        // covers the whole target and is cut down to the camera's viewport by its scissor
+        // rectangle; see draw_camera().
+        let window = self.ui_rect();
+        let mut clear_batch = batch::Batch::new();
+        let clear_runs: Vec<Option<usize>> = cameras
+            .iter()
+            .map(|camera| {
+                let colour = camera.clear_colour?;
+                let target = camera
+                    .render_target
+                    .as_ref()
+                    .map_or(size, |target| target.size);
+                let run = clear_batch.split();
+                clear_batch.push(
+                    &self.debug_draw.material,
+                    viewport_scissor(camera.viewport, target),
+                    &clear_quad(window, colour),
+                    &[0, 2, 3, 3, 1, 0],
+                );
+                Some(run)
+            })
+            .collect();
+        self.clear_batcher
+            .upload(&self.device, &self.queue, &clear_batch);
+        let clear = |i: usize| {
+            clear_runs[i].map(|run| Clear {
+                batcher: &self.clear_batcher,
+                batch: &clear_batch,
+                run,
+                camera_bind_group: &self.ui_camera.bind_group,
+            })
+        };
+
+        // textures first, so the window can show what's in them this frame rather than last.
+        for (i, camera) in cameras.iter().enumerate() {
+            let Some(target) = &camera.render_target else {
+                continue;
+            };
+            draw_camera(
+                &mut encoder,
+                camera,
+                &self.objects,
+                &self.render_pipelines,
+                (
+                    &target.texture.view,
+                    &target.depth_texture.view,
+                    wgpu::LoadOp::Clear(self.clear_colour),
+                    target.size,
+                ),
+                clear(i),
+                None,
+            );
+        }
+
+        // only the first pass on the window clears all of it; everything after draws over it,
+        // apart from cameras with clear colours of their own.
+        let mut load = wgpu::LoadOp::Clear(self.clear_colour);
+        for (i, camera) in cameras.iter().enumerate() {
+            if camera.render_target.is_some() {
+                continue;
+            }
+            draw_camera(
+                &mut encoder,
+                camera,
+                &self.objects,
+                &self.render_pipelines,
+                (&view, &self.depth_texture.view, load, size),
+                clear(i),
+                Some(&lines),
+            );
+            load = wgpu::LoadOp::Load;
+        }
+
+        if !self.sprites.is_empty() || !self.tilemaps.is_empty() {
+            self.draw_2d(&mut encoder, &view, load);
+            load = wgpu::LoadOp::Load;
+        }
+
+        // the ui goes over every camera, across the whole window, with debug_draw's text under it.
+        let mut batch = batch::Batch::new();
+        for label in &self.debug_labels {
+            batch.push(
+                &self.fonts.material,
+                (0, 0, size.0, size.1),
+                label.vertices(),
+                label.indices(),
+            );
+        }
+        for node in ui::draw_order(&self.ui_elements, &self.texts) {
+            let (material, clip, vertices, indices) = match node {
+                ui::Node::Element(i) => {
+                    let element = &self.ui_elements[i];
+                    (
+                        &element.material,
+                        element.clipped_to(),
+                        element.vertices(),
+                        &ui::INDICES[..],
+                    )
+                }
+                ui::Node::Text(i) => {
+                    let text = &self.texts[i];
+                    (
+                        &self.fonts.material,
+                        text.clipped_to(),
+                        text.vertices(),
+                        text.indices(),
+                    )
+                }
+            };
+            let Some(scissor) = scissor(clip, self.scale_factor, size) else {
+                continue; // clipped off entirely.
+            };
+            batch.push(material, scissor, vertices, indices);
+        }
+        self.batcher.upload(&self.device, &self.queue, &batch);
+
         let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-            label: Some("render pass"),
+            label: Some("ui render pass"),
             color_attachments: &[Some(wgpu::RenderPassColorAttachment {
                 view: &view,
                 resolve_target: None,
-                ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(wgpu::Color {
-                        r: 0.1,
-                        g: 0.2,
-                        b: 0.3,
-                        a: 1.0,
-                    }),
-                    store: true,
-                },
+                ops: wgpu::Operations { load, store: true },
             })],
             depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
                 view: &self.depth_texture.view,
                 depth_ops: Some(wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(1.0),
+                    load: wgpu::LoadOp::Clear(self.ui_camera.depth_clear()),
                     store: true,
                 }),
                 stencil_ops: None,
             }),
         });
-
-        render_pass.set_pipeline(&self.render_pipeline);
-        //render_pass.set_bind_group(0, &self.diffuse_bing_group, &[]);
-        //render_pass.set_bind_group(1, &self.camera_bind_group, &[]);
-        //render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-        //render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-        //render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
-        //render_pass.draw_indexed(0..self.num_indices, 0, 0..self.instances.len() as _);
-        for i in &self.objects {
-            render_pass.draw_object_instanced(&i, &self.camera.bind_group);
-        }
-        for i in &self.ui_elements {
-            render_pass.draw_mesh(
-                &i.mesh,
-                &i.material,
-                &self.ui_camera.bind_group,
-                &i.bind_group,
-            );
-        }
+        render_pass.set_pipeline(
+            &self.render_pipelines[&pipeline::Key {
+                reverse_z: self.ui_camera.reverse_z(),
+                depth_test: false,
+                ..Default::default()
+            }],
+        );
+        self.batcher.draw(
+            &mut render_pass,
+            &batch,
+            0..batch.draw_calls(),
+            &self.ui_camera.bind_group,
+        );
 
         drop(render_pass);
-        self.queue.submit(std::iter::once(encoder.finish()));
+        // the overlay's uploads have to go in before the pass that draws it.
+        #[cfg(feature = "debug-ui")]
+        let uploads = self.draw_debug_ui(&mut encoder, &view);
+        #[cfg(not(feature = "debug-ui"))]
+        let uploads = Vec::new();
+        self.queue
+            .submit(uploads.into_iter().chain(std::iter::once(encoder.finish())));
         output.present();
 
         Ok(())
     }
+
+    // makes the pipelines for anything that's about to be drawn with one that doesn't exist
+    // yet. every level of detail is included, so switching levels doesn't have to wait on one.
+    fn prepare_pipelines(&mut self) {
+        let mut reverse_z: Vec<bool> = self
+            .cameras
+            .iter()
+            .chain([&self.ui_camera, &self.sprite_camera])
+            .map(|camera| camera.reverse_z())
+            .collect();
+        reverse_z.sort();
+        reverse_z.dedup();
+
+        let lines = pipeline::Key {
+            topology: wgpu::PrimitiveTopology::LineList,
+            ..Default::default()
+        };
+        // the ui, sprites and clear colours, then debug_draw's lines both ways, then the meshes.
+        let mut keys = vec![
+            (pipeline::Key::default(), false),
+            (lines, true),
+            (lines, false),
+        ];
+        if !self.tilemaps.is_empty() {
+            keys.push((tilemap::pipeline_key(), false));
+        }
+        let meshes = self
+            .objects
+            .iter()
+            .flat_map(|object| &object.lods)
+            .flat_map(|lod| &lod.model.meshes)
+            .map(|mesh| (mesh.pipeline_key(), true));
+        for (key, depth_test) in keys.into_iter().chain(meshes) {
+            for &reverse_z in &reverse_z {
+                let key = pipeline::Key {
+                    reverse_z,
+                    depth_test,
+                    ..key
+                };
+                self.render_pipelines.prepare(&self.device, key);
+            }
+        }
+    }
+
+    // the debug overlay gets events before anything else does; true means it used this one up.
+    #[cfg(feature = "debug-ui")]
+    pub fn debug_ui_input(&mut self, event: &WindowEvent) -> bool {
+        self.debug_ui.input(event)
+    }
+
+    #[cfg(feature = "debug-ui")]
+    pub fn toggle_debug_ui(&mut self) {
+        self.debug_ui.visible = !self.debug_ui.visible;
+    }
+
+    #[cfg(feature = "debug-ui")]
+    fn draw_debug_ui(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+    ) -> Vec<wgpu::CommandBuffer> {
+        if !self.debug_ui.visible {
+            return Vec::new();
+        }
+        let vsync = self.config.present_mode == wgpu::PresentMode::AutoVsync;
+        let mut settings = debug_ui::Settings {
+            clear_colour: &mut self.clear_colour,
+            vsync,
+            camera_2d: &mut self.camera_2d,
+            frame_time: self.delta,
+        };
+        self.debug_ui.run(
+            &self.window,
+            &mut self.objects,
+            &mut self.cameras,
+            &mut settings,
+        );
+        if settings.vsync != vsync {
+            self.config.present_mode = match settings.vsync {
+                true => wgpu::PresentMode::AutoVsync,
+                false => wgpu::PresentMode::AutoNoVsync,
+            };
+            self.surface.configure(&self.device, &self.config);
+        }
+        self.debug_ui.paint(
+            &self.device,
+            &self.queue,
+            encoder,
+            view,
+            (self.config.width, self.config.height),
+        )
+    }
+
+    // sprites and tile layers, sorted by z (layers go under sprites at the same z). sprites are
+    // batched, and the batch is split wherever a layer has to go in between.
+    fn draw_2d(
+        &mut self,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        load: wgpu::LoadOp<wgpu::Color>,
+    ) {
+        enum Item {
+            Layer(usize, usize),
+            Sprite(usize),
+        }
+        enum Step {
+            Layer(usize, usize),
+            Sprites(std::ops::Range<usize>),
+        }
+
+        let visible = |bounds: ui::Rect| {
+            let overlap = bounds.intersection(&self.view_2d);
+            overlap.width > 0.0 && overlap.height > 0.0
+        };
+        let mut items: Vec<(i32, Item)> = self
+            .tilemaps
+            .iter()
+            .enumerate()
+            .flat_map(|(i, tilemap)| {
+                (0..tilemap.layers.len())
+                    .map(move |layer| (tilemap.z + layer as i32, Item::Layer(i, layer)))
+            })
+            .collect();
+        items.extend(
+            self.sprites
+                .iter()
+                .enumerate()
+                .filter(|(_, sprite)| sprite.visible && visible(sprite.bounds()))
+                .map(|(i, sprite)| (sprite.z, Item::Sprite(i))),
+        );
+        items.sort_by_key(|(z, _)| *z); // stable, so layers stay under sprites.
+
+        let size = (self.config.width, self.config.height);
+        let whole = (0, 0, size.0, size.1);
+        let mut batch = batch::Batch::new();
+        let mut steps = Vec::new();
+        let mut start = 0;
+        for (_, item) in items {
+            match item {
+                Item::Sprite(i) => {
+                    let sprite = &self.sprites[i];
+                    batch.push(
+                        &sprite.sheet.material,
+                        whole,
+                        sprite.vertices(),
+                        &sprite::INDICES,
+                    );
+                }
+                Item::Layer(tilemap, layer) => {
+                    let end = batch.split();
+                    if end > start {
+                        steps.push(Step::Sprites(start..end));
+                    }
+                    start = end;
+                    steps.push(Step::Layer(tilemap, layer));
+                }
+            }
+        }
+        steps.push(Step::Sprites(start..batch.draw_calls()));
+        self.sprite_batcher
+            .upload(&self.device, &self.queue, &batch);
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("2d render pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations { load, store: true },
+            })],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &self.depth_texture.view,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(self.sprite_camera.depth_clear()),
+                    store: true,
+                }),
+                stencil_ops: None,
+            }),
+        });
+        let sprite_pipeline = &self.render_pipelines[&pipeline::Key {
+            reverse_z: self.sprite_camera.reverse_z(),
+            depth_test: false,
+            ..Default::default()
+        }];
+        // tile layers only upload the attributes they use, so they have a pipeline of their own.
+        let tile_pipeline = (!self.tilemaps.is_empty()).then(|| {
+            &self.render_pipelines[&pipeline::Key {
+                reverse_z: self.sprite_camera.reverse_z(),
+                depth_test: false,
+                ..tilemap::pipeline_key()
+            }]
+        });
+        for step in steps {
+            match step {
+                Step::Layer(tilemap, layer) => {
+                    if let Some(tile_pipeline) = tile_pipeline {
+                        render_pass.set_pipeline(tile_pipeline);
+                    }
+                    // the sprites before it may have left a scissor rectangle behind.
+                    render_pass.set_scissor_rect(0, 0, size.0, size.1);
+                    self.tilemaps[tilemap].draw_layer(
+                        &mut render_pass,
+                        layer,
+                        self.view_2d,
+                        &self.sprite_camera,
+                    );
+                }
+                Step::Sprites(runs) => {
+                    render_pass.set_pipeline(sprite_pipeline);
+                    self.sprite_batcher.draw(
+                        &mut render_pass,
+                        &batch,
+                        runs,
+                        &self.sprite_camera.bind_group,
+                    );
+                }
+            }
+        }
+    }
+}
+
+// draws every object the camera can see into its viewport on the target. the depth buffer is
+// cleared for each camera, so cameras can't hide each other's objects; later ones just draw over
+// earlier ones where their viewports overlap, unless they have a clear colour of their own.
+//
+// a camera drawing into a texture can't see anything that uses that same texture (wgpu won't
+// have it read and written in one pass); put those objects on a layer the camera doesn't see.
+fn draw_camera(
+    encoder: &mut wgpu::CommandEncoder,
+    camera: &camera::Camera,
+    objects: &[object::Object],
+    pipelines: &pipeline::Pipelines,
+    (view, depth_view, load, (width, height)): (
+        &wgpu::TextureView,
+        &wgpu::TextureView,
+        wgpu::LoadOp<wgpu::Color>,
+        (u32, u32),
+    ),
+    clear: Option<Clear>,
+    lines: Option<&Lines>,
+) {
+    let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some(&format!("{} render pass", camera.label)),
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+            view,
+            resolve_target: None,
+            ops: wgpu::Operations { load, store: true },
+        })],
+        depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+            view: depth_view,
+            depth_ops: Some(wgpu::Operations {
+                load: wgpu::LoadOp::Clear(camera.depth_clear()),
+                store: true,
+            }),
+            stencil_ops: None,
+        }),
+    });
+
+    // a load op clears the whole target, so the viewport gets a quad instead.
+    if let Some(clear) = clear {
+        render_pass.set_viewport(0.0, 0.0, width as f32, height as f32, 0.0, 1.0);
+        render_pass.set_pipeline(
+            &pipelines[&pipeline::Key {
+                reverse_z: camera.reverse_z(),
+                depth_test: false,
+                ..Default::default()
+            }],
+        );
+        clear.batcher.draw(
+            &mut render_pass,
+            clear.batch,
+            clear.run..clear.run + 1,
+            clear.camera_bind_group,
+        );
+        render_pass.set_scissor_rect(0, 0, width, height);
+    }
+
+    let (x, y, width, height) = camera.viewport.rect(width, height);
+    render_pass.set_viewport(x, y, width, height, 0.0, 1.0);
+
+    //render_pass.set_bind_group(0, &self.diffuse_bing_group, &[]);
+    //render_pass.set_bind_group(1, &self.camera_bind_group, &[]);
+    //render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+    //render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+    //render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+    //render_pass.draw_indexed(0..self.num_indices, 0, 0..self.instances.len() as _);
+    for i in objects.iter().filter(|object| camera.sees(object.layers)) {
+        render_pass.draw_object_instanced(i, camera, pipelines);
+    }
+
+    let Some(lines) = lines else {
+        return;
+    };
+    let runs = [
+        (true, 0..lines.split),
+        (false, lines.split..lines.batch.draw_calls()),
+    ];
+    for (depth_test, runs) in runs {
+        render_pass.set_pipeline(
+            &pipelines[&pipeline::Key {
+                topology: wgpu::PrimitiveTopology::LineList,
+                reverse_z: camera.reverse_z(),
+                depth_test,
+                ..Default::default()
+            }],
+        );
+        lines
+            .batcher
+            .draw(&mut render_pass, lines.batch, runs, &camera.bind_group);
+    }
+}
+
+// a camera's clear colour quad, uploaded and ready to go, for draw_camera().
+struct Clear<'a> {
+    batcher: &'a batch::Batcher,
+    batch: &'a batch::Batch<'a>,
+    run: usize,
+    camera_bind_group: &'a wgpu::BindGroup, // the ui camera's, which the quad is laid out for.
+}
+
+// covers the whole of the ui camera's view, so whatever the target is.
+fn clear_quad(window: ui::Rect, colour: wgpu::Color) -> [model::ModelVertex; 4] {
+    let colour = [colour.r, colour.g, colour.b, colour.a].map(|channel| channel as f32);
+    [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0), (1.0, 1.0)].map(|(x, y)| model::ModelVertex {
+        position: [x * window.width, y * window.height, 0.0],
+        colour,
+        ..Default::default()
+    })
+}
+
+// the viewport in whole pixels, rounded outwards.
+fn viewport_scissor(
+    viewport: camera::Viewport,
+    (width, height): (u32, u32),
+) -> (u32, u32, u32, u32) {
+    let (x, y, w, h) = viewport.rect(width, height);
+    let left = (x.floor() as u32).min(width);
+    let top = (y.floor() as u32).min(height);
+    let right = ((x + w).ceil() as u32).min(width);
+    let bottom = ((y + h).ceil() as u32).min(height);
+    (left, top, right - left, bottom - top)
+}
+
+// debug_draw's lines, uploaded and ready to go, for draw_camera(). the runs before `split` are
+// the ones that get depth tested.
+struct Lines<'a> {
+    batcher: &'a batch::Batcher,
+    batch: &'a batch::Batch<'a>,
+    split: usize,
 }
 
 // つづ: deprecate this if no use is found for it.
 pub async fn run() {}
+
+// a clip rectangle in logical pixels as whole physical ones on the window, or None if there's
+// nothing left of it.
+fn scissor(
+    clip: Option<ui::Rect>,
+    scale_factor: f64,
+    (width, height): (u32, u32),
+) -> Option<(u32, u32, u32, u32)> {
+    let Some(clip) = clip else {
+        return Some((0, 0, width, height));
+    };
+    let scale_factor = scale_factor as f32;
+    let left = ((clip.x * scale_factor).floor().max(0.0) as u32).min(width);
+    let top = ((clip.y * scale_factor).floor().max(0.0) as u32).min(height);
+    let right = (((clip.x + clip.width) * scale_factor).ceil().max(0.0) as u32).min(width);
+    let bottom = (((clip.y + clip.height) * scale_factor).ceil().max(0.0) as u32).min(height);
+    (right > left && bottom > top).then_some((left, top, right - left, bottom - top))
+}
+
+// the ui camera sits at the default eye looking down -z, so this puts (0, 0) in the top left
+// corner and (width, height) in the bottom right, in logical pixels.
+fn ui_bounds((width, height): (u32, u32), scale_factor: f64) -> camera::Projection {
+    camera::Projection::OrthographicBounds {
+        left: 0.0,
+        right: (width as f64 / scale_factor) as f32,
+        bottom: (height as f64 / scale_factor) as f32,
+        top: 0.0,
+    }
+}
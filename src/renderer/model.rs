@@ -1,63 +1,447 @@
+use std::sync::Arc;
+
+use super::camera;
 use super::object;
+use super::pipeline;
 use super::texture;
 
-// つづ: should this be public? in fact, should there even be a trait? having a const variable with
-// the VBL would work... ideally, this file should match with object.rs in terms of structure.
-//
-// i noticed this descriptor is used in the render pipeline, but the trait should still be able to
-// be removed.
-
-pub trait Vertex {
-    fn desc() -> wgpu::VertexBufferLayout<'static>;
-}
-
 #[repr(C)]
 #[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct ModelVertex {
     pub position: [f32; 3],
     pub tex_coords: [f32; 2],
+    pub normal: [f32; 3],
+    pub tangent: [f32; 4], // w holds the handedness of the bitangent.
+    pub colour: [f32; 4],
+    pub tex_coords_1: [f32; 2],
+}
+
+// missing attributes fall back to values that leave the shader output unchanged, so a mesh
+// without vertex colours is simply white and one without normals faces +z until they get
+// generated. the shader uses the same ones for attributes a mesh doesn't upload.
+impl Default for ModelVertex {
+    fn default() -> Self {
+        Self {
+            position: [0.0; 3],
+            tex_coords: [0.0; 2],
+            normal: [0.0, 0.0, 1.0],
+            tangent: [1.0, 0.0, 0.0, 1.0],
+            colour: [1.0; 4],
+            tex_coords_1: [0.0; 2],
+        }
+    }
+}
+
+impl ModelVertex {
+    // in the same order as ATTRIBUTES.
+    fn fields(&self) -> [&[u8]; 6] {
+        [
+            bytemuck::bytes_of(&self.position),
+            bytemuck::bytes_of(&self.tex_coords),
+            bytemuck::bytes_of(&self.normal),
+            bytemuck::bytes_of(&self.tangent),
+            bytemuck::bytes_of(&self.colour),
+            bytemuck::bytes_of(&self.tex_coords_1),
+        ]
+    }
+}
+
+// the same attributes squeezed down to 28 bytes instead of 72, along the lines of what
+// KHR_mesh_quantization allows: half floats for positions and uvs, normalised bytes for
+// directions and colours. the shader sees floats either way, so it doesn't need to know.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct QuantizedVertex {
+    pub position: [u16; 4], // w is padding; there's no Float16x3.
+    pub tex_coords: [u16; 2],
+    pub normal: [i8; 4],
+    pub tangent: [i8; 4],
+    pub colour: [u8; 4],
+    pub tex_coords_1: [u16; 2],
+}
+
+impl QuantizedVertex {
+    fn fields(&self) -> [&[u8]; 6] {
+        [
+            bytemuck::bytes_of(&self.position),
+            bytemuck::bytes_of(&self.tex_coords),
+            bytemuck::bytes_of(&self.normal),
+            bytemuck::bytes_of(&self.tangent),
+            bytemuck::bytes_of(&self.colour),
+            bytemuck::bytes_of(&self.tex_coords_1),
+        ]
+    }
+}
+
+impl From<&ModelVertex> for QuantizedVertex {
+    fn from(vertex: &ModelVertex) -> Self {
+        let half = |x: f32| half::f16::from_f32(x).to_bits();
+        let snorm = |x: f32| (x.clamp(-1.0, 1.0) * 127.0).round() as i8;
+        let unorm = |x: f32| (x.clamp(0.0, 1.0) * 255.0).round() as u8;
+
+        let [x, y, z] = vertex.position;
+        Self {
+            position: [half(x), half(y), half(z), half(1.0)],
+            tex_coords: vertex.tex_coords.map(half),
+            normal: [vertex.normal[0], vertex.normal[1], vertex.normal[2], 0.0].map(snorm),
+            tangent: vertex.tangent.map(snorm),
+            colour: vertex.colour.map(unorm),
+            tex_coords_1: vertex.tex_coords_1.map(half),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum VertexKind {
+    Full,
+    Quantized,
 }
 
-impl Vertex for ModelVertex {
-    fn desc() -> wgpu::VertexBufferLayout<'static> {
+// which attributes a mesh actually has, once the missing normals and tangents have been
+// generated. only those get uploaded, and the mesh gets a layout and a shader to match (see
+// pipeline::Key), so a mesh made of positions and uvs doesn't drag 40 bytes of defaults around
+// per vertex.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct VertexAttributes(u8);
+
+impl VertexAttributes {
+    pub const POSITION: Self = Self(1 << 0);
+    pub const TEX_COORDS: Self = Self(1 << 1);
+    pub const NORMAL: Self = Self(1 << 2);
+    pub const TANGENT: Self = Self(1 << 3);
+    pub const COLOUR: Self = Self(1 << 4);
+    pub const TEX_COORDS_1: Self = Self(1 << 5);
+    // laid out exactly like ModelVertex, which is what the ui, sprites and debug lines upload.
+    pub const ALL: Self = Self((1 << 6) - 1);
+
+    pub fn contains(&self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn insert(&mut self, other: Self) {
+        self.0 |= other.0;
+    }
+
+    fn present(self) -> impl Iterator<Item = (usize, &'static Attribute)> {
+        ATTRIBUTES
+            .iter()
+            .enumerate()
+            .filter(move |(_, attribute)| self.contains(attribute.flag))
+    }
+
+    pub fn layout(&self, kind: VertexKind) -> VertexLayout {
+        let mut offset = 0;
+        let attributes = self
+            .present()
+            .map(|(_, attribute)| {
+                let format = attribute.format(kind);
+                let vertex_attribute = wgpu::VertexAttribute {
+                    offset,
+                    shader_location: attribute.location,
+                    format,
+                };
+                offset += format.size();
+                vertex_attribute
+            })
+            .collect();
+        VertexLayout {
+            stride: offset,
+            attributes,
+        }
+    }
+
+    // the vertices as they go into the vertex buffer: only these attributes, one after another.
+    pub fn pack(&self, kind: VertexKind, vertices: &[ModelVertex]) -> Vec<u8> {
+        let stride = self.layout(kind).stride as usize;
+        let mut data = Vec::with_capacity(vertices.len() * stride);
+        for vertex in vertices {
+            let quantized;
+            let fields = match kind {
+                VertexKind::Full => vertex.fields(),
+                VertexKind::Quantized => {
+                    quantized = QuantizedVertex::from(vertex);
+                    quantized.fields()
+                }
+            };
+            for (i, _) in self.present() {
+                data.extend_from_slice(fields[i]);
+            }
+        }
+        data
+    }
+
+    // the VertexInput struct shader.wgsl gets for meshes with these attributes, along with a
+    // vertex_<name>() function for each attribute that gives back the mesh's value, or the
+    // default one when the mesh doesn't have it.
+    pub fn shader_input(&self) -> String {
+        let mut input = String::from("struct VertexInput {\n");
+        for (_, attribute) in self.present() {
+            input += &format!(
+                "\t@location({}) {}: {},\n",
+                attribute.location, attribute.name, attribute.shader_type
+            );
+        }
+        input += "}\n";
+        for attribute in &ATTRIBUTES {
+            let value = match self.contains(attribute.flag) {
+                true => format!("model.{}", attribute.name),
+                false => attribute.default.to_string(),
+            };
+            input += &format!(
+                "fn vertex_{}(model: VertexInput) -> {} {{ return {}; }}\n",
+                attribute.name, attribute.shader_type, value
+            );
+        }
+        input
+    }
+}
+
+impl std::ops::BitOr for VertexAttributes {
+    type Output = Self;
+
+    fn bitor(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+}
+
+struct Attribute {
+    flag: VertexAttributes,
+    location: u32,
+    name: &'static str,
+    shader_type: &'static str,
+    default: &'static str,            // matches ModelVertex::default().
+    formats: [wgpu::VertexFormat; 2], // full and quantized.
+}
+
+impl Attribute {
+    fn format(&self, kind: VertexKind) -> wgpu::VertexFormat {
+        match kind {
+            VertexKind::Full => self.formats[0],
+            VertexKind::Quantized => self.formats[1],
+        }
+    }
+}
+
+// in the order they're packed in. 5 to 8 are taken by the instance matrix.
+const ATTRIBUTES: [Attribute; 6] = [
+    Attribute {
+        flag: VertexAttributes::POSITION,
+        location: 0,
+        name: "position",
+        shader_type: "vec3<f32>",
+        default: "vec3<f32>(0.0)",
+        formats: [wgpu::VertexFormat::Float32x3, wgpu::VertexFormat::Float16x4],
+    },
+    Attribute {
+        flag: VertexAttributes::TEX_COORDS,
+        location: 1,
+        name: "tex_coords",
+        shader_type: "vec2<f32>",
+        default: "vec2<f32>(0.0)",
+        formats: [wgpu::VertexFormat::Float32x2, wgpu::VertexFormat::Float16x2],
+    },
+    Attribute {
+        flag: VertexAttributes::NORMAL,
+        location: 2,
+        name: "normal",
+        shader_type: "vec3<f32>",
+        default: "vec3<f32>(0.0, 0.0, 1.0)",
+        formats: [wgpu::VertexFormat::Float32x3, wgpu::VertexFormat::Snorm8x4],
+    },
+    Attribute {
+        flag: VertexAttributes::TANGENT,
+        location: 3,
+        name: "tangent",
+        shader_type: "vec4<f32>",
+        default: "vec4<f32>(1.0, 0.0, 0.0, 1.0)",
+        formats: [wgpu::VertexFormat::Float32x4, wgpu::VertexFormat::Snorm8x4],
+    },
+    Attribute {
+        flag: VertexAttributes::COLOUR,
+        location: 4,
+        name: "colour",
+        shader_type: "vec4<f32>",
+        default: "vec4<f32>(1.0)",
+        formats: [wgpu::VertexFormat::Float32x4, wgpu::VertexFormat::Unorm8x4],
+    },
+    Attribute {
+        flag: VertexAttributes::TEX_COORDS_1,
+        location: 9,
+        name: "tex_coords_1",
+        shader_type: "vec2<f32>",
+        default: "vec2<f32>(0.0)",
+        formats: [wgpu::VertexFormat::Float32x2, wgpu::VertexFormat::Float16x2],
+    },
+];
+
+// what VertexAttributes::layout() works out; desc() borrows from it for the pipeline.
+pub struct VertexLayout {
+    pub stride: wgpu::BufferAddress,
+    attributes: Vec<wgpu::VertexAttribute>,
+}
+
+impl VertexLayout {
+    pub fn desc(&self) -> wgpu::VertexBufferLayout<'_> {
         wgpu::VertexBufferLayout {
-            array_stride: std::mem::size_of::<ModelVertex>() as wgpu::BufferAddress,
+            array_stride: self.stride,
             step_mode: wgpu::VertexStepMode::Vertex,
-            attributes: &[
-                wgpu::VertexAttribute {
-                    offset: 0,
-                    shader_location: 0,
-                    format: wgpu::VertexFormat::Float32x3,
-                },
-                wgpu::VertexAttribute {
-                    offset: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
-                    shader_location: 1,
-                    format: wgpu::VertexFormat::Float32x2,
-                },
-            ],
+            attributes: &self.attributes,
         }
     }
 }
 
 #[derive(Debug)]
 pub struct Mesh {
-    pub label: String,
     pub vertex_buffer: wgpu::Buffer,
     pub index_buffer: wgpu::Buffer,
     pub num_elements: u32,
     pub material: usize, // you can't index with u32 (trust me, i've tried).
+    pub attributes: VertexAttributes,
+    pub topology: wgpu::PrimitiveTopology,
+    pub vertex_kind: VertexKind,
+    pub index_format: wgpu::IndexFormat,
+}
+
+impl Mesh {
+    pub fn pipeline_key(&self) -> pipeline::Key {
+        pipeline::Key {
+            topology: self.topology,
+            attributes: self.attributes,
+            vertex_kind: self.vertex_kind,
+            strip_index_format: match self.topology {
+                wgpu::PrimitiveTopology::LineStrip | wgpu::PrimitiveTopology::TriangleStrip => {
+                    Some(self.index_format)
+                }
+                _ => None,
+            },
+            ..Default::default()
+        }
+    }
 }
 
 #[derive(Debug)]
 pub struct Material {
-    pub label: String,
     pub texture: texture::Texture,
     pub bind_group: wgpu::BindGroup,
 }
 
 pub struct Model {
     pub meshes: Vec<Mesh>,
-    pub materials: Vec<Material>,
+    // shared between the levels of detail made from the same file.
+    pub materials: Arc<Vec<Material>>,
+    pub bounds: Bounds,
+    // a copy of every triangle kept around for picking, since the buffers can't be read back.
+    // lines and points don't have any, so picking falls back to the bounds for them.
+    pub triangles: Vec<[cgmath::Point3<f32>; 3]>,
+}
+
+impl Model {
+    // how far along the ray (in multiples of its direction, which doesn't have to be normalized)
+    // it first hits the model, if at all. both are in the model's own space.
+    pub fn raycast(
+        &self,
+        origin: cgmath::Point3<f32>,
+        direction: cgmath::Vector3<f32>,
+    ) -> Option<f32> {
+        let bounds = self.bounds.raycast(origin, direction)?;
+        if self.triangles.is_empty() {
+            return Some(bounds);
+        }
+        self.triangles
+            .iter()
+            .filter_map(|triangle| raycast_triangle(triangle, origin, direction))
+            .min_by(f32::total_cmp)
+    }
+}
+
+// möller-trumbore; both sides count, since culling is the renderer's business.
+fn raycast_triangle(
+    [a, b, c]: &[cgmath::Point3<f32>; 3],
+    origin: cgmath::Point3<f32>,
+    direction: cgmath::Vector3<f32>,
+) -> Option<f32> {
+    use cgmath::InnerSpace;
+    let ab = b - a;
+    let ac = c - a;
+    let p = direction.cross(ac);
+    let determinant = ab.dot(p);
+    if determinant.abs() < f32::EPSILON {
+        return None; // the ray runs along the triangle.
+    }
+    let to_origin = origin - a;
+    let u = to_origin.dot(p) / determinant;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+    let q = to_origin.cross(ab);
+    let v = direction.dot(q) / determinant;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+    let distance = ac.dot(q) / determinant;
+    (distance >= 0.0).then_some(distance)
+}
+
+// axis-aligned, in the model's own space.
+#[derive(Copy, Clone, Debug)]
+pub struct Bounds {
+    pub min: cgmath::Point3<f32>,
+    pub max: cgmath::Point3<f32>,
+}
+
+// inverted so that extending it with anything at all makes it valid.
+impl Default for Bounds {
+    fn default() -> Self {
+        Self {
+            min: cgmath::Point3::new(f32::MAX, f32::MAX, f32::MAX),
+            max: cgmath::Point3::new(f32::MIN, f32::MIN, f32::MIN),
+        }
+    }
+}
+
+impl Bounds {
+    pub fn extend<I: IntoIterator<Item = [f32; 3]>>(&mut self, positions: I) {
+        for [x, y, z] in positions {
+            self.min = cgmath::Point3::new(self.min.x.min(x), self.min.y.min(y), self.min.z.min(z));
+            self.max = cgmath::Point3::new(self.max.x.max(x), self.max.y.max(y), self.max.z.max(z));
+        }
+    }
+
+    pub fn center(&self) -> cgmath::Point3<f32> {
+        cgmath::EuclideanSpace::midpoint(self.min, self.max)
+    }
+
+    // of the sphere around the box, not inside it.
+    pub fn radius(&self) -> f32 {
+        use cgmath::MetricSpace;
+        self.min.distance(self.max) * 0.5
+    }
+
+    // same idea as Model::raycast(). starting inside the box counts as hitting it straight away.
+    pub fn raycast(
+        &self,
+        origin: cgmath::Point3<f32>,
+        direction: cgmath::Vector3<f32>,
+    ) -> Option<f32> {
+        let (mut near, mut far) = (0.0f32, f32::INFINITY);
+        for axis in 0..3 {
+            // parallel to this pair of faces, so it's either between them the whole way or never.
+            // dividing by zero would mostly sort itself out, but not with the origin right on a
+            // face, where it makes a nan.
+            if direction[axis] == 0.0 {
+                if origin[axis] < self.min[axis] || origin[axis] > self.max[axis] {
+                    return None;
+                }
+                continue;
+            }
+            let inverse = 1.0 / direction[axis];
+            let a = (self.min[axis] - origin[axis]) * inverse;
+            let b = (self.max[axis] - origin[axis]) * inverse;
+            near = near.max(a.min(b));
+            far = far.min(a.max(b));
+        }
+        (near <= far).then_some(near)
+    }
 }
 
 // temporarily deprecated while i figure out what the hell i should do.
@@ -166,7 +550,8 @@ pub trait DrawObject<'a> {
     fn draw_object_instanced(
         &mut self,
         object: &'a object::Object,
-        camera_bind_group: &'a wgpu::BindGroup,
+        camera: &'a camera::Camera,
+        pipelines: &'a pipeline::Pipelines,
     );
 }
 
@@ -177,18 +562,33 @@ where
     fn draw_object_instanced(
         &mut self,
         object: &'b object::Object,
-        camera_bind_group: &'b wgpu::BindGroup,
+        camera: &'b camera::Camera,
+        pipelines: &'b pipeline::Pipelines,
     ) {
         self.set_vertex_buffer(1, object.instance_buffer.slice(..));
-        self.set_bind_group(1, camera_bind_group, &[]);
-        self.set_bind_group(2, &object.bind_group, &[]);
-
-        for mesh in &object.model.meshes {
-            let material = &object.model.materials[mesh.material];
-            self.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
-            self.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
-            self.set_bind_group(0, &material.bind_group, &[]);
-            self.draw_indexed(0..mesh.num_elements, 0, 0..object.instances.len() as u32);
+        self.set_bind_group(1, &camera.bind_group, &[]);
+
+        // while cross-fading, the outgoing level is drawn too, dithered the other way round.
+        let levels = std::iter::once((object.model(), &object.bind_group)).chain(
+            object
+                .previous_lod
+                .map(|previous| (&object.lods[previous].model, &object.fading_bind_group)),
+        );
+
+        for (model, bind_group) in levels {
+            self.set_bind_group(2, bind_group, &[]);
+            for mesh in &model.meshes {
+                let material = &model.materials[mesh.material];
+                let key = pipeline::Key {
+                    reverse_z: camera.reverse_z(),
+                    ..mesh.pipeline_key()
+                };
+                self.set_pipeline(&pipelines[&key]);
+                self.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+                self.set_index_buffer(mesh.index_buffer.slice(..), mesh.index_format);
+                self.set_bind_group(0, &material.bind_group, &[]);
+                self.draw_indexed(0..mesh.num_elements, 0, 0..object.instances.len() as u32);
+            }
         }
     }
 
@@ -200,10 +600,115 @@ where
         transformation_bind_group: &'b wgpu::BindGroup,
     ) {
         self.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
-        self.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        self.set_index_buffer(mesh.index_buffer.slice(..), mesh.index_format);
         self.set_bind_group(0, &material.bind_group, &[]);
         self.set_bind_group(1, camera_bind_group, &[]);
         self.set_bind_group(2, transformation_bind_group, &[]);
         self.draw_indexed(0..mesh.num_elements, 0, 0..1);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{Point3, Vector3};
+
+    use super::*;
+
+    // flat on z = 0, facing +z.
+    const TRIANGLE: [Point3<f32>; 3] = [
+        Point3::new(0.0, 0.0, 0.0),
+        Point3::new(1.0, 0.0, 0.0),
+        Point3::new(0.0, 1.0, 0.0),
+    ];
+
+    fn unit_box() -> Bounds {
+        let mut bounds = Bounds::default();
+        bounds.extend([[-1.0, -1.0, -1.0], [1.0, 1.0, 1.0]]);
+        bounds
+    }
+
+    #[test]
+    fn triangles() {
+        let origin = Point3::new(0.25, 0.25, 2.0);
+        assert_eq!(
+            raycast_triangle(&TRIANGLE, origin, Vector3::new(0.0, 0.0, -1.0)),
+            Some(2.0)
+        );
+        // from behind counts too, and the direction doesn't have to be normalized.
+        assert_eq!(
+            raycast_triangle(
+                &TRIANGLE,
+                Point3::new(0.25, 0.25, -1.0),
+                Vector3::new(0.0, 0.0, 4.0)
+            ),
+            Some(0.25)
+        );
+        // past the long edge.
+        assert_eq!(
+            raycast_triangle(
+                &TRIANGLE,
+                Point3::new(0.75, 0.75, 2.0),
+                Vector3::new(0.0, 0.0, -1.0)
+            ),
+            None
+        );
+        // going away from it.
+        assert_eq!(
+            raycast_triangle(&TRIANGLE, origin, Vector3::new(0.0, 0.0, 1.0)),
+            None
+        );
+        // along it, in its own plane.
+        assert_eq!(
+            raycast_triangle(
+                &TRIANGLE,
+                Point3::new(-1.0, 0.25, 0.0),
+                Vector3::new(1.0, 0.0, 0.0)
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn boxes() {
+        let bounds = unit_box();
+        let down = Vector3::new(0.0, 0.0, -1.0);
+        assert_eq!(bounds.raycast(Point3::new(0.5, 0.5, 3.0), down), Some(2.0));
+        assert_eq!(bounds.raycast(Point3::new(1.5, 0.5, 3.0), down), None);
+        assert_eq!(
+            bounds.raycast(Point3::new(0.5, 0.5, 3.0), Vector3::new(0.0, 0.0, 1.0)),
+            None
+        );
+        // diagonally, into a corner.
+        let distance = bounds
+            .raycast(Point3::new(-3.0, -3.0, -3.0), Vector3::new(1.0, 1.0, 1.0))
+            .unwrap();
+        assert!((distance - 2.0).abs() < 1e-6, "{}", distance);
+    }
+
+    #[test]
+    fn boxes_from_inside() {
+        let bounds = unit_box();
+        assert_eq!(
+            bounds.raycast(Point3::new(0.0, 0.0, 0.0), Vector3::new(0.3, -0.2, 1.0)),
+            Some(0.0)
+        );
+        // and from right on a face, whichever way.
+        assert_eq!(
+            bounds.raycast(Point3::new(1.0, 0.0, 0.0), Vector3::new(0.0, 0.0, 1.0)),
+            Some(0.0)
+        );
+    }
+
+    // parallel to a pair of faces, the ray's either between them the whole way or never.
+    #[test]
+    fn boxes_side_on() {
+        let bounds = unit_box();
+        let across = Vector3::new(1.0, 0.0, 0.0);
+        assert_eq!(
+            bounds.raycast(Point3::new(-5.0, 0.5, 0.5), across),
+            Some(4.0)
+        );
+        assert_eq!(bounds.raycast(Point3::new(-5.0, 1.5, 0.5), across), None);
+        assert_eq!(bounds.raycast(Point3::new(-5.0, 0.5, -1.5), across), None);
+    }
+}
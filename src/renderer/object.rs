@@ -1,94 +1,239 @@
 use std::mem::size_of;
 
 use cgmath::Matrix4;
-use cgmath::SquareMatrix;
+use cgmath::{Matrix, SquareMatrix};
 use wgpu::util::DeviceExt;
 
+use super::camera;
 use super::instance;
 use super::model;
 
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct TransformationUniform {
+    pub matrix: [[f32; 4]; 4],
+    // the inverse transpose of matrix's upper 3x3, for normals; see normal_matrix(). each column
+    // is padded out to four floats, the way wgsl lays out a mat3x3.
+    pub normal_matrix: [[f32; 4]; 3],
+    // x is a dither threshold and y says which side of it gets drawn (1.0 for below, 0.0 for
+    // above), so two levels of detail can be faded across each other without overlapping.
+    // [1.0, 1.0] draws everything.
+    pub lod_fade: [f32; 4],
+    // multiplies whatever gets drawn.
+    pub tint: [f32; 4],
+}
+
+impl Default for TransformationUniform {
+    fn default() -> Self {
+        Self {
+            matrix: Matrix4::identity().into(),
+            normal_matrix: normal_matrix(Matrix4::identity()),
+            lod_fade: [1.0, 1.0, 0.0, 0.0],
+            tint: [1.0; 4],
+        }
+    }
+}
+
+pub struct Lod {
+    pub model: model::Model,
+    // the smallest share of the screen's height the object can take up and still use this
+    // level. the last level should have 0.0 so there's always something to draw.
+    pub screen_size: f32,
+}
+
 pub struct Object {
     pub label: String,
-    pub model: model::Model,
+    pub lods: Vec<Lod>, // most detailed first.
+    pub lod: usize,
+    // how long switching levels takes, in seconds. None makes them pop.
+    pub lod_fade_time: Option<f32>,
+    pub previous_lod: Option<usize>,
+    pub lod_fade: f32,
     pub position: cgmath::Point3<f32>,
     pub rotation: cgmath::Quaternion<f32>,
     pub scale: (f32, f32, f32),
+    pub layers: u32, // see camera::Camera::layers.
+    // the transform as of the last tick; frames in between blend from it.
+    previous_position: cgmath::Point3<f32>,
+    previous_rotation: cgmath::Quaternion<f32>,
+    previous_scale: (f32, f32, f32),
     pub instances: Vec<instance::Instance>,
     // there would be an instance count here, but vectors have their own length field.
     pub instance_buffer: wgpu::Buffer,
-    pub transformation_uniform: [[f32; 4]; 4],
+    pub transformation_uniform: TransformationUniform,
     pub transformation_buffer: wgpu::Buffer,
     pub bind_group: wgpu::BindGroup,
+    // same matrix, other side of the dither. used for the level that's being faded out.
+    pub fading_bind_group: wgpu::BindGroup,
+    fading_offset: wgpu::BufferAddress,
 }
 
-impl Object {
-    pub fn new(
-        label: String,
-        device: &wgpu::Device,
-        model: model::Model,
-        position: Option<cgmath::Point3<f32>>,
-        rotation: Option<cgmath::Quaternion<f32>>,
-        scale: Option<(f32, f32, f32)>,
-        instances: Option<Vec<instance::Instance>>,
-    ) -> Self {
-        // this code is here to show how not accounting for collect() can break everything.
-        // this code would also be useless since it wouldn't store the sole instance in the
-        // instances variable if none was provided...
-        /*let instance_data = if let Some(taste_buds_last_around_ten_days) = instances {
-            taste_buds_last_around_ten_days
-        } else {
-            vec![instance::Instance {
-                position: (0.0, 0.0, 0.0).into(),
-                rotation: (0.0, 0.0, 0.0, 1.0).into(),
-            }]
-        }.iter().map(instance::Instance::to_raw).collect();*/
+pub struct ObjectBuilder {
+    label: String,
+    model: model::Model,
+    position: cgmath::Point3<f32>,
+    rotation: cgmath::Quaternion<f32>,
+    scale: (f32, f32, f32),
+    layers: u32,
+    instances: Vec<instance::Instance>,
+    lods: Vec<(model::Model, f32)>,
+    lod_fade_time: Option<f32>,
+}
 
-        let instances = if let Some(taste_buds_last_around_ten_days) = instances {
-            taste_buds_last_around_ten_days
-        } else {
-            vec![instance::Instance {
-                position: (0.0, 0.0, 0.0).into(),
-                rotation: (0.0, 0.0, 0.0, 1.0).into(),
-            }]
-        };
+impl ObjectBuilder {
+    pub fn position<P: Into<cgmath::Point3<f32>>>(mut self, position: P) -> Self {
+        self.position = position.into();
+        self
+    }
 
-        let instance_data: Vec<instance::InstanceRaw> =
-            instances.iter().map(instance::Instance::to_raw).collect();
+    pub fn rotation<R: Into<cgmath::Quaternion<f32>>>(mut self, rotation: R) -> Self {
+        self.rotation = rotation.into();
+        self
+    }
+
+    pub fn scale(mut self, scale: (f32, f32, f32)) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    pub fn layers(mut self, layers: u32) -> Self {
+        self.layers = layers;
+        self
+    }
+
+    pub fn instances(mut self, instances: Vec<instance::Instance>) -> Self {
+        self.instances = instances;
+        self
+    }
+
+    // switches to `model` once the object takes up less than `screen_size` of the screen's
+    // height. levels have to be added from most to least detailed.
+    pub fn lod(mut self, model: model::Model, screen_size: f32) -> Self {
+        self.lods.push((model, screen_size));
+        self
+    }
+
+    pub fn lod_fade_time(mut self, seconds: f32) -> Self {
+        self.lod_fade_time = Some(seconds);
+        self
+    }
+
+    pub fn build(self, device: &wgpu::Device) -> Result<Object, String> {
+        let label = self.label;
+        let (x, y, z) = self.scale;
+        if [x, y, z]
+            .iter()
+            .any(|axis| *axis == 0.0 || !axis.is_finite())
+        {
+            return Err(format!("{}: scale has to be finite and non-zero", label));
+        }
+        // there would be nothing to draw, and wgpu doesn't like empty buffers anyway.
+        if self.instances.is_empty() {
+            return Err(format!("{}: objects need at least one instance", label));
+        }
+        if self.lods.windows(2).any(|pair| pair[0].1 <= pair[1].1) {
+            return Err(format!("{}: lods have to get smaller on screen", label));
+        }
+        if let Some(fade_time) = self.lod_fade_time {
+            if fade_time <= 0.0 {
+                return Err(format!("{}: lod fade time has to be positive", label));
+            }
+        }
+
+        let instance_data: Vec<instance::InstanceRaw> = self
+            .instances
+            .iter()
+            .map(instance::Instance::to_raw)
+            .collect();
         let instance_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some(&format!("{} instance buffer", &label)),
             contents: bytemuck::cast_slice(&instance_data),
             usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
         });
 
-        let transformation_uniform = Matrix4::identity().into();
+        let transformation_uniform = TransformationUniform::default();
+
+        // both uniforms live in the same buffer; the second one has to start on an offset the
+        // device is happy binding from.
+        let uniform_size = size_of::<TransformationUniform>() as wgpu::BufferAddress;
+        let alignment = device.limits().min_uniform_buffer_offset_alignment as wgpu::BufferAddress;
+        let fading_offset = uniform_size.div_ceil(alignment) * alignment;
 
         let transformation_buffer = device.create_buffer(&wgpu::BufferDescriptor {
             label: Some(&format!("{} object buffer", label)),
-            size: size_of::<[[f32; 4]; 4]>() as u64, // windows xp version canned. ) :
+            size: fading_offset + uniform_size, // windows xp version canned. ) :
             usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
             mapped_at_creation: false, //つづ: look into this and why it crashes when true.
         });
 
-        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some(&format!("{} bind group", &label)),
-            layout: &Object::layout(&device),
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: transformation_buffer.as_entire_binding(),
-            }],
+        let [bind_group, fading_bind_group] = [0, fading_offset].map(|offset| {
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some(&format!("{} bind group", &label)),
+                layout: &Object::layout(device),
+                entries: &[wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                        buffer: &transformation_buffer,
+                        offset,
+                        size: wgpu::BufferSize::new(uniform_size),
+                    }),
+                }],
+            })
         });
 
-        Self {
+        // each level is used down to the size where the next one takes over.
+        let thresholds: Vec<f32> = self
+            .lods
+            .iter()
+            .map(|(_, screen_size)| *screen_size)
+            .collect();
+        let lods = std::iter::once(self.model)
+            .chain(self.lods.into_iter().map(|(model, _)| model))
+            .zip(thresholds.into_iter().chain(std::iter::once(0.0)))
+            .map(|(model, screen_size)| Lod { model, screen_size })
+            .collect();
+
+        Ok(Object {
             label,
-            model,
-            position: position.unwrap_or((0.0, 0.0, 0.0).into()),
-            rotation: rotation.unwrap_or((0.0, 0.0, 0.0, 1.0).into()),
-            scale: scale.unwrap_or((1.0, 1.0, 1.0)),
-            instances,
+            lods,
+            lod: 0,
+            lod_fade_time: self.lod_fade_time,
+            previous_lod: None,
+            lod_fade: 1.0,
+            position: self.position,
+            rotation: self.rotation,
+            scale: self.scale,
+            layers: self.layers,
+            previous_position: self.position,
+            previous_rotation: self.rotation,
+            previous_scale: self.scale,
+            instances: self.instances,
             instance_buffer,
             transformation_uniform,
             transformation_buffer,
             bind_group,
+            fading_bind_group,
+            fading_offset,
+        })
+    }
+}
+
+impl Object {
+    pub fn builder<L: Into<String>>(label: L, model: model::Model) -> ObjectBuilder {
+        ObjectBuilder {
+            label: label.into(),
+            model,
+            position: (0.0, 0.0, 0.0).into(),
+            rotation: (0.0, 0.0, 0.0, 1.0).into(),
+            scale: (1.0, 1.0, 1.0),
+            layers: 1,
+            // a single instance sitting right on the object.
+            instances: vec![instance::Instance {
+                position: (0.0, 0.0, 0.0).into(),
+                rotation: (0.0, 0.0, 0.0, 1.0).into(),
+            }],
+            lods: Vec::new(),
+            lod_fade_time: None,
         }
     }
 
@@ -98,7 +243,7 @@ impl Object {
             label: Some("object bind group layout"),
             entries: &[wgpu::BindGroupLayoutEntry {
                 binding: 0,
-                visibility: wgpu::ShaderStages::VERTEX,
+                visibility: wgpu::ShaderStages::VERTEX_FRAGMENT, // the fragment stage dithers.
                 ty: wgpu::BindingType::Buffer {
                     ty: wgpu::BufferBindingType::Uniform,
                     has_dynamic_offset: false,
@@ -109,17 +254,153 @@ impl Object {
         })
     }
 
-    pub fn update(&mut self, queue: &wgpu::Queue) {
-        use cgmath::EuclideanSpace;
-        self.transformation_uniform = (cgmath::Matrix4::from_translation(self.position.to_vec())
-            * cgmath::Matrix4::from(self.rotation)
-            * cgmath::Matrix4::from_nonuniform_scale(self.scale.0, self.scale.1, self.scale.2))
-        .into();
+    pub fn model(&self) -> &model::Model {
+        &self.lods[self.lod].model
+    }
+
+    // as of the last tick.
+    pub fn matrix(&self) -> Matrix4<f32> {
+        transformation(self.position, self.rotation, self.scale)
+    }
+
+    // for teleporting: without it, the frames until the next tick would show the object sliding
+    // over from wherever it was.
+    pub fn snap(&mut self) {
+        self.previous_position = self.position;
+        self.previous_rotation = self.rotation;
+        self.previous_scale = self.scale;
+    }
+
+    fn blended_matrix(&self, blend: f32) -> Matrix4<f32> {
+        let (from, to) = (self.previous_scale, self.scale);
+        transformation(
+            self.previous_position + (self.position - self.previous_position) * blend,
+            self.previous_rotation.slerp(self.rotation, blend),
+            (
+                from.0 + (to.0 - from.0) * blend,
+                from.1 + (to.1 - from.1) * blend,
+                from.2 + (to.2 - from.2) * blend,
+            ),
+        )
+    }
+
+    // the instance the ray hits first and how far along the ray it is. goes by the level of
+    // detail being drawn, so what gets picked is what's on screen.
+    pub fn raycast(&self, ray: &camera::Ray) -> Option<(usize, f32)> {
+        use cgmath::{SquareMatrix, Transform};
+        let matrix = self.matrix();
+        self.instances
+            .iter()
+            .enumerate()
+            .filter_map(|(i, instance)| {
+                // into the model's own space. the direction isn't normalized again afterwards, so
+                // distances along it still match the world's.
+                let inverse = (matrix * instance.matrix()).invert()?;
+                let distance = self.model().raycast(
+                    inverse.transform_point(ray.origin),
+                    inverse.transform_vector(ray.direction),
+                )?;
+                Some((i, distance))
+            })
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+    }
+
+    // picks the level of detail for how big the object is on the screen of whichever camera sees
+    // it biggest, and moves any cross-fade along by `delta` seconds. instances are ignored;
+    // they're assumed to be close enough to the object for its own size to stand in for theirs.
+    pub fn select_lod(&mut self, cameras: &[camera::Camera], delta: f32) {
+        let bounds = &self.model().bounds;
+        let center =
+            cgmath::Point3::from_homogeneous(self.matrix() * bounds.center().to_homogeneous());
+        let scale = self
+            .scale
+            .0
+            .abs()
+            .max(self.scale.1.abs())
+            .max(self.scale.2.abs());
+        let screen_size = cameras
+            .iter()
+            .filter(|camera| camera.sees(self.layers))
+            .map(|camera| camera.screen_size(center, bounds.radius() * scale))
+            .fold(0.0, f32::max);
+
+        let lod = self
+            .lods
+            .iter()
+            .position(|lod| screen_size >= lod.screen_size)
+            .unwrap_or(self.lods.len() - 1);
+
+        if lod != self.lod {
+            if self.lod_fade_time.is_some() {
+                self.previous_lod = Some(self.lod);
+                self.lod_fade = 0.0;
+            }
+            self.lod = lod;
+        }
+
+        if let (Some(_), Some(fade_time)) = (self.previous_lod, self.lod_fade_time) {
+            self.lod_fade += delta / fade_time;
+            if self.lod_fade >= 1.0 {
+                self.previous_lod = None;
+                self.lod_fade = 1.0;
+            }
+        }
+    }
+
+    // `blend` goes from 0.0 (where it was as of the last tick) to 1.0 (where it is now).
+    pub fn update(&mut self, queue: &wgpu::Queue, blend: f32) {
+        let fade = match self.previous_lod {
+            Some(_) => self.lod_fade,
+            None => 1.0,
+        };
+        let matrix = self.blended_matrix(blend);
+        self.transformation_uniform = TransformationUniform {
+            matrix: matrix.into(),
+            normal_matrix: normal_matrix(matrix),
+            lod_fade: [fade, 1.0, 0.0, 0.0],
+            ..self.transformation_uniform
+        };
+        let fading_uniform = TransformationUniform {
+            lod_fade: [fade, 0.0, 0.0, 0.0],
+            ..self.transformation_uniform
+        };
 
         queue.write_buffer(
             &self.transformation_buffer,
             0,
             bytemuck::cast_slice(&[self.transformation_uniform]),
         );
+        queue.write_buffer(
+            &self.transformation_buffer,
+            self.fading_offset,
+            bytemuck::cast_slice(&[fading_uniform]),
+        );
     }
 }
+
+fn transformation(
+    position: cgmath::Point3<f32>,
+    rotation: cgmath::Quaternion<f32>,
+    scale: (f32, f32, f32),
+) -> Matrix4<f32> {
+    use cgmath::EuclideanSpace;
+    cgmath::Matrix4::from_translation(position.to_vec())
+        * cgmath::Matrix4::from(rotation)
+        * cgmath::Matrix4::from_nonuniform_scale(scale.0, scale.1, scale.2)
+}
+
+// scaling an object unevenly leaves its normals leaning over unless they go through the inverse
+// transpose instead. a scale of zero has no inverse, but then there's no surface left to light
+// either, so anything will do.
+fn normal_matrix(matrix: Matrix4<f32>) -> [[f32; 4]; 3] {
+    let upper = cgmath::Matrix3::from_cols(
+        matrix.x.truncate(),
+        matrix.y.truncate(),
+        matrix.z.truncate(),
+    );
+    let normal = upper
+        .invert()
+        .unwrap_or(cgmath::Matrix3::identity())
+        .transpose();
+    [normal.x, normal.y, normal.z].map(|column| column.extend(0.0).into())
+}
@@ -0,0 +1,138 @@
+use std::collections::HashMap;
+
+use super::{instance, model, texture};
+
+// everything a mesh can change about how it has to be drawn. there's one pipeline per
+// combination, made the first time something needs it (see Pipelines::prepare()).
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Key {
+    pub topology: wgpu::PrimitiveTopology,
+    pub attributes: model::VertexAttributes,
+    pub vertex_kind: model::VertexKind,
+    // strips need to know what their index buffers hold, since the biggest index there is means
+    // "start a new strip". None for everything else.
+    pub strip_index_format: Option<wgpu::IndexFormat>,
+    // follows the camera rather than the mesh; see camera::Camera::reverse_z().
+    pub reverse_z: bool,
+    // false draws over everything in the depth buffer and leaves it alone, like the ui does.
+    pub depth_test: bool,
+}
+
+impl Default for Key {
+    fn default() -> Self {
+        Self {
+            topology: wgpu::PrimitiveTopology::TriangleList,
+            attributes: model::VertexAttributes::ALL,
+            vertex_kind: model::VertexKind::Full,
+            strip_index_format: None,
+            reverse_z: false,
+            depth_test: true,
+        }
+    }
+}
+
+// every combination up front would be well over a thousand pipelines, most of which nothing
+// ever uses, so they're made as meshes turn up instead. State::render() prepares everything it's
+// about to draw before any pass starts, which is the only place they can be made anyway.
+pub struct Pipelines {
+    layout: wgpu::PipelineLayout,
+    format: wgpu::TextureFormat,
+    // one per set of vertex attributes; see model::VertexAttributes::shader_input().
+    shaders: HashMap<model::VertexAttributes, wgpu::ShaderModule>,
+    pipelines: HashMap<Key, wgpu::RenderPipeline>,
+}
+
+impl Pipelines {
+    pub fn new(layout: wgpu::PipelineLayout, format: wgpu::TextureFormat) -> Self {
+        Self {
+            layout,
+            format,
+            shaders: HashMap::new(),
+            pipelines: HashMap::new(),
+        }
+    }
+
+    pub fn prepare(&mut self, device: &wgpu::Device, key: Key) {
+        if self.pipelines.contains_key(&key) {
+            return;
+        }
+        let shader = self.shaders.entry(key.attributes).or_insert_with(|| {
+            let source = key.attributes.shader_input() + include_str!("shader.wgsl");
+            device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some(&format!("{:?} shader", key.attributes)),
+                source: wgpu::ShaderSource::Wgsl(source.into()),
+            })
+        });
+        let pipeline = create(device, &self.layout, shader, self.format, key);
+        self.pipelines.insert(key, pipeline);
+    }
+}
+
+// panics if the pipeline hasn't been prepared.
+impl std::ops::Index<&Key> for Pipelines {
+    type Output = wgpu::RenderPipeline;
+
+    fn index(&self, key: &Key) -> &wgpu::RenderPipeline {
+        &self.pipelines[key]
+    }
+}
+
+fn create(
+    device: &wgpu::Device,
+    layout: &wgpu::PipelineLayout,
+    shader: &wgpu::ShaderModule,
+    format: wgpu::TextureFormat,
+    key: Key,
+) -> wgpu::RenderPipeline {
+    let vertices = key.attributes.layout(key.vertex_kind);
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(&format!("{:?} render pipeline", key)),
+        layout: Some(layout),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: "vs_main",
+            buffers: &[vertices.desc(), instance::InstanceRaw::desc()],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: "fs_main",
+            targets: &[Some(wgpu::ColorTargetState {
+                format,
+                blend: Some(wgpu::BlendState::ALPHA_BLENDING), // no linear!
+                write_mask: wgpu::ColorWrites::ALL,
+            })],
+        }),
+        primitive: wgpu::PrimitiveState {
+            topology: key.topology,
+            strip_index_format: key.strip_index_format,
+            front_face: wgpu::FrontFace::Ccw,
+            // lines and points have no back to them.
+            cull_mode: match key.topology {
+                wgpu::PrimitiveTopology::TriangleList | wgpu::PrimitiveTopology::TriangleStrip => {
+                    Some(wgpu::Face::Back)
+                }
+                _ => None,
+            },
+            polygon_mode: wgpu::PolygonMode::Fill,
+            unclipped_depth: false,
+            conservative: false,
+        },
+        depth_stencil: Some(wgpu::DepthStencilState {
+            format: texture::Texture::DEPTH_FORMAT,
+            depth_write_enabled: key.depth_test,
+            depth_compare: match (key.depth_test, key.reverse_z) {
+                (false, _) => wgpu::CompareFunction::Always,
+                (true, true) => wgpu::CompareFunction::GreaterEqual,
+                (true, false) => wgpu::CompareFunction::LessEqual,
+            },
+            stencil: wgpu::StencilState::default(),
+            bias: wgpu::DepthBiasState::default(),
+        }),
+        multisample: wgpu::MultisampleState {
+            count: 1,
+            mask: !0,
+            alpha_to_coverage_enabled: false,
+        },
+        multiview: None,
+    })
+}
@@ -1,8 +1,31 @@
+use std::sync::Arc;
+
 use wgpu::util::DeviceExt;
 
+use cgmath::{ElementWise, EuclideanSpace};
+
 use super::model;
+use super::object;
 use super::texture;
 
+pub struct LoadOptions {
+    // store vertices as half floats and normalised bytes. saves well over half the memory, at
+    // the cost of precision on big or far-from-the-origin meshes.
+    pub quantize: bool,
+    // reorder triangles and vertices for the gpu's vertex cache and to cut down on overdraw.
+    // only affects triangle lists.
+    pub optimize: bool,
+}
+
+impl Default for LoadOptions {
+    fn default() -> Self {
+        Self {
+            quantize: false,
+            optimize: true,
+        }
+    }
+}
+
 pub fn load_model_bytes(
     label: &str,
     data: &[u8],
@@ -10,100 +33,571 @@ pub fn load_model_bytes(
     queue: &wgpu::Queue,
     layout: &wgpu::BindGroupLayout,
 ) -> Result<model::Model, String> {
+    load_model_bytes_with_options(label, data, device, queue, layout, &LoadOptions::default())
+}
+
+pub fn load_model_bytes_with_options(
+    label: &str,
+    data: &[u8],
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    layout: &wgpu::BindGroupLayout,
+    options: &LoadOptions,
+) -> Result<model::Model, String> {
+    let mut models = load_model_lods_bytes(label, data, device, queue, layout, options, &[1.0])?;
+    Ok(models.remove(0))
+}
+
+// loads the file once and makes one model per entry in `ratios`, each simplified down to roughly
+// that fraction of its triangles (1.0 keeps the mesh as is). the models share their materials,
+// so the textures are only uploaded once. lines and points are never simplified.
+pub fn load_model_lods_bytes(
+    label: &str,
+    data: &[u8],
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    layout: &wgpu::BindGroupLayout,
+    options: &LoadOptions,
+    ratios: &[f32],
+) -> Result<Vec<model::Model>, String> {
+    let (_, primitives, materials) = import(data, device, queue, layout)?;
+    let primitives: Vec<&Primitive> = primitives.iter().collect();
+    Ok(ratios
+        .iter()
+        .map(|&ratio| build_model(label, &primitives, &materials, ratio, options, device))
+        .collect())
+}
+
+// makes one object per node in the file's default scene that has a mesh, placed where the node
+// is. the builders can still be tweaked before they're built.
+pub fn load_scene_bytes(
+    label: &str,
+    data: &[u8],
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    layout: &wgpu::BindGroupLayout,
+    options: &LoadOptions,
+) -> Result<Vec<object::ObjectBuilder>, String> {
+    let (document, primitives, materials) = import(data, device, queue, layout)?;
+    let scene = document
+        .default_scene()
+        .or_else(|| document.scenes().next())
+        .ok_or(format!("{}: there are no scenes", label))?;
+
+    let mut builders = Vec::new();
+    let root = NodeTransform {
+        position: cgmath::Vector3::new(0.0, 0.0, 0.0),
+        rotation: cgmath::Quaternion::new(1.0, 0.0, 0.0, 0.0),
+        scale: cgmath::Vector3::new(1.0, 1.0, 1.0),
+    };
+    let mut stack: Vec<(gltf::Node, NodeTransform)> =
+        scene.nodes().map(|node| (node, root)).collect();
+    while let Some((node, parent)) = stack.pop() {
+        let transform = parent.then(node.transform());
+        stack.extend(node.children().map(|child| (child, transform)));
+
+        let Some(mesh) = node.mesh() else {
+            continue;
+        };
+        let node_label = match node.name() {
+            Some(name) => format!("{} {}", label, name),
+            None => format!("{} node {}", label, node.index()),
+        };
+        let mesh_primitives: Vec<&Primitive> = primitives
+            .iter()
+            .filter(|primitive| primitive.mesh == mesh.index())
+            .collect();
+        let model = build_model(
+            &node_label,
+            &mesh_primitives,
+            &materials,
+            1.0,
+            options,
+            device,
+        );
+
+        builders.push(
+            object::Object::builder(node_label, model)
+                .position(cgmath::Point3::from_vec(transform.position))
+                .rotation(transform.rotation)
+                .scale(transform.scale.into()),
+        );
+    }
+
+    Ok(builders)
+}
+
+// kept as separate parts instead of a matrix since objects want them that way. nodes whose
+// parents are scaled unevenly *and* rotated can't be represented exactly; they come out without
+// the shear.
+#[derive(Copy, Clone)]
+struct NodeTransform {
+    position: cgmath::Vector3<f32>,
+    rotation: cgmath::Quaternion<f32>,
+    scale: cgmath::Vector3<f32>,
+}
+
+impl NodeTransform {
+    fn then(&self, local: gltf::scene::Transform) -> Self {
+        let (position, rotation, scale) = local.decomposed();
+        let [x, y, z, w] = rotation;
+        let position = cgmath::Vector3::from(position);
+        Self {
+            position: self.position + self.rotation * self.scale.mul_element_wise(position),
+            rotation: self.rotation * cgmath::Quaternion::new(w, x, y, z),
+            scale: self.scale.mul_element_wise(cgmath::Vector3::from(scale)),
+        }
+    }
+}
+
+type Imported = (gltf::Document, Vec<Primitive>, Arc<Vec<model::Material>>);
+
+fn import(
+    data: &[u8],
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    layout: &wgpu::BindGroupLayout,
+) -> Result<Imported, String> {
     // i'm on my own now...
-    let (document, buffers, images) = gltf::import_slice(data).unwrap();
+    let (document, buffers, images) = gltf::import_slice(data).map_err(|e| e.to_string())?;
     let mut materials = Vec::new();
     for material in document.materials() {
-        let buffer_index = material
-            .pbr_metallic_roughness()
-            .base_color_texture()
-            .unwrap()
-            .texture()
-            .source()
-            .index();
-        let dimensions = (images[buffer_index].width, images[buffer_index].height);
-
         let label = material.name().unwrap_or("Unnamed material").to_string();
 
-        let texture = texture::Texture::from_raw_data(
-            Some(&format!("{} texture", label)),
-            device,
-            queue,
-            &images[buffer_index].pixels,
-            dimensions,
-        )
+        // untextured materials (which is what gizmos and point clouds tend to come with) get a
+        // white pixel so they still go through the same pipeline.
+        let texture = match material.pbr_metallic_roughness().base_color_texture() {
+            Some(info) => {
+                let image = &images[info.texture().source().index()];
+                texture::Texture::from_raw_data(
+                    Some(&format!("{} texture", label)),
+                    device,
+                    queue,
+                    &image.pixels,
+                    (image.width, image.height),
+                )
+            }
+            None => white_texture(&label, device, queue),
+        }
         .unwrap();
 
-        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some(&format!("{} bind group", label)),
-            layout, // つづ: fetch layout instead of relying on function arguments.
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&texture.view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&texture.sampler),
-                },
-            ],
-        });
-
-        materials.push(model::Material {
-            label,
-            texture,
-            bind_group,
-        });
+        materials.push(create_material(label, texture, device, layout));
     }
+    // only made if some primitive doesn't reference a material.
+    let mut default_material = None;
 
-    let mut meshes = Vec::new();
+    let mut primitives = Vec::new();
     for mesh in document.meshes() {
         for primitive in mesh.primitives() {
             let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
 
+            let mut attributes = model::VertexAttributes::POSITION;
             let mut vertices = Vec::new();
             if let Some(attribute) = reader.read_positions() {
                 for position in attribute {
                     vertices.push(model::ModelVertex {
                         position,
-                        tex_coords: Default::default(),
+                        ..Default::default()
                     });
                 }
             }
             if let Some(attribute) = reader.read_tex_coords(0).map(|x| x.into_f32()) {
-                let mut field_iterator = 0;
-                for coords in attribute {
-                    vertices[field_iterator].tex_coords = coords;
-                    field_iterator += 1;
+                attributes.insert(model::VertexAttributes::TEX_COORDS);
+                for (vertex, coords) in vertices.iter_mut().zip(attribute) {
+                    vertex.tex_coords = coords;
+                }
+            }
+            if let Some(attribute) = reader.read_normals() {
+                attributes.insert(model::VertexAttributes::NORMAL);
+                for (vertex, normal) in vertices.iter_mut().zip(attribute) {
+                    vertex.normal = normal;
+                }
+            }
+            if let Some(attribute) = reader.read_tangents() {
+                attributes.insert(model::VertexAttributes::TANGENT);
+                for (vertex, tangent) in vertices.iter_mut().zip(attribute) {
+                    vertex.tangent = tangent;
+                }
+            }
+            if let Some(attribute) = reader.read_colors(0).map(|x| x.into_rgba_f32()) {
+                attributes.insert(model::VertexAttributes::COLOUR);
+                for (vertex, colour) in vertices.iter_mut().zip(attribute) {
+                    vertex.colour = colour;
+                }
+            }
+            if let Some(attribute) = reader.read_tex_coords(1).map(|x| x.into_f32()) {
+                attributes.insert(model::VertexAttributes::TEX_COORDS_1);
+                for (vertex, coords) in vertices.iter_mut().zip(attribute) {
+                    vertex.tex_coords_1 = coords;
                 }
             }
 
-            let mut indices = Vec::new();
-            if let Some(raw_indices) = reader.read_indices() {
-                indices.append(&mut raw_indices.into_u32().collect::<Vec<u32>>());
+            // non-indexed primitives just get their vertices numbered in order, so everything
+            // further down only has to deal with one kind of draw.
+            let indices = match reader.read_indices() {
+                Some(raw_indices) => raw_indices.into_u32().collect::<Vec<u32>>(),
+                None => (0..vertices.len() as u32).collect(),
+            };
+            let (topology, indices) = convert_mode(primitive.mode(), indices);
+
+            // normals and tangents only make sense for surfaces.
+            if let Some(triangles) = triangle_list(topology, &indices) {
+                // the spec says flat normals should be used when they're missing, but that would
+                // mean splitting every vertex; smooth ones are close enough and keep the index
+                // buffer.
+                if !attributes.contains(model::VertexAttributes::NORMAL) {
+                    generate_normals(&mut vertices, &triangles);
+                    attributes.insert(model::VertexAttributes::NORMAL);
+                }
+                // mikktspace needs both normals and uvs to work with. without uvs there's no
+                // meaningful tangent space anyway, so the default one is kept.
+                if !attributes.contains(model::VertexAttributes::TANGENT)
+                    && attributes.contains(model::VertexAttributes::TEX_COORDS)
+                {
+                    generate_tangents(&mut vertices, &triangles);
+                    attributes.insert(model::VertexAttributes::TANGENT);
+                }
             }
 
-            let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some(&format!("{} vertex buffer", label)),
-                contents: bytemuck::cast_slice(&vertices),
-                usage: wgpu::BufferUsages::VERTEX,
-            });
+            let material = match primitive.material().index() {
+                Some(index) => index,
+                None => *default_material.get_or_insert_with(|| {
+                    let texture = white_texture("default material", device, queue).unwrap();
+                    materials.push(create_material(
+                        "default material".to_string(),
+                        texture,
+                        device,
+                        layout,
+                    ));
+                    materials.len() - 1
+                }),
+            };
 
-            let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some(&format!("{} index buffer", label)),
-                contents: bytemuck::cast_slice(&indices),
-                usage: wgpu::BufferUsages::INDEX,
+            primitives.push(Primitive {
+                mesh: mesh.index(),
+                vertices,
+                indices,
+                topology,
+                attributes,
+                material,
             });
+        }
+    }
+    let materials = Arc::new(materials);
 
-            meshes.push(model::Mesh {
-                label: label.to_string(),
-                vertex_buffer,
-                index_buffer,
-                num_elements: indices.len() as u32,
-                material: primitive.material().index().unwrap(),
-            });
+    Ok((document, primitives, materials))
+}
+
+fn build_model(
+    label: &str,
+    primitives: &[&Primitive],
+    materials: &Arc<Vec<model::Material>>,
+    ratio: f32,
+    options: &LoadOptions,
+    device: &wgpu::Device,
+) -> model::Model {
+    let mut meshes = Vec::new();
+    let mut bounds = model::Bounds::default();
+    let mut triangles = Vec::new();
+    for primitive in primitives {
+        let Primitive {
+            vertices,
+            indices,
+            topology,
+            attributes,
+            material,
+            ..
+        } = primitive;
+        let (vertices, indices) = match topology {
+            wgpu::PrimitiveTopology::TriangleList if ratio < 1.0 => {
+                simplify(vertices, indices, ratio)
+            }
+            _ => (vertices.clone(), indices.clone()),
+        };
+
+        let (vertices, indices) = match topology {
+            wgpu::PrimitiveTopology::TriangleList if options.optimize => {
+                optimize(vertices, indices)
+            }
+            _ => (vertices, indices),
+        };
+
+        let vertex_kind = match options.quantize {
+            true => model::VertexKind::Quantized,
+            false => model::VertexKind::Full,
+        };
+        let vertex_data = attributes.pack(vertex_kind, &vertices);
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&format!("{} vertex buffer", label)),
+            contents: &vertex_data,
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+
+        // half the size whenever the mesh is small enough to get away with it, which is
+        // most of them. strips can't use the last index, since that restarts the strip.
+        let u16_vertices = match topology {
+            wgpu::PrimitiveTopology::LineStrip | wgpu::PrimitiveTopology::TriangleStrip => {
+                u16::MAX as usize
+            }
+            _ => u16::MAX as usize + 1,
+        };
+        let (index_format, index_data) = if vertices.len() <= u16_vertices {
+            let indices: Vec<u16> = indices.iter().map(|&i| i as u16).collect();
+            (
+                wgpu::IndexFormat::Uint16,
+                bytemuck::cast_slice(&indices).to_vec(),
+            )
+        } else {
+            (
+                wgpu::IndexFormat::Uint32,
+                bytemuck::cast_slice(&indices).to_vec(),
+            )
+        };
+        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&format!("{} index buffer", label)),
+            contents: &index_data,
+            usage: wgpu::BufferUsages::INDEX,
+        });
+
+        meshes.push(model::Mesh {
+            vertex_buffer,
+            index_buffer,
+            num_elements: indices.len() as u32,
+            material: *material,
+            attributes: *attributes,
+            topology: *topology,
+            vertex_kind,
+            index_format,
+        });
+        bounds.extend(vertices.iter().map(|vertex| vertex.position));
+        if let Some(indices) = triangle_list(*topology, &indices) {
+            triangles.extend(indices.chunks_exact(3).map(|triangle| {
+                [0, 1, 2].map(|corner| {
+                    cgmath::Point3::from(vertices[triangle[corner] as usize].position)
+                })
+            }));
         }
     }
 
-    return Ok(model::Model { meshes, materials });
+    model::Model {
+        meshes,
+        materials: materials.clone(),
+        bounds,
+        triangles,
+    }
+}
+
+// what a glTF primitive turns into before anything gets uploaded.
+struct Primitive {
+    mesh: usize,
+    vertices: Vec<model::ModelVertex>,
+    indices: Vec<u32>,
+    topology: wgpu::PrimitiveTopology,
+    attributes: model::VertexAttributes,
+    material: usize,
+}
+
+fn white_texture(
+    label: &str,
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+) -> anyhow::Result<texture::Texture> {
+    texture::Texture::from_raw_data(
+        Some(&format!("{} texture", label)),
+        device,
+        queue,
+        &[255; 4],
+        (1, 1),
+    )
+}
+
+// also how a camera's render target gets onto a model: swap it into the model's materials.
+pub fn create_material(
+    label: String,
+    texture: texture::Texture,
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+) -> model::Material {
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some(&format!("{} bind group", label)),
+        layout, // つづ: fetch layout instead of relying on function arguments.
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(&texture.view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(&texture.sampler),
+            },
+        ],
+    });
+
+    model::Material {
+        texture,
+        bind_group,
+    }
+}
+
+// wgpu has no loops or fans, so those get rewritten into something it does have.
+fn convert_mode(mode: gltf::mesh::Mode, indices: Vec<u32>) -> (wgpu::PrimitiveTopology, Vec<u32>) {
+    use gltf::mesh::Mode;
+    use wgpu::PrimitiveTopology;
+
+    match mode {
+        Mode::Points => (PrimitiveTopology::PointList, indices),
+        Mode::Lines => (PrimitiveTopology::LineList, indices),
+        Mode::LineStrip => (PrimitiveTopology::LineStrip, indices),
+        Mode::LineLoop => {
+            let mut indices = indices;
+            if let Some(&first) = indices.first() {
+                indices.push(first);
+            }
+            (PrimitiveTopology::LineStrip, indices)
+        }
+        Mode::Triangles => (PrimitiveTopology::TriangleList, indices),
+        Mode::TriangleStrip => (PrimitiveTopology::TriangleStrip, indices),
+        Mode::TriangleFan => {
+            let fanned = indices
+                .windows(2)
+                .skip(1)
+                .flat_map(|edge| [indices[0], edge[0], edge[1]])
+                .collect();
+            (PrimitiveTopology::TriangleList, fanned)
+        }
+    }
+}
+
+// unrolls strips so the normal and tangent generation only ever sees separate triangles.
+fn triangle_list(topology: wgpu::PrimitiveTopology, indices: &[u32]) -> Option<Vec<u32>> {
+    match topology {
+        wgpu::PrimitiveTopology::TriangleList => Some(indices.to_vec()),
+        // every other triangle is wound the other way round.
+        wgpu::PrimitiveTopology::TriangleStrip => Some(
+            indices
+                .windows(3)
+                .enumerate()
+                .flat_map(|(i, t)| match i % 2 {
+                    0 => [t[0], t[1], t[2]],
+                    _ => [t[1], t[0], t[2]],
+                })
+                .collect(),
+        ),
+        _ => None,
+    }
+}
+
+// the simplified index buffer still points into the full vertex buffer; the unused vertices are
+// only dropped if the mesh gets optimised afterwards.
+fn simplify(
+    vertices: &[model::ModelVertex],
+    indices: &[u32],
+    ratio: f32,
+) -> (Vec<model::ModelVertex>, Vec<u32>) {
+    let adapter = meshopt::VertexDataAdapter::new(
+        bytemuck::cast_slice(vertices),
+        std::mem::size_of::<model::ModelVertex>(),
+        0,
+    )
+    .unwrap();
+    let target_count = (indices.len() as f32 * ratio) as usize / 3 * 3;
+    // the error is relative to the mesh's size; this lets it go as far as it has to to reach the
+    // target, since a level that isn't any smaller is pointless.
+    let indices = meshopt::simplify(
+        indices,
+        &adapter,
+        target_count,
+        1.0,
+        meshopt::SimplifyOptions::None,
+        None,
+    );
+    (vertices.to_vec(), indices)
+}
+
+// the usual meshoptimizer order: vertex cache first, then overdraw (which is allowed to undo a
+// little of the former), then the vertices get sorted to match the order they're fetched in.
+fn optimize(
+    vertices: Vec<model::ModelVertex>,
+    indices: Vec<u32>,
+) -> (Vec<model::ModelVertex>, Vec<u32>) {
+    let mut indices = meshopt::optimize_vertex_cache(&indices, vertices.len());
+
+    let adapter = meshopt::VertexDataAdapter::new(
+        bytemuck::cast_slice(&vertices),
+        std::mem::size_of::<model::ModelVertex>(),
+        0,
+    )
+    .unwrap();
+    meshopt::optimize_overdraw_in_place(&mut indices, &adapter, 1.05);
+
+    let vertices = meshopt::optimize_vertex_fetch(&mut indices, &vertices);
+    (vertices, indices)
+}
+
+// area-weighted: the cross product of two edges is twice the triangle's area, so bigger faces
+// pull the normal harder without any extra work.
+fn generate_normals(vertices: &mut [model::ModelVertex], indices: &[u32]) {
+    use cgmath::{InnerSpace, Vector3};
+
+    let mut normals = vec![Vector3::new(0.0, 0.0, 0.0); vertices.len()];
+    for triangle in indices.chunks_exact(3) {
+        let [a, b, c] = [0, 1, 2].map(|i| Vector3::from(vertices[triangle[i] as usize].position));
+        let face_normal = (b - a).cross(c - a);
+        for &index in triangle {
+            normals[index as usize] += face_normal;
+        }
+    }
+
+    for (vertex, normal) in vertices.iter_mut().zip(normals) {
+        if normal.magnitude2() > 0.0 {
+            vertex.normal = normal.normalize().into();
+        }
+    }
+}
+
+struct TangentGeometry<'a> {
+    vertices: &'a mut [model::ModelVertex],
+    indices: &'a [u32],
+}
+
+impl TangentGeometry<'_> {
+    fn vertex(&self, face: usize, vert: usize) -> &model::ModelVertex {
+        &self.vertices[self.indices[face * 3 + vert] as usize]
+    }
+}
+
+impl mikktspace::Geometry for TangentGeometry<'_> {
+    fn num_faces(&self) -> usize {
+        self.indices.len() / 3
+    }
+
+    fn num_vertices_of_face(&self, _face: usize) -> usize {
+        3
+    }
+
+    fn position(&self, face: usize, vert: usize) -> [f32; 3] {
+        self.vertex(face, vert).position
+    }
+
+    fn normal(&self, face: usize, vert: usize) -> [f32; 3] {
+        self.vertex(face, vert).normal
+    }
+
+    fn tex_coord(&self, face: usize, vert: usize) -> [f32; 2] {
+        self.vertex(face, vert).tex_coords
+    }
+
+    // vertices shared between faces get whichever tangent is written last. this only matters on
+    // uv seams, which exporters already split.
+    fn set_tangent_encoded(&mut self, tangent: [f32; 4], face: usize, vert: usize) {
+        let index = self.indices[face * 3 + vert] as usize;
+        self.vertices[index].tangent = tangent;
+    }
+}
+
+fn generate_tangents(vertices: &mut [model::ModelVertex], indices: &[u32]) {
+    let mut geometry = TangentGeometry { vertices, indices };
+    if !mikktspace::generate_tangents(&mut geometry) {
+        log::warn!("couldn't generate tangents; keeping the default ones");
+    }
 }
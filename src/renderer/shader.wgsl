@@ -8,6 +8,9 @@ var<uniform> camera: CameraUniform;
 
 struct TransformationUniform {
 	matrix: mat4x4<f32>,
+	normal_matrix: mat3x3<f32>,
+	lod_fade: vec4<f32>,
+	tint: vec4<f32>,
 };
 @group(2) @binding(0)
 var<uniform> transformation: TransformationUniform;
@@ -19,15 +22,16 @@ struct InstanceInput {
 	@location(8) model_matrix_3: vec4<f32>,
 }
 
-struct VertexInput {
-	@location(0) position: vec3<f32>,
-	//@location(1) colour: vec3<f32>,
-	@location(1) tex_coords: vec2<f32>,
-}
+// VertexInput, and the vertex_*() functions that read it, go in front of this file; they depend
+// on which attributes the mesh has. see model::VertexAttributes::shader_input().
 
 struct VertexOutput {
 	@builtin(position) clip_position: vec4<f32>,
 	@location(1) tex_coords: vec2<f32>,
+	@location(2) world_normal: vec3<f32>,
+	@location(3) world_tangent: vec4<f32>,
+	@location(4) colour: vec4<f32>,
+	@location(5) tex_coords_1: vec2<f32>,
 };
 
 @vertex
@@ -42,10 +46,27 @@ fn vs_main(
 		instance.model_matrix_3,
 	);
 
+	let world_matrix = transformation.matrix * model_matrix;
+	// instances only move and turn, so their own inverse transpose is the rotation itself.
+	let instance_rotation = mat3x3<f32>(
+		instance.model_matrix_0.xyz,
+		instance.model_matrix_1.xyz,
+		instance.model_matrix_2.xyz,
+	);
+	let world_normal = normalize(transformation.normal_matrix * instance_rotation * vertex_normal(model));
+	// tangents lie along the surface, so they stretch with it like positions do, then get
+	// straightened back out against the normal.
+	let tangent = vertex_tangent(model);
+	let stretched = (world_matrix * vec4<f32>(tangent.xyz, 0.0)).xyz;
+	let world_tangent = normalize(stretched - world_normal * dot(world_normal, stretched));
+
 	var out: VertexOutput;
-	//out.colour = model.colour;
-	out.tex_coords = model.tex_coords;	
-	out.clip_position = camera.view_proj * transformation.matrix * model_matrix * vec4<f32>(model.position, 1.0);
+	out.colour = vertex_colour(model);
+	out.tex_coords = vertex_tex_coords(model);
+	out.tex_coords_1 = vertex_tex_coords_1(model);
+	out.world_normal = world_normal;
+	out.world_tangent = vec4<f32>(world_tangent, tangent.w);
+	out.clip_position = camera.view_proj * world_matrix * vec4<f32>(vertex_position(model), 1.0);
 	return out;
 }
 
@@ -56,8 +77,29 @@ var t_diffuse: texture_2d<f32>;
 @group(0) @binding(1)
 var s_diffuse: sampler;
 
+// an ordered 4x4 bayer pattern, so a fade looks like a fine screen door instead of noise.
+fn dither_threshold(position: vec2<f32>) -> f32 {
+	var bayer = array<f32, 16>(
+		0.0, 8.0, 2.0, 10.0,
+		12.0, 4.0, 14.0, 6.0,
+		3.0, 11.0, 1.0, 9.0,
+		15.0, 7.0, 13.0, 5.0,
+	);
+	let index = (u32(position.y) % 4u) * 4u + u32(position.x) % 4u;
+	return (bayer[index] + 0.5) / 16.0;
+}
+
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-	return textureSample(t_diffuse, s_diffuse, in.tex_coords);
-	//return vec4<f32>(in.colour, 1.0);
+	// sampled before anything can be discarded; textureSample() has to stay in uniform control
+	// flow, and wgpu won't build the pipeline otherwise.
+	let texel = textureSample(t_diffuse, s_diffuse, in.tex_coords);
+
+	// see TransformationUniform in object.rs.
+	let below = dither_threshold(in.clip_position.xy) < transformation.lod_fade.x;
+	if below != (transformation.lod_fade.y > 0.5) {
+		discard;
+	}
+
+	return texel * in.colour * transformation.tint;
 }
@@ -0,0 +1,346 @@
+use std::sync::Arc;
+
+use super::{atlas, model, resource, texture, ui};
+
+// a texture cut up into frames. sprites share one through an Arc, and sprites on the same sheet
+// get drawn together.
+pub struct Sheet {
+    pub label: String,
+    pub material: model::Material,
+    frames: Vec<ui::Rect>, // in texture pixels.
+}
+
+impl Sheet {
+    // frames of the same size, packed into an atlas, left to right and then top to bottom, with
+    // `spacing` pixels between them and `margin` around the edge (the way tiled describes
+    // tilesets).
+    pub fn grid_in(
+        device: &wgpu::Device,
+        label: &str,
+        region: &atlas::Region,
+        (width, height): (u32, u32),
+        spacing: u32,
+        margin: u32,
+    ) -> Result<Arc<Self>, String> {
+        if width == 0 || height == 0 {
+            return Err(format!("{}: frames can't be empty", label));
+        }
+        let frames = grid(region.rect, (width, height), spacing, margin);
+        Self::from_frames(device, label, region.texture.clone(), frames)
+    }
+
+    // one frame per image, for animations that were drawn as separate files. they all have to
+    // have ended up on the same page.
+    pub fn from_regions(
+        device: &wgpu::Device,
+        label: &str,
+        regions: &[&atlas::Region],
+    ) -> Result<Arc<Self>, String> {
+        let Some(first) = regions.first() else {
+            return Err(format!("{}: a sheet needs at least one frame", label));
+        };
+        if regions.iter().any(|region| region.page != first.page) {
+            return Err(format!("{}: the frames are on different pages", label));
+        }
+        let frames = regions.iter().map(|region| region.rect).collect();
+        Self::from_frames(device, label, first.texture.clone(), frames)
+    }
+
+    // for frames that aren't in a grid, like ones that have been packed together.
+    pub fn from_frames(
+        device: &wgpu::Device,
+        label: &str,
+        texture: texture::Texture,
+        frames: Vec<ui::Rect>,
+    ) -> Result<Arc<Self>, String> {
+        if frames.is_empty() {
+            return Err(format!("{}: a sheet needs at least one frame", label));
+        }
+        let size = texture.texture.size();
+        let whole = ui::Rect {
+            width: size.width as f32,
+            height: size.height as f32,
+            ..Default::default()
+        };
+        if frames
+            .iter()
+            .any(|frame| whole.intersection(frame) != *frame)
+        {
+            return Err(format!("{}: frames have to be inside the texture", label));
+        }
+        let material = resource::create_material(
+            format!("{} material", label),
+            texture,
+            device,
+            &texture::Texture::layout(device),
+        );
+        Ok(Arc::new(Self {
+            label: label.to_string(),
+            material,
+            frames,
+        }))
+    }
+
+    pub fn frames(&self) -> usize {
+        self.frames.len()
+    }
+
+    // texture coordinates for the top left and bottom right corners.
+    fn uv(&self, frame: usize) -> ([f32; 2], [f32; 2]) {
+        let frame = self.frames[frame.min(self.frames.len() - 1)];
+        let size = self.material.texture.texture.size();
+        let (width, height) = (size.width as f32, size.height as f32);
+        (
+            [frame.x / width, frame.y / height],
+            [
+                (frame.x + frame.width) / width,
+                (frame.y + frame.height) / height,
+            ],
+        )
+    }
+}
+
+// frames of the same size inside `rect`, left to right and then top to bottom.
+fn grid(rect: ui::Rect, (width, height): (u32, u32), spacing: u32, margin: u32) -> Vec<ui::Rect> {
+    let fit = |length: f32, frame: u32| {
+        ((length as u32).saturating_sub(margin * 2) + spacing) / (frame + spacing)
+    };
+    let (columns, rows) = (fit(rect.width, width), fit(rect.height, height));
+    (0..rows)
+        .flat_map(|row| (0..columns).map(move |column| (column, row)))
+        .map(|(column, row)| ui::Rect {
+            x: rect.x + (margin + column * (width + spacing)) as f32,
+            y: rect.y + (margin + row * (height + spacing)) as f32,
+            width: width as f32,
+            height: height as f32,
+        })
+        .collect()
+}
+
+// frames of a sheet shown one after another.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Animation {
+    pub frames: Vec<usize>,
+    pub frame_time: f32, // seconds per frame.
+    pub looping: bool,   // otherwise it stops on the last frame.
+}
+
+// a picture in the 2d world; see camera::Camera2d. animations run on the frame's clock, like
+// lod fades, since they're only for show; game code that needs to know where one's up to should
+// count ticks itself.
+pub struct Sprite {
+    pub sheet: Arc<Sheet>,
+    pub frame: usize,
+    pub position: cgmath::Point2<f32>, // where the pivot goes, in world pixels.
+    pub size: (f32, f32),
+    pub pivot: ui::Anchor,
+    pub rotation: cgmath::Deg<f32>, // about the pivot, clockwise.
+    pub flip: (bool, bool),         // mirrors the frame across, then up and down.
+    pub tint: [f32; 4],
+    pub z: i32, // higher draws over lower, tilemap layers included.
+    pub visible: bool,
+    animation: Option<Animation>,
+    time: f32, // into the animation.
+    previous_position: cgmath::Point2<f32>,
+    previous_rotation: cgmath::Deg<f32>,
+    vertices: [model::ModelVertex; 4],
+    bounds: ui::Rect, // around the vertices, for culling.
+}
+
+pub struct SpriteBuilder {
+    label: String,
+    sheet: Arc<Sheet>,
+    frame: usize,
+    position: cgmath::Point2<f32>,
+    size: Option<(f32, f32)>,
+    pivot: ui::Anchor,
+    rotation: cgmath::Deg<f32>,
+    tint: [f32; 4],
+    z: i32,
+}
+
+impl SpriteBuilder {
+    pub fn frame(mut self, frame: usize) -> Self {
+        self.frame = frame;
+        self
+    }
+
+    pub fn position<P: Into<cgmath::Point2<f32>>>(mut self, position: P) -> Self {
+        self.position = position.into();
+        self
+    }
+
+    // the first frame's size by default.
+    pub fn size(mut self, width: f32, height: f32) -> Self {
+        self.size = Some((width, height));
+        self
+    }
+
+    pub fn pivot(mut self, pivot: ui::Anchor) -> Self {
+        self.pivot = pivot;
+        self
+    }
+
+    pub fn rotation<A: Into<cgmath::Deg<f32>>>(mut self, rotation: A) -> Self {
+        self.rotation = rotation.into();
+        self
+    }
+
+    pub fn tint(mut self, tint: [f32; 4]) -> Self {
+        self.tint = tint;
+        self
+    }
+
+    pub fn z(mut self, z: i32) -> Self {
+        self.z = z;
+        self
+    }
+
+    pub fn build(self) -> Result<Sprite, String> {
+        let label = self.label;
+        if self.frame >= self.sheet.frames() {
+            return Err(format!(
+                "{}: there's no frame {} in {}",
+                label, self.frame, self.sheet.label
+            ));
+        }
+        let first = self.sheet.frames[0];
+        let size = self.size.unwrap_or((first.width, first.height));
+        if !(size.0 >= 0.0 && size.1 >= 0.0 && size.0.is_finite() && size.1.is_finite()) {
+            return Err(format!("{}: size has to be finite and positive", label));
+        }
+        Ok(Sprite {
+            sheet: self.sheet,
+            frame: self.frame,
+            position: self.position,
+            size,
+            pivot: self.pivot,
+            rotation: self.rotation,
+            flip: (false, false),
+            tint: self.tint,
+            z: self.z,
+            visible: true,
+            animation: None,
+            time: 0.0,
+            previous_position: self.position,
+            previous_rotation: self.rotation,
+            vertices: [model::ModelVertex::default(); 4],
+            bounds: ui::Rect::default(),
+        })
+    }
+}
+
+impl Sprite {
+    pub fn builder<L: Into<String>>(label: L, sheet: Arc<Sheet>) -> SpriteBuilder {
+        SpriteBuilder {
+            label: label.into(),
+            sheet,
+            frame: 0,
+            position: (0.0, 0.0).into(),
+            size: None,
+            pivot: ui::Anchor::CENTRE,
+            rotation: cgmath::Deg(0.0),
+            tint: [1.0; 4],
+            z: 0,
+        }
+    }
+
+    // starts an animation from its first frame, unless it's the one that's already playing.
+    pub fn play(&mut self, animation: &Animation) {
+        if self.animation.as_ref() == Some(animation) {
+            return;
+        }
+        self.animation = Some(animation.clone());
+        self.time = 0.0;
+        if let Some(first) = animation.frames.first() {
+            self.frame = *first;
+        }
+    }
+
+    // leaves it on whatever frame it got to.
+    pub fn stop(&mut self) {
+        self.animation = None;
+    }
+
+    // false once an animation that doesn't loop has got to its last frame.
+    pub fn is_playing(&self) -> bool {
+        self.animation.as_ref().is_some_and(|animation| {
+            animation.looping || self.time < animation.frame_time * animation.frames.len() as f32
+        })
+    }
+
+    // same as object::Object::snap().
+    pub fn snap(&mut self) {
+        self.previous_position = self.position;
+        self.previous_rotation = self.rotation;
+    }
+
+    pub fn advance(&mut self, seconds: f32) {
+        let Some(animation) = &self.animation else {
+            return;
+        };
+        if animation.frames.is_empty() || animation.frame_time <= 0.0 {
+            return;
+        }
+        self.time += seconds;
+        let frame = (self.time / animation.frame_time) as usize;
+        let frame = match animation.looping {
+            true => frame % animation.frames.len(),
+            false => frame.min(animation.frames.len() - 1),
+        };
+        self.frame = animation.frames[frame];
+    }
+
+    pub fn update(&mut self, blend: f32) {
+        let position = self.previous_position + (self.position - self.previous_position) * blend;
+        let rotation = self.previous_rotation + (self.rotation - self.previous_rotation) * blend;
+        let (width, height) = self.size;
+        let (mut top_left, mut bottom_right) = self.sheet.uv(self.frame);
+        if self.flip.0 {
+            std::mem::swap(&mut top_left[0], &mut bottom_right[0]);
+        }
+        if self.flip.1 {
+            std::mem::swap(&mut top_left[1], &mut bottom_right[1]);
+        }
+
+        let (sin, cos) = cgmath::Rad::from(rotation).0.sin_cos();
+        let (min, max) = ((f32::MAX, f32::MAX), (f32::MIN, f32::MIN));
+        let mut bounds = (min, max);
+        // top left, bottom left, bottom right, top right, like ui elements.
+        let corners = [(0.0, 0.0), (0.0, 1.0), (1.0, 1.0), (1.0, 0.0)];
+        for (vertex, (x, y)) in self.vertices.iter_mut().zip(corners) {
+            let local = ((x - self.pivot.x) * width, (y - self.pivot.y) * height);
+            let turned = (
+                position.x + local.0 * cos - local.1 * sin,
+                position.y + local.0 * sin + local.1 * cos,
+            );
+            bounds.0 = (bounds.0 .0.min(turned.0), bounds.0 .1.min(turned.1));
+            bounds.1 = (bounds.1 .0.max(turned.0), bounds.1 .1.max(turned.1));
+            *vertex = model::ModelVertex {
+                position: [turned.0, turned.1, 0.0],
+                tex_coords: [
+                    [top_left[0], bottom_right[0]][x as usize],
+                    [top_left[1], bottom_right[1]][y as usize],
+                ],
+                colour: self.tint,
+                ..Default::default()
+            };
+        }
+        self.bounds = ui::Rect {
+            x: bounds.0 .0,
+            y: bounds.0 .1,
+            width: bounds.1 .0 - bounds.0 .0,
+            height: bounds.1 .1 - bounds.0 .1,
+        };
+    }
+
+    pub fn vertices(&self) -> &[model::ModelVertex] {
+        &self.vertices
+    }
+
+    pub fn bounds(&self) -> ui::Rect {
+        self.bounds
+    }
+}
+
+pub const INDICES: [u32; 6] = [0, 1, 2, 2, 3, 0];
@@ -0,0 +1,647 @@
+use std::collections::HashMap;
+
+use super::{model, resource, texture, ui};
+
+const ATLAS_SIZE: u32 = 1024;
+// empty space around each glyph, so linear filtering doesn't pick up bits of the neighbours.
+const PADDING: u32 = 1;
+
+pub type FontId = usize; // index into Fonts.
+
+// a ttf or otf font. shaping goes through rustybuzz and rasterizing through fontdue; both read
+// the same bytes, so glyph ids from one work with the other.
+pub struct Font {
+    data: Vec<u8>,
+    rasterizer: fontdue::Font,
+}
+
+impl Font {
+    // collections (ttc) only get their first face.
+    pub fn from_bytes<L: Into<String>>(label: L, data: Vec<u8>) -> Result<Self, String> {
+        let label = label.into();
+        if rustybuzz::Face::from_slice(&data, 0).is_none() {
+            return Err(format!("{}: couldn't read the font", label));
+        }
+        let rasterizer =
+            fontdue::Font::from_bytes(data.as_slice(), fontdue::FontSettings::default())
+                .map_err(|error| format!("{}: couldn't read the font: {}", label, error))?;
+        Ok(Self { data, rasterizer })
+    }
+
+    fn face(&self) -> rustybuzz::Face<'_> {
+        // already checked in from_bytes().
+        rustybuzz::Face::from_slice(&self.data, 0).unwrap()
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+struct GlyphKey {
+    font: FontId,
+    glyph: u16,
+    size: u32, // the bits of the f32, since f32s can't be hashed.
+}
+
+// where a glyph's bitmap is in the atlas and how it sits on the baseline, in pixels.
+#[derive(Copy, Clone)]
+struct Glyph {
+    rect: Option<(u32, u32, u32, u32)>, // None for glyphs with nothing to draw, like spaces.
+    left: i32,
+    bottom: i32, // from the baseline, going up.
+}
+
+// a row of glyphs in the atlas, filled in from the left.
+struct Shelf {
+    y: u32,
+    height: u32,
+    x: u32,
+}
+
+// every font, plus one atlas with every glyph any text has used so far, at every size it's been
+// used at. when the atlas fills up it's emptied and whatever's still being shown gets put back.
+pub struct Fonts {
+    fonts: Vec<Font>,
+    glyphs: HashMap<GlyphKey, Glyph>,
+    shelves: Vec<Shelf>,
+    // goes up every time the atlas is emptied; texts laid out before then have to be redone.
+    generation: u64,
+    pub material: model::Material,
+}
+
+impl Fonts {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let texture =
+            texture::Texture::create_blank(device, (ATLAS_SIZE, ATLAS_SIZE), "glyph atlas");
+        Self {
+            fonts: Vec::new(),
+            glyphs: HashMap::new(),
+            shelves: Vec::new(),
+            generation: 0,
+            material: resource::create_material(
+                "glyph atlas material".to_string(),
+                texture,
+                device,
+                &texture::Texture::layout(device),
+            ),
+        }
+    }
+
+    pub fn add(&mut self, font: Font) -> FontId {
+        self.fonts.push(font);
+        self.fonts.len() - 1
+    }
+
+    pub fn get(&self, font: FontId) -> Option<&Font> {
+        self.fonts.get(font)
+    }
+
+    // empties the atlas. every text gets laid out again the next time it's updated.
+    pub fn clear(&mut self) {
+        self.glyphs.clear();
+        self.shelves.clear();
+        self.generation += 1;
+    }
+
+    // rasterizes the glyph into the atlas the first time it's asked for. None if it doesn't fit.
+    fn glyph(&mut self, queue: &wgpu::Queue, key: GlyphKey) -> Option<Glyph> {
+        if let Some(glyph) = self.glyphs.get(&key) {
+            return Some(*glyph);
+        }
+        let (metrics, coverage) = self.fonts[key.font]
+            .rasterizer
+            .rasterize_indexed(key.glyph, f32::from_bits(key.size));
+        let (width, height) = (metrics.width as u32, metrics.height as u32);
+        let rect = match width == 0 || height == 0 {
+            true => None,
+            false => {
+                let (x, y) = self.allocate(width + PADDING * 2, height + PADDING * 2)?;
+                let (x, y) = (x + PADDING, y + PADDING);
+                // white, with the coverage as alpha; the text's colour gets multiplied in.
+                let pixels: Vec<u8> = coverage
+                    .iter()
+                    .flat_map(|alpha| [255, 255, 255, *alpha])
+                    .collect();
+                queue.write_texture(
+                    wgpu::ImageCopyTexture {
+                        texture: &self.material.texture.texture,
+                        mip_level: 0,
+                        origin: wgpu::Origin3d { x, y, z: 0 },
+                        aspect: wgpu::TextureAspect::All,
+                    },
+                    &pixels,
+                    wgpu::ImageDataLayout {
+                        offset: 0,
+                        bytes_per_row: Some(4 * width),
+                        rows_per_image: Some(height),
+                    },
+                    wgpu::Extent3d {
+                        width,
+                        height,
+                        depth_or_array_layers: 1,
+                    },
+                );
+                Some((x, y, width, height))
+            }
+        };
+        let glyph = Glyph {
+            rect,
+            left: metrics.xmin,
+            bottom: metrics.ymin,
+        };
+        self.glyphs.insert(key, glyph);
+        Some(glyph)
+    }
+
+    // the first shelf the box fits on without wasting too much height, or a new one.
+    fn allocate(&mut self, width: u32, height: u32) -> Option<(u32, u32)> {
+        if let Some(shelf) = self.shelves.iter_mut().find(|shelf| {
+            shelf.height >= height && shelf.height <= height * 2 && shelf.x + width <= ATLAS_SIZE
+        }) {
+            shelf.x += width;
+            return Some((shelf.x - width, shelf.y));
+        }
+        let y = self
+            .shelves
+            .last()
+            .map(|shelf| shelf.y + shelf.height)
+            .unwrap_or(0);
+        if y + height > ATLAS_SIZE || width > ATLAS_SIZE {
+            return None;
+        }
+        self.shelves.push(Shelf {
+            y,
+            height,
+            x: width,
+        });
+        Some((0, y))
+    }
+}
+
+// lines are lined up within the width the text is wrapped to (or the widest line, if it isn't
+// wrapped).
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum Align {
+    #[default]
+    Left,
+    Centre,
+    Right,
+}
+
+// a bit of text in the ui, laid out like a ui::Element that's as big as the text. sizes are in
+// logical pixels; the glyphs are rasterized at the window's scale so they stay sharp.
+pub struct Text {
+    pub label: String,
+    pub anchor: ui::Anchor,
+    pub pivot: ui::Anchor,
+    pub position: cgmath::Point2<f32>,
+    pub parent: Option<usize>, // an index into the state's ui elements.
+    pub z: i32,                // among its siblings, elements included.
+    previous_position: cgmath::Point2<f32>,
+    clipped_to: Option<ui::Rect>,
+    font: FontId,
+    text: String,
+    size: f32,
+    colour: [f32; 4],
+    align: Align,
+    wrap: Option<f32>,
+    // the layout only gets redone when something above changes.
+    // the atlas generation and scale factor it was laid out with.
+    laid_out: Option<(u64, f32)>,
+    dimensions: (f32, f32),
+    glyphs: Vec<model::ModelVertex>, // in physical pixels from the top left.
+    indices: Vec<u32>,
+    vertices: Vec<model::ModelVertex>, // the glyphs where they end up on the window.
+}
+
+pub struct TextBuilder {
+    label: String,
+    font: FontId,
+    text: String,
+    anchor: ui::Anchor,
+    pivot: ui::Anchor,
+    position: cgmath::Point2<f32>,
+    parent: Option<usize>,
+    z: i32,
+    size: f32,
+    colour: [f32; 4],
+    align: Align,
+    wrap: Option<f32>,
+}
+
+impl TextBuilder {
+    pub fn text<T: Into<String>>(mut self, text: T) -> Self {
+        self.text = text.into();
+        self
+    }
+
+    pub fn position<P: Into<cgmath::Point2<f32>>>(mut self, position: P) -> Self {
+        self.position = position.into();
+        self
+    }
+
+    pub fn anchor(mut self, anchor: ui::Anchor) -> Self {
+        self.anchor = anchor;
+        self
+    }
+
+    pub fn pivot(mut self, pivot: ui::Anchor) -> Self {
+        self.pivot = pivot;
+        self
+    }
+
+    // the parent has to be one of the state's ui elements.
+    pub fn parent(mut self, parent: usize) -> Self {
+        self.parent = Some(parent);
+        self
+    }
+
+    pub fn z(mut self, z: i32) -> Self {
+        self.z = z;
+        self
+    }
+
+    pub fn size(mut self, size: f32) -> Self {
+        self.size = size;
+        self
+    }
+
+    // multiplied in like a vertex colour.
+    pub fn colour(mut self, colour: [f32; 4]) -> Self {
+        self.colour = colour;
+        self
+    }
+
+    pub fn align(mut self, align: Align) -> Self {
+        self.align = align;
+        self
+    }
+
+    // breaks lines between words so none is wider than `width` pixels. a word that's wider than
+    // that on its own gets a line to itself and sticks out.
+    pub fn wrap(mut self, width: f32) -> Self {
+        self.wrap = Some(width);
+        self
+    }
+
+    pub fn build(self) -> Result<Text, String> {
+        let label = self.label;
+        if self.size <= 0.0 || !self.size.is_finite() {
+            return Err(format!("{}: size has to be finite and positive", label));
+        }
+        if let Some(width) = self.wrap {
+            if width <= 0.0 || !width.is_finite() {
+                return Err(format!(
+                    "{}: wrap width has to be finite and positive",
+                    label
+                ));
+            }
+        }
+
+        Ok(Text {
+            label,
+            anchor: self.anchor,
+            pivot: self.pivot,
+            position: self.position,
+            parent: self.parent,
+            z: self.z,
+            previous_position: self.position,
+            clipped_to: None,
+            font: self.font,
+            text: self.text,
+            size: self.size,
+            colour: self.colour,
+            align: self.align,
+            wrap: self.wrap,
+            laid_out: None,
+            dimensions: (0.0, 0.0),
+            glyphs: Vec::new(),
+            indices: Vec::new(),
+            vertices: Vec::new(),
+        })
+    }
+}
+
+impl Text {
+    pub fn builder<L: Into<String>>(label: L, font: FontId) -> TextBuilder {
+        TextBuilder {
+            label: label.into(),
+            font,
+            text: String::new(),
+            anchor: ui::Anchor::TOP_LEFT,
+            pivot: ui::Anchor::TOP_LEFT,
+            position: (0.0, 0.0).into(),
+            parent: None,
+            z: 0,
+            size: 16.0,
+            colour: [1.0; 4],
+            align: Align::Left,
+            wrap: None,
+        }
+    }
+
+    // cheap to call every tick with the same text; nothing's redone unless it actually changed.
+    pub fn set_text<T: AsRef<str>>(&mut self, text: T) {
+        if self.text != text.as_ref() {
+            self.text = text.as_ref().to_string();
+            self.laid_out = None;
+        }
+    }
+
+    pub fn set_font(&mut self, font: FontId) {
+        if self.font != font {
+            self.font = font;
+            self.laid_out = None;
+        }
+    }
+
+    // ignored unless it's finite and positive.
+    pub fn set_size(&mut self, size: f32) {
+        if self.size != size && size > 0.0 && size.is_finite() {
+            self.size = size;
+            self.laid_out = None;
+        }
+    }
+
+    pub fn set_colour(&mut self, colour: [f32; 4]) {
+        if self.colour != colour {
+            self.colour = colour;
+            self.laid_out = None;
+        }
+    }
+
+    pub fn set_wrap(&mut self, width: Option<f32>) {
+        if self.wrap != width {
+            self.wrap = width;
+            self.laid_out = None;
+        }
+    }
+
+    // how much room the text takes up, as of the last update. that's the width it's wrapped to,
+    // if it is.
+    pub fn dimensions(&self) -> (f32, f32) {
+        self.dimensions
+    }
+
+    pub fn clipped_to(&self) -> Option<ui::Rect> {
+        self.clipped_to
+    }
+
+    pub fn vertices(&self) -> &[model::ModelVertex] {
+        &self.vertices
+    }
+
+    pub fn indices(&self) -> &[u32] {
+        &self.indices
+    }
+
+    // same as object::Object::snap().
+    pub fn snap(&mut self) {
+        self.previous_position = self.position;
+    }
+
+    // lays the text out again if it's changed (or the atlas has been emptied since). returns
+    // false if its glyphs didn't all fit in the atlas, which then wants emptying.
+    pub fn lay_out(&mut self, queue: &wgpu::Queue, fonts: &mut Fonts, scale_factor: f32) -> bool {
+        if self.laid_out == Some((fonts.generation, scale_factor)) {
+            return true;
+        }
+        let Some(font) = fonts.get(self.font) else {
+            log::warn!("{}: there's no font {}", self.label, self.font);
+            self.glyphs.clear();
+            self.indices.clear();
+            self.laid_out = Some((fonts.generation, scale_factor));
+            return true;
+        };
+        // everything from here on is in physical pixels.
+        let size = self.size * scale_factor;
+        let wrap = self.wrap.map(|width| width * scale_factor);
+        let (glyphs, (width, height)) = shape(font, &self.text, size, self.align, wrap);
+        self.dimensions = (
+            self.wrap.unwrap_or(width / scale_factor),
+            height / scale_factor,
+        );
+
+        let mut vertices = Vec::with_capacity(glyphs.len() * 4);
+        let mut indices: Vec<u32> = Vec::with_capacity(glyphs.len() * 6);
+        let mut fits = true;
+        for placed in glyphs {
+            let key = GlyphKey {
+                font: self.font,
+                glyph: placed.glyph,
+                size: size.to_bits(),
+            };
+            let Some(glyph) = fonts.glyph(queue, key) else {
+                fits = false;
+                continue;
+            };
+            let Some((u, v, width, height)) = glyph.rect else {
+                continue;
+            };
+            // whole pixels keep the glyphs as sharp as they were rasterized.
+            let left = placed.x.round() + glyph.left as f32;
+            let top = placed.y.round() - (glyph.bottom + height as i32) as f32;
+            let (right, bottom) = (left + width as f32, top + height as f32);
+            let uv = |x: u32, y: u32| [x as f32 / ATLAS_SIZE as f32, y as f32 / ATLAS_SIZE as f32];
+            let first = vertices.len() as u32;
+            // in physical pixels from the top left; update() scales them down to the ui.
+            for (position, tex_coords) in [
+                ([left, top], uv(u, v)),
+                ([left, bottom], uv(u, v + height)),
+                ([right, bottom], uv(u + width, v + height)),
+                ([right, top], uv(u + width, v)),
+            ] {
+                vertices.push(model::ModelVertex {
+                    position: [position[0], position[1], 0.0],
+                    tex_coords,
+                    colour: self.colour,
+                    ..Default::default()
+                });
+            }
+            indices.extend([0, 1, 2, 2, 3, 0].map(|i| first + i));
+        }
+
+        self.glyphs = vertices;
+        self.indices = indices;
+        self.laid_out = Some((fonts.generation, scale_factor));
+        fits
+    }
+
+    // see ui::inherited().
+    pub fn update(&mut self, blend: f32, parent: ui::Inherited, scale_factor: f32) {
+        let position = self.previous_position + (self.position - self.previous_position) * blend;
+        let mut rect = parent
+            .rect
+            .place(self.anchor, self.pivot, position, self.dimensions);
+        // onto whole physical pixels, or the glyphs would be resampled and go blurry.
+        rect.x = (rect.x * scale_factor).round() / scale_factor;
+        rect.y = (rect.y * scale_factor).round() / scale_factor;
+        // つづ: that's undone as soon as a parent is scaled or turned.
+        self.clipped_to = parent.clip;
+        let transform = parent.transform
+            * cgmath::Matrix4::from_translation(cgmath::Vector3::new(rect.x, rect.y, 0.0))
+            * cgmath::Matrix4::from_scale(1.0 / scale_factor);
+        self.vertices.clear();
+        self.vertices.extend(self.glyphs.iter().map(|glyph| {
+            let [x, y, _] = glyph.position;
+            let position = transform * cgmath::Vector4::new(x, y, 0.0, 1.0);
+            let [r, g, b, a] = glyph.colour;
+            model::ModelVertex {
+                position: [position.x, position.y, 0.0],
+                colour: [r, g, b, a * parent.opacity],
+                ..*glyph
+            }
+        }));
+    }
+}
+
+// a glyph's pen position in the laid out text, in pixels from the top left, on the baseline.
+struct Placed {
+    glyph: u16,
+    x: f32,
+    y: f32,
+}
+
+struct ShapedGlyph {
+    glyph: u16,
+    advance: f32,
+    offset: (f32, f32), // y goes down.
+    trailing: bool,     // part of the whitespace at the end of the word.
+}
+
+struct Word {
+    glyphs: Vec<ShapedGlyph>, // in the order they're drawn, left to right.
+    width: f32,
+    trimmed_width: f32, // without the whitespace at the end.
+}
+
+fn shape_word(
+    face: &rustybuzz::Face,
+    word: &str,
+    direction: rustybuzz::Direction,
+    scale: f32,
+) -> Word {
+    let mut buffer = rustybuzz::UnicodeBuffer::new();
+    buffer.push_str(word);
+    buffer.set_direction(direction);
+    buffer.guess_segment_properties(); // the script and language.
+    let output = rustybuzz::shape(face, &[], buffer);
+
+    let trimmed = word.trim_end().len();
+    let glyphs: Vec<ShapedGlyph> = output
+        .glyph_infos()
+        .iter()
+        .zip(output.glyph_positions())
+        .map(|(info, position)| ShapedGlyph {
+            glyph: info.glyph_id as u16,
+            advance: position.x_advance as f32 * scale,
+            offset: (
+                position.x_offset as f32 * scale,
+                -position.y_offset as f32 * scale,
+            ),
+            trailing: info.cluster as usize >= trimmed,
+        })
+        .collect();
+    Word {
+        width: glyphs.iter().map(|glyph| glyph.advance).sum(),
+        trimmed_width: glyphs
+            .iter()
+            .filter(|glyph| !glyph.trailing)
+            .map(|glyph| glyph.advance)
+            .sum(),
+        glyphs,
+    }
+}
+
+// words are shaped one at a time (so ligatures and kerning work within words, but not across
+// the spaces between them) and wrapped greedily. a paragraph going right to left has its words
+// laid out right to left too.
+// つづ: mixing directions within a paragraph needs the unicode bidi algorithm; for now the whole
+// paragraph goes whichever way its first strong character does.
+fn shape(
+    font: &Font,
+    text: &str,
+    size: f32,
+    align: Align,
+    wrap: Option<f32>,
+) -> (Vec<Placed>, (f32, f32)) {
+    let face = font.face();
+    let scale = size / face.units_per_em() as f32;
+    let (ascent, line_height) = match font.rasterizer.horizontal_line_metrics(size) {
+        Some(metrics) => (metrics.ascent, metrics.new_line_size),
+        None => (size, size * 1.2), // vertical only fonts, which are rare enough.
+    };
+
+    let mut lines: Vec<(Vec<Placed>, f32)> = Vec::new();
+    for paragraph in text.split('\n') {
+        let mut buffer = rustybuzz::UnicodeBuffer::new();
+        buffer.push_str(paragraph);
+        buffer.guess_segment_properties();
+        let direction = match buffer.direction() {
+            rustybuzz::Direction::RightToLeft => rustybuzz::Direction::RightToLeft,
+            _ => rustybuzz::Direction::LeftToRight,
+        };
+
+        let words: Vec<Word> = paragraph
+            .split_inclusive(char::is_whitespace)
+            .map(|word| shape_word(&face, word, direction, scale))
+            .collect();
+        let mut line: Vec<&Word> = Vec::new();
+        let mut width = 0.0;
+        for word in &words {
+            if let Some(wrap) = wrap {
+                if !line.is_empty() && width + word.trimmed_width > wrap {
+                    lines.push(place_line(&line, direction));
+                    line.clear();
+                    width = 0.0;
+                }
+            }
+            width += word.width;
+            line.push(word);
+        }
+        lines.push(place_line(&line, direction));
+    }
+
+    let widest = lines.iter().map(|(_, width)| *width).fold(0.0, f32::max);
+    let box_width = wrap.unwrap_or(widest);
+    let mut placed = Vec::new();
+    for (i, (glyphs, width)) in lines.iter_mut().enumerate() {
+        let shift = match align {
+            Align::Left => 0.0,
+            Align::Centre => (box_width - *width) / 2.0,
+            Align::Right => box_width - *width,
+        };
+        let baseline = ascent + i as f32 * line_height;
+        placed.extend(glyphs.drain(..).map(|glyph| Placed {
+            x: glyph.x + shift,
+            y: glyph.y + baseline,
+            ..glyph
+        }));
+    }
+    (placed, (widest, lines.len() as f32 * line_height))
+}
+
+// puts a line's words next to each other, from x = 0.0 on the baseline. the whitespace after the
+// last word is left off, so it doesn't count towards the line's width.
+fn place_line(words: &[&Word], direction: rustybuzz::Direction) -> (Vec<Placed>, f32) {
+    let last = words.len().saturating_sub(1);
+    let mut order: Vec<(usize, &&Word)> = words.iter().enumerate().collect();
+    if direction == rustybuzz::Direction::RightToLeft {
+        order.reverse();
+    }
+
+    let mut placed = Vec::new();
+    let mut x = 0.0;
+    for (i, word) in order {
+        for glyph in &word.glyphs {
+            if i == last && glyph.trailing {
+                continue;
+            }
+            placed.push(Placed {
+                glyph: glyph.glyph,
+                x: x + glyph.offset.0,
+                y: glyph.offset.1,
+            });
+            x += glyph.advance;
+        }
+    }
+    (placed, x)
+}
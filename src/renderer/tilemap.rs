@@ -0,0 +1,1145 @@
+use std::io::Read;
+use std::path::Path;
+
+use cgmath::Matrix4;
+use wgpu::util::DeviceExt;
+
+use super::model::DrawObject;
+use super::{camera, instance, model, object, pipeline, resource, texture, ui};
+
+// maps made in tiled (https://www.mapeditor.org), either .tmx or .tmj, with their tilesets
+// inline or in .tsx/.tsj files next to them. only orthogonal maps, and only tile layers get drawn;
+// object and image layers are skipped. each layer is cut into chunks of CHUNK by CHUNK tiles, one
+// mesh per chunk per tileset, so only the chunks the 2d camera can see get drawn.
+// つづ: animated tiles show their first frame.
+const CHUNK: i32 = 16;
+
+const FLIPPED_HORIZONTALLY: u32 = 0x80000000;
+const FLIPPED_VERTICALLY: u32 = 0x40000000;
+const FLIPPED_DIAGONALLY: u32 = 0x20000000;
+const GID: u32 = 0x0fffffff; // what's left once the flags are off (the fourth is for hexagons).
+
+pub struct Tilemap {
+    pub position: cgmath::Point2<f32>, // where the top left corner of tile (0, 0) goes.
+    // layer i is drawn at z + i, so sprites can go in between; see sprite::Sprite::z.
+    pub z: i32,
+    pub layers: Vec<Layer>,
+    tile_size: (u32, u32),
+    materials: Vec<model::Material>, // one per tileset.
+    previous_position: cgmath::Point2<f32>,
+    drawn_at: cgmath::Point2<f32>, // as of the last update.
+    instance_buffer: wgpu::Buffer,
+    transformation_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+}
+
+pub struct Layer {
+    pub name: String,
+    pub visible: bool,
+    chunks: Vec<Chunk>,
+}
+
+struct Chunk {
+    mesh: model::Mesh,
+    bounds: ui::Rect, // relative to the map's position.
+}
+
+impl Tilemap {
+    // `path` says which format `data` is in (by its extension) and where the tilesets and images
+    // are, which `read` then gets the bytes of, from the filesystem or wherever they're kept.
+    pub fn load_bytes<F>(
+        label: &str,
+        path: &Path,
+        data: &[u8],
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        read: F,
+    ) -> Result<Self, String>
+    where
+        F: Fn(&Path) -> Result<Vec<u8>, String>,
+    {
+        let map = Map::parse(path, data, &read)?;
+        let mut materials = Vec::with_capacity(map.tilesets.len());
+        let mut sizes = Vec::with_capacity(map.tilesets.len());
+        for tileset in &map.tilesets {
+            let bytes = read(&tileset.image)?;
+            let texture = texture::Texture::from_image_bytes(
+                Some(&format!("{} {} texture", label, tileset.name)),
+                device,
+                queue,
+                &bytes,
+            )
+            .map_err(|e| format!("{}: {}", tileset.image.display(), e))?;
+            let size = texture.texture.size();
+            sizes.push((size.width, size.height));
+            materials.push(resource::create_material(
+                format!("{} {} material", label, tileset.name),
+                texture,
+                device,
+                &texture::Texture::layout(device),
+            ));
+        }
+
+        let layers = map
+            .layers
+            .iter()
+            .map(|layer| {
+                let chunks = build_chunks(label, layer, &map, &sizes, device)?;
+                Ok(Layer {
+                    name: layer.name.clone(),
+                    visible: layer.visible,
+                    chunks,
+                })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        let instance_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&format!("{} instance buffer", label)),
+            contents: bytemuck::cast_slice(&[instance::Instance {
+                position: (0.0, 0.0, 0.0).into(),
+                rotation: (0.0, 0.0, 0.0, 1.0).into(),
+            }
+            .to_raw()]),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+        let transformation_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(&format!("{} transformation buffer", label)),
+            contents: bytemuck::cast_slice(&[object::TransformationUniform::default()]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some(&format!("{} transformation bind group", label)),
+            layout: &object::Object::layout(device),
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: transformation_buffer.as_entire_binding(),
+            }],
+        });
+
+        Ok(Self {
+            position: (0.0, 0.0).into(),
+            z: 0,
+            layers,
+            tile_size: map.tile_size,
+            materials,
+            previous_position: (0.0, 0.0).into(),
+            drawn_at: (0.0, 0.0).into(),
+            instance_buffer,
+            transformation_buffer,
+            bind_group,
+        })
+    }
+
+    // in world pixels.
+    pub fn tile_size(&self) -> (u32, u32) {
+        self.tile_size
+    }
+
+    // same as object::Object::snap().
+    pub fn snap(&mut self) {
+        self.previous_position = self.position;
+    }
+
+    pub fn update(&mut self, queue: &wgpu::Queue, blend: f32) {
+        let position = self.previous_position + (self.position - self.previous_position) * blend;
+        self.drawn_at = position;
+        let uniform = object::TransformationUniform {
+            matrix: Matrix4::from_translation((position.x, position.y, 0.0).into()).into(),
+            ..Default::default()
+        };
+        queue.write_buffer(
+            &self.transformation_buffer,
+            0,
+            bytemuck::cast_slice(&[uniform]),
+        );
+    }
+
+    // draws the chunks of a layer that overlap `view` (see camera::Camera2d::view()), with the
+    // pipeline already set.
+    pub fn draw_layer<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        layer: usize,
+        view: ui::Rect,
+        camera: &'a camera::Camera,
+    ) {
+        let Some(layer) = self.layers.get(layer).filter(|layer| layer.visible) else {
+            return;
+        };
+        let view = ui::Rect {
+            x: view.x - self.drawn_at.x,
+            y: view.y - self.drawn_at.y,
+            ..view
+        };
+        render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+        for chunk in &layer.chunks {
+            let overlap = chunk.bounds.intersection(&view);
+            if overlap.width <= 0.0 || overlap.height <= 0.0 {
+                continue;
+            }
+            let mesh = &chunk.mesh;
+            render_pass.draw_mesh(
+                mesh,
+                &self.materials[mesh.material],
+                &camera.bind_group,
+                &self.bind_group,
+            );
+        }
+    }
+}
+
+// every chunk of every map gets drawn the same way. tiles only need positions, uvs and colours;
+// the rest would only ever be defaults.
+pub(super) fn pipeline_key() -> pipeline::Key {
+    pipeline::Key {
+        attributes: model::VertexAttributes::POSITION
+            | model::VertexAttributes::TEX_COORDS
+            | model::VertexAttributes::COLOUR,
+        ..Default::default()
+    }
+}
+
+// (chunk x, chunk y, tileset) to the quads in it. a btree so the meshes come out in the same
+// order every time.
+type Quads = std::collections::BTreeMap<(i32, i32, usize), Vec<model::ModelVertex>>;
+
+fn build_chunks(
+    label: &str,
+    layer: &TileLayer,
+    map: &Map,
+    sizes: &[(u32, u32)],
+    device: &wgpu::Device,
+) -> Result<Vec<Chunk>, String> {
+    Ok(quads(layer, map, sizes)?
+        .into_iter()
+        .map(|((x, y, tileset), vertices)| {
+            let label = format!("{} {} chunk ({}, {})", label, layer.name, x, y);
+            let indices: Vec<u32> = (0..vertices.len() as u32 / 4)
+                .flat_map(|quad| super::sprite::INDICES.map(|i| quad * 4 + i))
+                .collect();
+            let (mut min, mut max) = ((f32::MAX, f32::MAX), (f32::MIN, f32::MIN));
+            for vertex in &vertices {
+                min = (min.0.min(vertex.position[0]), min.1.min(vertex.position[1]));
+                max = (max.0.max(vertex.position[0]), max.1.max(vertex.position[1]));
+            }
+            let key = pipeline_key();
+            let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(&format!("{} vertex buffer", label)),
+                contents: &key.attributes.pack(key.vertex_kind, &vertices),
+                usage: wgpu::BufferUsages::VERTEX,
+            });
+            let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(&format!("{} index buffer", label)),
+                contents: bytemuck::cast_slice(&indices),
+                usage: wgpu::BufferUsages::INDEX,
+            });
+            Chunk {
+                mesh: model::Mesh {
+                    vertex_buffer,
+                    index_buffer,
+                    num_elements: indices.len() as u32,
+                    material: tileset,
+                    attributes: key.attributes,
+                    topology: key.topology,
+                    vertex_kind: key.vertex_kind,
+                    index_format: wgpu::IndexFormat::Uint32,
+                },
+                bounds: ui::Rect {
+                    x: min.0,
+                    y: min.1,
+                    width: max.0 - min.0,
+                    height: max.1 - min.1,
+                },
+            }
+        })
+        .collect())
+}
+
+// the tiles' corners, in pixels from the map's position, and where they are in their tilesets'
+// images. `sizes` are the images' sizes.
+fn quads(layer: &TileLayer, map: &Map, sizes: &[(u32, u32)]) -> Result<Quads, String> {
+    let mut chunks = Quads::new();
+    for &(x, y, gid) in &layer.tiles {
+        let flags = gid & !GID;
+        let gid = gid & GID;
+        if gid == 0 {
+            continue; // nothing there.
+        }
+        let Some(index) = map
+            .tilesets
+            .iter()
+            .rposition(|tileset| tileset.first_gid <= gid)
+        else {
+            return Err(format!("{}: no tileset has tile {}", layer.name, gid));
+        };
+        let tileset = &map.tilesets[index];
+        let (width, height) = sizes[index];
+        let (tile_width, tile_height) = tileset.tile_size;
+        // only a tileset (or map) nobody could have drawn gets anywhere near overflowing, but the
+        // numbers come straight from the file.
+        let too_big = || format!("{}: {} is too big", layer.name, tileset.name);
+        let fit = |length: u32, tile: u32| {
+            let inside = length.saturating_sub(tileset.margin.saturating_mul(2));
+            Some(inside.checked_add(tileset.spacing)? / tile.checked_add(tileset.spacing)?)
+        };
+        let columns = match tileset.columns {
+            0 => fit(width, tile_width).ok_or_else(too_big)?,
+            columns => columns,
+        };
+        let rows = fit(height, tile_height).ok_or_else(too_big)?;
+        let tiles = columns.checked_mul(rows).ok_or_else(too_big)?;
+        let tile = gid - tileset.first_gid;
+        if tile >= tiles {
+            return Err(format!(
+                "{}: tile {} is past the end of {}",
+                layer.name, gid, tileset.name
+            ));
+        }
+        // where the tile's top left corner is in the image, along one side.
+        let corner = |index: u32, tile: u32| {
+            index
+                .checked_mul(tile.checked_add(tileset.spacing)?)?
+                .checked_add(tileset.margin)
+        };
+        let u = corner(tile % columns, tile_width).ok_or_else(too_big)?;
+        let v = corner(tile / columns, tile_height).ok_or_else(too_big)?;
+        let (u0, v0) = (u as f32 / width as f32, v as f32 / height as f32);
+        let (u1, v1) = (
+            (u as f32 + tile_width as f32) / width as f32,
+            (v as f32 + tile_height as f32) / height as f32,
+        );
+
+        // tiles bigger than the grid stick up and out to the right, like they do in tiled.
+        let too_far = || format!("{}: the tile at ({}, {}) is too far out", layer.name, x, y);
+        let grid = |index: i32, length: u32| index.checked_mul(i32::try_from(length).ok()?);
+        let left = grid(x, map.tile_size.0).ok_or_else(too_far)? as f32 + layer.offset.0;
+        let bottom = y
+            .checked_add(1)
+            .and_then(|below| grid(below, map.tile_size.1))
+            .ok_or_else(too_far)? as f32
+            + layer.offset.1;
+        let top = bottom - tile_height as f32;
+        let right = left + tile_width as f32;
+
+        let quads = chunks
+            .entry((x.div_euclid(CHUNK), y.div_euclid(CHUNK), index))
+            .or_default();
+        // top left, bottom left, bottom right, top right, like sprites.
+        for (corner_x, corner_y) in [(0, 0), (0, 1), (1, 1), (1, 0)] {
+            // the flips are undone in the opposite order tiled does them in: across and up and
+            // down first, then the diagonal.
+            let mut sample = (corner_x, corner_y);
+            if flags & FLIPPED_HORIZONTALLY != 0 {
+                sample.0 = 1 - sample.0;
+            }
+            if flags & FLIPPED_VERTICALLY != 0 {
+                sample.1 = 1 - sample.1;
+            }
+            if flags & FLIPPED_DIAGONALLY != 0 {
+                sample = (sample.1, sample.0);
+            }
+            quads.push(model::ModelVertex {
+                position: [[left, right][corner_x], [top, bottom][corner_y], 0.0],
+                tex_coords: [[u0, u1][sample.0], [v0, v1][sample.1]],
+                colour: [1.0, 1.0, 1.0, layer.opacity],
+                ..Default::default()
+            });
+        }
+    }
+
+    Ok(chunks)
+}
+
+// what's left of a map once it's been read, whichever format it was in.
+struct Map {
+    tile_size: (u32, u32),
+    tilesets: Vec<Tileset>, // by first_gid.
+    layers: Vec<TileLayer>, // bottom first, with groups flattened out.
+}
+
+struct Tileset {
+    name: String,
+    first_gid: u32,
+    tile_size: (u32, u32),
+    spacing: u32,
+    margin: u32,
+    columns: u32, // 0 works it out from the image.
+    image: std::path::PathBuf,
+}
+
+struct TileLayer {
+    name: String,
+    visible: bool,
+    opacity: f32,
+    offset: (f32, f32),          // in pixels, including the groups it was in.
+    tiles: Vec<(i32, i32, u32)>, // x, y and gid, flags and all.
+}
+
+// what a group passes down to the layers in it.
+#[derive(Copy, Clone)]
+struct Group {
+    visible: bool,
+    opacity: f32,
+    offset: (f32, f32),
+}
+
+impl Group {
+    const ROOT: Self = Self {
+        visible: true,
+        opacity: 1.0,
+        offset: (0.0, 0.0),
+    };
+
+    fn child(self, visible: bool, opacity: f32, offset: (f32, f32)) -> Self {
+        Self {
+            visible: self.visible && visible,
+            opacity: self.opacity * opacity,
+            offset: (self.offset.0 + offset.0, self.offset.1 + offset.1),
+        }
+    }
+}
+
+impl Map {
+    fn parse<F>(path: &Path, data: &[u8], read: &F) -> Result<Self, String>
+    where
+        F: Fn(&Path) -> Result<Vec<u8>, String>,
+    {
+        let text = std::str::from_utf8(data).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let mut map = match extension(path).as_str() {
+            "tmx" => tmx::map(path, text, read),
+            "tmj" | "json" => tmj::map(path, text, read),
+            other => Err(format!("tiled maps are .tmx or .tmj, not .{}", other)),
+        }
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+        if map.tile_size.0 == 0 || map.tile_size.1 == 0 {
+            return Err(format!("{}: the map has no tile size", path.display()));
+        }
+        map.tilesets.sort_by_key(|tileset| tileset.first_gid);
+        Ok(map)
+    }
+}
+
+fn extension(path: &Path) -> String {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or_default()
+        .to_ascii_lowercase()
+}
+
+// tilesets and images are relative to the file that mentions them.
+fn relative(file: &Path, path: &str) -> std::path::PathBuf {
+    file.parent().unwrap_or(Path::new("")).join(path)
+}
+
+// layer data that isn't plain numbers: base64, maybe compressed, of little endian u32s.
+fn decode(text: &str, compression: Option<&str>) -> Result<Vec<u32>, String> {
+    let text: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+    let bytes = base64::decode(text).map_err(|e| e.to_string())?;
+    let bytes = match compression.unwrap_or_default() {
+        "" => bytes,
+        "zlib" => inflate(flate2::read::ZlibDecoder::new(&bytes[..]))?,
+        "gzip" => inflate(flate2::read::GzDecoder::new(&bytes[..]))?,
+        other => return Err(format!("{} compression isn't supported", other)),
+    };
+    if bytes.len() % 4 != 0 {
+        return Err("layer data isn't a whole number of tiles".to_string());
+    }
+    Ok(bytes
+        .chunks_exact(4)
+        .map(|gid| u32::from_le_bytes([gid[0], gid[1], gid[2], gid[3]]))
+        .collect())
+}
+
+fn inflate<R: Read>(mut reader: R) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+    reader
+        .read_to_end(&mut bytes)
+        .map_err(|e| format!("couldn't decompress layer data: {}", e))?;
+    Ok(bytes)
+}
+
+// a width by height block of gids with its top left tile at (x, y).
+fn place(tiles: &mut Vec<(i32, i32, u32)>, (x, y, width): (i32, i32, i32), gids: &[u32]) {
+    if width <= 0 {
+        return;
+    }
+    tiles.extend(
+        gids.iter()
+            .enumerate()
+            .map(|(i, &gid)| (x + i as i32 % width, y + i as i32 / width, gid)),
+    );
+}
+
+fn check_size(gids: &[u32], (width, height): (i32, i32)) -> Result<(), String> {
+    if gids.len() as i64 != width as i64 * height as i64 {
+        return Err(format!(
+            "expected {} by {} tiles but there are {}",
+            width,
+            height,
+            gids.len()
+        ));
+    }
+    Ok(())
+}
+
+mod tmx {
+    use std::path::Path;
+
+    use super::{Group, Map, TileLayer, Tileset};
+
+    fn attribute<T: std::str::FromStr>(
+        node: roxmltree::Node,
+        name: &str,
+        default: Option<T>,
+    ) -> Result<T, String> {
+        match node.attribute(name) {
+            Some(value) => value.parse().map_err(|_| {
+                format!(
+                    "{} has a bad {} ({:?})",
+                    node.tag_name().name(),
+                    name,
+                    value
+                )
+            }),
+            None => {
+                default.ok_or_else(|| format!("{} is missing its {}", node.tag_name().name(), name))
+            }
+        }
+    }
+
+    pub fn map<F>(path: &Path, text: &str, read: &F) -> Result<Map, String>
+    where
+        F: Fn(&Path) -> Result<Vec<u8>, String>,
+    {
+        let document = roxmltree::Document::parse(text).map_err(|e| e.to_string())?;
+        let root = document.root_element();
+        if root.tag_name().name() != "map" {
+            return Err("there's no map in it".to_string());
+        }
+        let orientation = root.attribute("orientation").unwrap_or("orthogonal");
+        if orientation != "orthogonal" {
+            return Err(format!("{} maps aren't supported", orientation));
+        }
+
+        let mut tilesets = Vec::new();
+        for node in root.children().filter(|node| node.has_tag_name("tileset")) {
+            let first_gid = attribute(node, "firstgid", None)?;
+            tilesets.push(match node.attribute("source") {
+                Some(source) => {
+                    let file = super::relative(path, source);
+                    let bytes = read(&file)?;
+                    let text = std::str::from_utf8(&bytes)
+                        .map_err(|e| format!("{}: {}", file.display(), e))?;
+                    match super::extension(&file).as_str() {
+                        "tsj" | "json" => super::tmj::external_tileset(&file, text, first_gid),
+                        _ => external_tileset(&file, text, first_gid),
+                    }
+                    .map_err(|e| format!("{}: {}", file.display(), e))?
+                }
+                None => tileset(path, node, first_gid)?,
+            });
+        }
+
+        let mut layers = Vec::new();
+        flatten(root, Group::ROOT, &mut layers)?;
+
+        Ok(Map {
+            tile_size: (
+                attribute(root, "tilewidth", None)?,
+                attribute(root, "tileheight", None)?,
+            ),
+            tilesets,
+            layers,
+        })
+    }
+
+    fn external_tileset(file: &Path, text: &str, first_gid: u32) -> Result<Tileset, String> {
+        let document = roxmltree::Document::parse(text).map_err(|e| e.to_string())?;
+        let root = document.root_element();
+        if root.tag_name().name() != "tileset" {
+            return Err("there's no tileset in it".to_string());
+        }
+        tileset(file, root, first_gid)
+    }
+
+    fn tileset(file: &Path, node: roxmltree::Node, first_gid: u32) -> Result<Tileset, String> {
+        let name = node.attribute("name").unwrap_or_default().to_string();
+        let Some(image) = node.children().find(|node| node.has_tag_name("image")) else {
+            return Err(format!(
+                "{} is a collection of images, which isn't supported",
+                name
+            ));
+        };
+        let tile_size = (
+            attribute(node, "tilewidth", None)?,
+            attribute(node, "tileheight", None)?,
+        );
+        if tile_size.0 == 0 || tile_size.1 == 0 {
+            return Err(format!("{} has no tile size", name));
+        }
+        Ok(Tileset {
+            first_gid,
+            tile_size,
+            spacing: attribute(node, "spacing", Some(0))?,
+            margin: attribute(node, "margin", Some(0))?,
+            columns: attribute(node, "columns", Some(0))?,
+            image: super::relative(file, &attribute::<String>(image, "source", None)?),
+            name,
+        })
+    }
+
+    fn flatten(
+        parent: roxmltree::Node,
+        group: Group,
+        layers: &mut Vec<TileLayer>,
+    ) -> Result<(), String> {
+        for node in parent.children().filter(|node| node.is_element()) {
+            let name = node.tag_name().name();
+            if name != "layer" && name != "group" {
+                continue;
+            }
+            let group = group.child(
+                attribute::<u8>(node, "visible", Some(1))? != 0,
+                attribute(node, "opacity", Some(1.0))?,
+                (
+                    attribute(node, "offsetx", Some(0.0))?,
+                    attribute(node, "offsety", Some(0.0))?,
+                ),
+            );
+            if name == "group" {
+                flatten(node, group, layers)?;
+                continue;
+            }
+            let layer_name = node.attribute("name").unwrap_or_default().to_string();
+            let tiles = tiles(node).map_err(|e| format!("{}: {}", layer_name, e))?;
+            layers.push(TileLayer {
+                name: layer_name,
+                visible: group.visible,
+                opacity: group.opacity,
+                offset: group.offset,
+                tiles,
+            });
+        }
+        Ok(())
+    }
+
+    fn tiles(layer: roxmltree::Node) -> Result<Vec<(i32, i32, u32)>, String> {
+        let mut tiles = Vec::new();
+        let Some(data) = layer.children().find(|node| node.has_tag_name("data")) else {
+            return Ok(tiles);
+        };
+        let encoding = data.attribute("encoding");
+        let compression = data.attribute("compression");
+        let chunks: Vec<_> = data
+            .children()
+            .filter(|node| node.has_tag_name("chunk"))
+            .collect();
+        // infinite maps come in chunks; finite ones are one big block.
+        let blocks = match chunks.is_empty() {
+            true => vec![(data, (0, 0), layer)],
+            false => chunks
+                .into_iter()
+                .map(|chunk| {
+                    let origin = (attribute(chunk, "x", None)?, attribute(chunk, "y", None)?);
+                    Ok((chunk, origin, chunk))
+                })
+                .collect::<Result<_, String>>()?,
+        };
+        for (node, (x, y), sized) in blocks {
+            let size = (
+                attribute(sized, "width", None)?,
+                attribute(sized, "height", None)?,
+            );
+            let text = node
+                .children()
+                .filter(|node| node.is_text())
+                .filter_map(|node| node.text())
+                .collect::<String>();
+            let gids = match encoding {
+                Some("csv") => text
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|gid| !gid.is_empty())
+                    .map(|gid| gid.parse().map_err(|_| format!("{:?} isn't a tile", gid)))
+                    .collect::<Result<Vec<u32>, String>>()?,
+                Some("base64") => super::decode(&text, compression)?,
+                Some(other) => return Err(format!("{} encoding isn't supported", other)),
+                None => node
+                    .children()
+                    .filter(|node| node.has_tag_name("tile"))
+                    .map(|tile| attribute(tile, "gid", Some(0)))
+                    .collect::<Result<Vec<u32>, String>>()?,
+            };
+            super::check_size(&gids, size)?;
+            super::place(&mut tiles, (x, y, size.0), &gids);
+        }
+        Ok(tiles)
+    }
+}
+
+mod tmj {
+    use std::path::Path;
+
+    use serde::Deserialize;
+
+    use super::{Group, Map, TileLayer, Tileset};
+
+    #[derive(Deserialize)]
+    struct JsonMap {
+        #[serde(default = "orthogonal")]
+        orientation: String,
+        tilewidth: u32,
+        tileheight: u32,
+        #[serde(default)]
+        tilesets: Vec<JsonTileset>,
+        #[serde(default)]
+        layers: Vec<JsonLayer>,
+    }
+
+    fn orthogonal() -> String {
+        "orthogonal".to_string()
+    }
+
+    fn one() -> f32 {
+        1.0
+    }
+
+    fn yes() -> bool {
+        true
+    }
+
+    #[derive(Deserialize)]
+    struct JsonTileset {
+        #[serde(default)]
+        firstgid: u32,
+        source: Option<String>,
+        #[serde(default)]
+        name: String,
+        #[serde(default)]
+        tilewidth: u32,
+        #[serde(default)]
+        tileheight: u32,
+        #[serde(default)]
+        spacing: u32,
+        #[serde(default)]
+        margin: u32,
+        #[serde(default)]
+        columns: u32,
+        image: Option<String>,
+    }
+
+    #[derive(Deserialize)]
+    struct JsonLayer {
+        #[serde(rename = "type")]
+        kind: String,
+        #[serde(default)]
+        name: String,
+        #[serde(default = "yes")]
+        visible: bool,
+        #[serde(default = "one")]
+        opacity: f32,
+        #[serde(default)]
+        offsetx: f32,
+        #[serde(default)]
+        offsety: f32,
+        #[serde(default)]
+        width: i32,
+        #[serde(default)]
+        height: i32,
+        data: Option<Data>,
+        encoding: Option<String>,
+        compression: Option<String>,
+        #[serde(default)]
+        chunks: Vec<JsonChunk>,
+        #[serde(default)]
+        layers: Vec<JsonLayer>,
+    }
+
+    #[derive(Deserialize)]
+    struct JsonChunk {
+        x: i32,
+        y: i32,
+        width: i32,
+        height: i32,
+        data: Data,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Data {
+        Gids(Vec<u32>),
+        Encoded(String),
+    }
+
+    pub fn map<F>(path: &Path, text: &str, read: &F) -> Result<Map, String>
+    where
+        F: Fn(&Path) -> Result<Vec<u8>, String>,
+    {
+        let map: JsonMap = serde_json::from_str(text).map_err(|e| e.to_string())?;
+        if map.orientation != "orthogonal" {
+            return Err(format!("{} maps aren't supported", map.orientation));
+        }
+        let tilesets = map
+            .tilesets
+            .into_iter()
+            .map(|tileset| match &tileset.source {
+                Some(source) => {
+                    let file = super::relative(path, source);
+                    let bytes = read(&file)?;
+                    let text = std::str::from_utf8(&bytes)
+                        .map_err(|e| format!("{}: {}", file.display(), e))?;
+                    match super::extension(&file).as_str() {
+                        "tsx" => Err("tilesets for .tmj maps have to be .tsj".to_string()),
+                        _ => external_tileset(&file, text, tileset.firstgid),
+                    }
+                    .map_err(|e| format!("{}: {}", file.display(), e))
+                }
+                None => convert(path, tileset, None),
+            })
+            .collect::<Result<_, String>>()?;
+        let mut layers = Vec::new();
+        flatten(map.layers, Group::ROOT, &mut layers)?;
+        Ok(Map {
+            tile_size: (map.tilewidth, map.tileheight),
+            tilesets,
+            layers,
+        })
+    }
+
+    pub fn external_tileset(file: &Path, text: &str, first_gid: u32) -> Result<Tileset, String> {
+        let tileset: JsonTileset = serde_json::from_str(text).map_err(|e| e.to_string())?;
+        convert(file, tileset, Some(first_gid))
+    }
+
+    fn convert(
+        file: &Path,
+        tileset: JsonTileset,
+        first_gid: Option<u32>,
+    ) -> Result<Tileset, String> {
+        let Some(image) = tileset.image else {
+            return Err(format!(
+                "{} is a collection of images, which isn't supported",
+                tileset.name
+            ));
+        };
+        if tileset.tilewidth == 0 || tileset.tileheight == 0 {
+            return Err(format!("{} has no tile size", tileset.name));
+        }
+        Ok(Tileset {
+            first_gid: first_gid.unwrap_or(tileset.firstgid),
+            tile_size: (tileset.tilewidth, tileset.tileheight),
+            spacing: tileset.spacing,
+            margin: tileset.margin,
+            columns: tileset.columns,
+            image: super::relative(file, &image),
+            name: tileset.name,
+        })
+    }
+
+    fn flatten(
+        children: Vec<JsonLayer>,
+        group: Group,
+        layers: &mut Vec<TileLayer>,
+    ) -> Result<(), String> {
+        for layer in children {
+            let group = group.child(layer.visible, layer.opacity, (layer.offsetx, layer.offsety));
+            match layer.kind.as_str() {
+                "group" => flatten(layer.layers, group, layers)?,
+                "tilelayer" => {
+                    let name = layer.name.clone();
+                    let tiles = tiles(layer).map_err(|e| format!("{}: {}", name, e))?;
+                    layers.push(TileLayer {
+                        name,
+                        visible: group.visible,
+                        opacity: group.opacity,
+                        offset: group.offset,
+                        tiles,
+                    });
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn tiles(layer: JsonLayer) -> Result<Vec<(i32, i32, u32)>, String> {
+        let compression = layer.compression.as_deref();
+        let gids = |data: Data| match data {
+            Data::Gids(gids) => Ok(gids),
+            Data::Encoded(text) => match layer.encoding.as_deref() {
+                Some("base64") => super::decode(&text, compression),
+                _ => Err("layer data is a string but isn't base64".to_string()),
+            },
+        };
+        let mut tiles = Vec::new();
+        // infinite maps come in chunks; finite ones are one big block.
+        if let Some(data) = layer.data {
+            let data = gids(data)?;
+            super::check_size(&data, (layer.width, layer.height))?;
+            super::place(&mut tiles, (0, 0, layer.width), &data);
+        }
+        for chunk in layer.chunks {
+            let data = gids(chunk.data)?;
+            super::check_size(&data, (chunk.width, chunk.height))?;
+            super::place(&mut tiles, (chunk.x, chunk.y, chunk.width), &data);
+        }
+        Ok(tiles)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use super::*;
+
+    // the tilesets are all one 64x64 image of four 32x32 tiles, which parsing never reads.
+    const SIZES: &[(u32, u32)] = &[(64, 64)];
+
+    fn parse(name: &str, text: &str) -> Result<Map, String> {
+        Map::parse(Path::new(name), text.as_bytes(), &|path: &Path| {
+            Err(format!("{} isn't there", path.display()))
+        })
+    }
+
+    // a map with one layer of `data`, which is everything inside the <data> element.
+    fn tmx(tileset: &str, data: &str) -> String {
+        format!(
+            r#"<map orientation="orthogonal" tilewidth="32" tileheight="32">
+                <tileset firstgid="1" name="test" {}>
+                    <image source="test.png" width="64" height="64"/>
+                </tileset>
+                <layer name="ground" width="2" height="2">{}</layer>
+            </map>"#,
+            tileset, data
+        )
+    }
+
+    fn csv(data: &str) -> String {
+        tmx(
+            r#"tilewidth="32" tileheight="32" columns="2""#,
+            &format!(r#"<data encoding="csv">{}</data>"#, data),
+        )
+    }
+
+    fn tiles(map: &Map) -> Vec<(i32, i32, u32)> {
+        map.layers[0].tiles.clone()
+    }
+
+    // a quad's top left corner: where it is and where it samples from.
+    fn top_left(quads: &Quads, chunk: (i32, i32), quad: usize) -> ([f32; 2], [f32; 2]) {
+        let vertex = quads[&(chunk.0, chunk.1, 0)][quad * 4];
+        ([vertex.position[0], vertex.position[1]], vertex.tex_coords)
+    }
+
+    #[test]
+    fn csv_layers() {
+        let map = parse("test.tmx", &csv("1,2,\n0,4")).unwrap();
+        assert_eq!(map.tile_size, (32, 32));
+        assert_eq!(map.layers[0].name, "ground");
+        assert_eq!(tiles(&map), [(0, 0, 1), (1, 0, 2), (0, 1, 0), (1, 1, 4)]);
+
+        let quads = quads(&map.layers[0], &map, SIZES).unwrap();
+        // the empty tile doesn't get a quad.
+        assert_eq!(quads[&(0, 0, 0)].len(), 3 * 4);
+        assert_eq!(top_left(&quads, (0, 0), 0), ([0.0, 0.0], [0.0, 0.0]));
+        assert_eq!(top_left(&quads, (0, 0), 1), ([32.0, 0.0], [0.5, 0.0]));
+        assert_eq!(top_left(&quads, (0, 0), 2), ([32.0, 32.0], [0.5, 0.5]));
+    }
+
+    #[test]
+    fn base64_zlib_layers() {
+        let gids: Vec<u8> = [1u32, 2, 0, 4]
+            .iter()
+            .flat_map(|gid| gid.to_le_bytes())
+            .collect();
+        let mut encoder =
+            flate2::write::ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(&gids).unwrap();
+        let data = base64::encode(encoder.finish().unwrap());
+
+        let map = parse(
+            "test.tmx",
+            &tmx(
+                r#"tilewidth="32" tileheight="32""#,
+                &format!(
+                    r#"<data encoding="base64" compression="zlib">
+                        {}
+                    </data>"#,
+                    data
+                ),
+            ),
+        )
+        .unwrap();
+        assert_eq!(
+            tiles(&map),
+            tiles(&parse("test.tmx", &csv("1,2,0,4")).unwrap())
+        );
+
+        let json = format!(
+            r#"{{
+                "tilewidth": 32, "tileheight": 32,
+                "tilesets": [{{ "firstgid": 1, "name": "test", "tilewidth": 32,
+                    "tileheight": 32, "image": "test.png" }}],
+                "layers": [{{ "type": "tilelayer", "name": "ground", "width": 2, "height": 2,
+                    "encoding": "base64", "compression": "zlib", "data": "{}" }}]
+            }}"#,
+            data
+        );
+        assert_eq!(tiles(&parse("test.tmj", &json).unwrap()), tiles(&map));
+    }
+
+    #[test]
+    fn infinite_maps() {
+        let map = parse(
+            "test.tmx",
+            &tmx(
+                r#"tilewidth="32" tileheight="32""#,
+                r#"<data encoding="csv">
+                    <chunk x="-16" y="0" width="2" height="1">1,2</chunk>
+                    <chunk x="16" y="16" width="1" height="1">3</chunk>
+                </data>"#,
+            ),
+        )
+        .unwrap();
+        assert_eq!(tiles(&map), [(-16, 0, 1), (-15, 0, 2), (16, 16, 3)]);
+
+        let quads = quads(&map.layers[0], &map, SIZES).unwrap();
+        assert_eq!(quads.len(), 2);
+        assert_eq!(top_left(&quads, (-1, 0), 0).0, [-512.0, 0.0]);
+        assert_eq!(top_left(&quads, (1, 1), 0), ([512.0, 512.0], [0.0, 0.5]));
+
+        let json = r#"{
+            "tilewidth": 32, "tileheight": 32,
+            "tilesets": [{ "firstgid": 1, "name": "test", "tilewidth": 32, "tileheight": 32,
+                "image": "test.png" }],
+            "layers": [{ "type": "tilelayer", "name": "ground", "chunks": [
+                { "x": -16, "y": 0, "width": 2, "height": 1, "data": [1, 2] },
+                { "x": 16, "y": 16, "width": 1, "height": 1, "data": [3] }
+            ] }]
+        }"#;
+        assert_eq!(tiles(&parse("test.tmj", json).unwrap()), tiles(&map));
+    }
+
+    #[test]
+    fn flipped_tiles() {
+        let across = FLIPPED_HORIZONTALLY | 1;
+        let up_and_down = FLIPPED_VERTICALLY | 1;
+        let diagonally = FLIPPED_DIAGONALLY | 2;
+        let map = parse(
+            "test.tmx",
+            &csv(&format!("{},{},{},0", across, up_and_down, diagonally)),
+        )
+        .unwrap();
+        let quads = quads(&map.layers[0], &map, SIZES).unwrap();
+        let uv = |quad: usize| {
+            let vertices = &quads[&(0, 0, 0)][quad * 4..quad * 4 + 4];
+            vertices
+                .iter()
+                .map(|vertex| vertex.tex_coords)
+                .collect::<Vec<_>>()
+        };
+        // top left, bottom left, bottom right, top right.
+        assert_eq!(uv(0), [[0.5, 0.0], [0.5, 0.5], [0.0, 0.5], [0.0, 0.0]]);
+        assert_eq!(uv(1), [[0.0, 0.5], [0.0, 0.0], [0.5, 0.0], [0.5, 0.5]]);
+        assert_eq!(uv(2), [[0.5, 0.0], [1.0, 0.0], [1.0, 0.5], [0.5, 0.5]]);
+    }
+
+    #[test]
+    fn tiles_past_the_end() {
+        let map = parse("test.tmx", &csv("1,2,4,5")).unwrap();
+        let error = quads(&map.layers[0], &map, SIZES).err().unwrap();
+        assert!(error.contains("past the end"), "{}", error);
+
+        // nothing at all fits in an image smaller than a tile.
+        let map = parse(
+            "test.tmx",
+            &tmx(
+                r#"tilewidth="32" tileheight="32""#,
+                r#"<data encoding="csv">1,0,0,0</data>"#,
+            ),
+        )
+        .unwrap();
+        let error = quads(&map.layers[0], &map, &[(16, 16)]).err().unwrap();
+        assert!(error.contains("past the end"), "{}", error);
+    }
+
+    #[test]
+    fn no_tile_size() {
+        let error = parse(
+            "test.tmx",
+            &tmx(
+                r#"tilewidth="0" tileheight="32""#,
+                r#"<data encoding="csv">1,0,0,0</data>"#,
+            ),
+        )
+        .err()
+        .unwrap();
+        assert!(error.contains("no tile size"), "{}", error);
+
+        let json = r#"{
+            "tilewidth": 32, "tileheight": 0,
+            "layers": [{ "type": "tilelayer", "name": "ground", "width": 1, "height": 1,
+                "data": [0] }]
+        }"#;
+        let error = parse("test.tmj", json).err().unwrap();
+        assert!(error.contains("no tile size"), "{}", error);
+    }
+
+    #[test]
+    fn overflows() {
+        // more tiles than a u32 can count.
+        let map = parse(
+            "test.tmx",
+            &tmx(
+                r#"tilewidth="32" tileheight="32" columns="4294967295""#,
+                r#"<data encoding="csv">1,0,0,0</data>"#,
+            ),
+        )
+        .unwrap();
+        let error = quads(&map.layers[0], &map, SIZES).err().unwrap();
+        assert!(error.contains("too big"), "{}", error);
+
+        // a tile too far into its image to say where.
+        let map = parse(
+            "test.tmx",
+            &tmx(
+                r#"tilewidth="32" tileheight="32" spacing="2147483648" columns="3""#,
+                r#"<data encoding="csv">3,0,0,0</data>"#,
+            ),
+        )
+        .unwrap();
+        let error = quads(&map.layers[0], &map, &[(i32::MAX as u32, i32::MAX as u32)])
+            .err()
+            .unwrap();
+        assert!(error.contains("too big"), "{}", error);
+
+        // and one too far out on the map.
+        let map = parse(
+            "test.tmx",
+            &tmx(
+                r#"tilewidth="32" tileheight="32""#,
+                r#"<data encoding="csv">
+                    <chunk x="100000000" y="0" width="1" height="1">1</chunk>
+                </data>"#,
+            ),
+        )
+        .unwrap();
+        let error = quads(&map.layers[0], &map, SIZES).err().unwrap();
+        assert!(error.contains("too far out"), "{}", error);
+    }
+
+    #[test]
+    fn demo_map() {
+        let map = parse("maps/demo.tmx", include_str!("../maps/demo.tmx")).unwrap();
+        let names: Vec<&str> = map.layers.iter().map(|layer| layer.name.as_str()).collect();
+        assert_eq!(names, ["ground", "decoration"]);
+        assert_eq!(
+            map.tilesets[0].image,
+            Path::new("maps/../textures/test.png")
+        );
+        for layer in &map.layers {
+            quads(layer, &map, SIZES).unwrap();
+        }
+    }
+}
@@ -1,172 +1,705 @@
-use std::mem::size_of;
+use cgmath::SquareMatrix;
 
-use cgmath::{Matrix4, SquareMatrix};
-use wgpu::util::DeviceExt;
+use super::{atlas, model, resource, text, texture};
 
-use super::{model, texture};
+// the ui is laid out in logical pixels (physical ones divided by the window's scale factor), from
+// the top left corner of the window with y going down.
+//
+// elements (and texts) make a tree through their parents. children are laid out in their parent's
+// rectangle, take on its scale and opacity, and are drawn after it, so they're on top; see
+// draw_order(). there's no depth test, so that's the only thing deciding what's in front.
 
+// a point on a rectangle, as shares of its size from the top left corner.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Anchor {
+    pub x: f32,
+    pub y: f32,
+}
+
+impl Anchor {
+    pub const TOP_LEFT: Self = Self { x: 0.0, y: 0.0 };
+    pub const TOP: Self = Self { x: 0.5, y: 0.0 };
+    pub const TOP_RIGHT: Self = Self { x: 1.0, y: 0.0 };
+    pub const LEFT: Self = Self { x: 0.0, y: 0.5 };
+    pub const CENTRE: Self = Self { x: 0.5, y: 0.5 };
+    pub const RIGHT: Self = Self { x: 1.0, y: 0.5 };
+    pub const BOTTOM_LEFT: Self = Self { x: 0.0, y: 1.0 };
+    pub const BOTTOM: Self = Self { x: 0.5, y: 1.0 };
+    pub const BOTTOM_RIGHT: Self = Self { x: 1.0, y: 1.0 };
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Length {
+    Pixels(f32),
+    Relative(f32), // a share of the parent's, so 1.0 fills it.
+}
+
+impl Length {
+    pub fn resolve(self, parent: f32) -> f32 {
+        match self {
+            Length::Pixels(pixels) => pixels,
+            Length::Relative(share) => share * parent,
+        }
+    }
+
+    // lengths of different kinds can't be blended without knowing the parent, so those jump.
+    fn lerp(self, to: Self, amount: f32) -> Self {
+        match (self, to) {
+            (Length::Pixels(from), Length::Pixels(to)) => {
+                Length::Pixels(from + (to - from) * amount)
+            }
+            (Length::Relative(from), Length::Relative(to)) => {
+                Length::Relative(from + (to - from) * amount)
+            }
+            _ => to,
+        }
+    }
+
+    fn is_valid(self) -> bool {
+        let (Length::Pixels(length) | Length::Relative(length)) = self;
+        length >= 0.0 && length.is_finite()
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Size {
+    pub width: Length,
+    pub height: Length,
+}
+
+impl Size {
+    pub fn pixels(width: f32, height: f32) -> Self {
+        Self {
+            width: Length::Pixels(width),
+            height: Length::Pixels(height),
+        }
+    }
+
+    pub fn relative(width: f32, height: f32) -> Self {
+        Self {
+            width: Length::Relative(width),
+            height: Length::Relative(height),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Rect {
+    pub x: f32, // the top left corner.
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Rect {
+    pub fn point(&self, anchor: Anchor) -> (f32, f32) {
+        (
+            self.x + self.width * anchor.x,
+            self.y + self.height * anchor.y,
+        )
+    }
+
+    pub fn contains(&self, (x, y): (f32, f32)) -> bool {
+        x >= self.x && y >= self.y && x < self.x + self.width && y < self.y + self.height
+    }
+
+    // the overlap, which is empty (but still somewhere) if there isn't one.
+    pub fn intersection(&self, other: &Rect) -> Rect {
+        let x = self.x.max(other.x);
+        let y = self.y.max(other.y);
+        Rect {
+            x,
+            y,
+            width: ((self.x + self.width).min(other.x + other.width) - x).max(0.0),
+            height: ((self.y + self.height).min(other.y + other.height) - y).max(0.0),
+        }
+    }
+
+    // the smallest rectangle around this one once it's been through `transform`.
+    fn bounds(&self, transform: cgmath::Matrix4<f32>) -> Rect {
+        let corners = [
+            (self.x, self.y),
+            (self.x + self.width, self.y),
+            (self.x, self.y + self.height),
+            (self.x + self.width, self.y + self.height),
+        ]
+        .map(|(x, y)| transform * cgmath::Vector4::new(x, y, 0.0, 1.0));
+        let (mut min, mut max) = ((f32::MAX, f32::MAX), (f32::MIN, f32::MIN));
+        for corner in corners {
+            min = (min.0.min(corner.x), min.1.min(corner.y));
+            max = (max.0.max(corner.x), max.1.max(corner.y));
+        }
+        Rect {
+            x: min.0,
+            y: min.1,
+            width: max.0 - min.0,
+            height: max.1 - min.1,
+        }
+    }
+
+    // a rectangle of the given size inside this one, with its pivot `offset` pixels away from
+    // the anchor.
+    pub fn place(
+        &self,
+        anchor: Anchor,
+        pivot: Anchor,
+        offset: cgmath::Point2<f32>,
+        (width, height): (f32, f32),
+    ) -> Rect {
+        let (x, y) = self.point(anchor);
+        Rect {
+            x: x + offset.x - width * pivot.x,
+            y: y + offset.y - height * pivot.y,
+            width,
+            height,
+        }
+    }
+}
+
+// nine-slice scaling: how far in from each edge of the texture (or its region) the corners go, in
+// texture pixels. the corners keep their size, the edges stretch along their length and the
+// middle stretches both ways, so a panel's border looks the same however big the panel is.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Slice {
+    pub left: f32,
+    pub right: f32,
+    pub top: f32,
+    pub bottom: f32,
+}
+
+// what an element hands down to everything under it.
+#[derive(Copy, Clone, Debug)]
+pub struct Inherited {
+    pub rect: Rect, // what they're laid out in.
+    // from where they're laid out to where they end up on the window.
+    pub transform: cgmath::Matrix4<f32>,
+    pub opacity: f32,
+    pub clip: Option<Rect>, // on the window, in logical pixels.
+}
+
+impl Inherited {
+    // what the roots of the tree get.
+    pub fn window(window: Rect) -> Self {
+        Self {
+            rect: window,
+            transform: cgmath::Matrix4::identity(),
+            opacity: 1.0,
+            clip: None,
+        }
+    }
+}
+
+// what something with the given parent inherits; parents have to come earlier in the list, and
+// anything else (including None) goes by the whole window.
+pub fn inherited(earlier: &[Element], parent: Option<usize>, window: Rect) -> Inherited {
+    parent
+        .and_then(|parent| earlier.get(parent))
+        .map(Element::inherited)
+        .unwrap_or(Inherited::window(window))
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Node {
+    Element(usize), // index into the state's ui elements.
+    Text(usize),    // and into its texts.
+}
+
+// parents before their children, and siblings from the lowest z to the highest. siblings with the
+// same z go in the order they were added, elements before texts.
+pub fn draw_order(elements: &[Element], texts: &[text::Text]) -> Vec<Node> {
+    let mut roots = Vec::new();
+    let mut children = vec![Vec::new(); elements.len()];
+    let nodes = elements
+        .iter()
+        .enumerate()
+        .map(|(i, element)| {
+            (
+                element.parent.filter(|parent| *parent < i),
+                element.z,
+                Node::Element(i),
+            )
+        })
+        .chain(texts.iter().enumerate().map(|(i, text)| {
+            (
+                text.parent.filter(|parent| *parent < elements.len()),
+                text.z,
+                Node::Text(i),
+            )
+        }));
+    for (parent, z, node) in nodes {
+        match parent {
+            Some(parent) => children[parent].push((z, node)),
+            None => roots.push((z, node)),
+        }
+    }
+
+    fn visit(
+        siblings: &mut [(i32, Node)],
+        children: &mut [Vec<(i32, Node)>],
+        order: &mut Vec<Node>,
+    ) {
+        siblings.sort();
+        for (_, node) in siblings.iter() {
+            order.push(*node);
+            if let Node::Element(element) = *node {
+                let mut under = std::mem::take(&mut children[element]);
+                visit(&mut under, children, order);
+            }
+        }
+    }
+    let mut order = Vec::with_capacity(elements.len() + texts.len());
+    visit(&mut roots, &mut children, &mut order);
+    order
+}
+
+// where an element was put, and what it hands down to its children.
+#[derive(Copy, Clone, Debug)]
+pub struct Placed {
+    pub rect: Rect,               // before it (or its parents) got scaled or turned.
+    pub clipped_to: Option<Rect>, // what its parents cut it off at.
+    pub inherited: Inherited,
+}
+
+impl Placed {
+    // a point on the window (in logical pixels) in the space it was laid out in, or None if it's
+    // clipped off there.
+    pub fn to_local(self, (x, y): (f32, f32)) -> Option<(f32, f32)> {
+        if self.clipped_to.is_some_and(|clip| !clip.contains((x, y))) {
+            return None;
+        }
+        let point = self.inherited.transform.invert()? * cgmath::Vector4::new(x, y, 0.0, 1.0);
+        Some((point.x, point.y))
+    }
+
+    pub fn contains(&self, point: (f32, f32)) -> bool {
+        self.to_local(point)
+            .is_some_and(|point| self.rect.contains(point))
+    }
+}
+
+// the elements laid out where they are as of the last tick, without blending towards the next
+// one. anything that asks what's under the pointer goes by this rather than by what was drawn
+// last, so the answer doesn't depend on the frame rate (and a replay gets the same one).
+#[derive(Default)]
+pub struct Layout {
+    placed: Vec<Placed>,
+    order: Vec<usize>, // indices into placed, in drawing order.
+}
+
+impl Layout {
+    pub fn new(elements: &[Element], window: Rect) -> Self {
+        let mut placed: Vec<Placed> = Vec::with_capacity(elements.len());
+        for (i, element) in elements.iter().enumerate() {
+            let parent = element
+                .parent
+                .filter(|parent| *parent < i)
+                .map_or(Inherited::window(window), |parent| placed[parent].inherited);
+            placed.push(element.place(1.0, parent));
+        }
+        let order = draw_order(elements, &[])
+            .into_iter()
+            .filter_map(|node| match node {
+                Node::Element(i) => Some(i),
+                Node::Text(_) => None,
+            })
+            .collect();
+        Self { placed, order }
+    }
+
+    // None for elements that were added since it was laid out.
+    pub fn get(&self, element: usize) -> Option<&Placed> {
+        self.placed.get(element)
+    }
+
+    // the topmost element over a point on the window, in logical pixels.
+    pub fn element_at(&self, point: (f32, f32)) -> Option<usize> {
+        self.order
+            .iter()
+            .rev()
+            .copied()
+            .find(|i| self.placed[*i].contains(point))
+    }
+}
+
+// a textured rectangle. by default it's the size of its texture, in the top left corner.
 pub struct Element {
     pub label: String,
-    pub position: cgmath::Point2<f32>,
-    pub scale: (f32, f32),
-    pub mesh: model::Mesh,
+    pub anchor: Anchor,                // where on the parent it goes.
+    pub pivot: Anchor,                 // which point of the element goes there.
+    pub position: cgmath::Point2<f32>, // from the anchor to the pivot, in pixels.
+    pub size: Size,
+    pub parent: Option<usize>, // an index into the state's ui elements.
+    pub z: i32,                // higher goes over its siblings.
+    pub scale: f32,            // about the pivot, along with everything under it.
+    pub rotation: cgmath::Deg<f32>, // same, clockwise.
+    pub opacity: f32,          // same again.
+    pub tint: [f32; 4],        // multiplies the texture, but only on this element.
+    pub clip: bool,            // whether to cut off what's under it at its edges.
+    // the part of the texture that's shown, in texture pixels; None for all of it.
+    pub region: Option<Rect>,
+    pub slice: Option<Slice>,
+    previous_position: cgmath::Point2<f32>,
+    previous_size: Size,
+    previous_scale: f32,
+    previous_rotation: cgmath::Deg<f32>,
+    placed: Placed, // as of the last frame.
+    // where it ends up on the window, cut into three by three for nine-slicing; see INDICES.
+    vertices: [model::ModelVertex; 16],
     pub material: model::Material, // つづ: support for alternate materials.
-    pub transformation_uniform: [[f32; 4]; 4],
-    pub transformation_buffer: wgpu::Buffer,
-    pub bind_group: wgpu::BindGroup,
 }
 
-impl Element {
-    pub fn new(
-        label: String,
-        device: &wgpu::Device,
-        texture: texture::Texture,
-        position: Option<cgmath::Point2<f32>>, // つづ: should these be options?
-        //rotation: Option<cgmath::Quaternion<f32>>,
-        scale: Option<(f32, f32)>,
-    ) -> Self {
-        /*let vertices: [model::ModelVertex; 4] = [
-            model::ModelVertex {
-                // top left
-                position: [-1.0, -1.0, 0.0],
-                tex_coords: [0.0, 0.0],
-            },
-            model::ModelVertex {
-                // bottom left
-                position: [-1.0, 1.0, 0.0],
-                tex_coords: [0.0, 1.0],
-            },
-            model::ModelVertex {
-                // bottom right
-                position: [-1.0, -1.0, 0.0],
-                tex_coords: [1.0, 1.0],
-            },
-            model::ModelVertex {
-                // top right
-                position: [1.0, 1.0, 0.0],
-                tex_coords: [1.0, 0.0],
-            },
-        ];
-        let indices: [u32; 6] = [0, 1, 2, 3, 0, 2];*/
-        let vertices: [model::ModelVertex; 4] = [
-            model::ModelVertex {
-                // top left
-                position: [-1.0, 1.0, 0.0],
-                tex_coords: [0.0, 0.0],
-            },
-            model::ModelVertex {
-                // bottom left
-                position: [-1.0, -1.0, 0.0],
-                tex_coords: [0.0, 1.0],
-            },
-            model::ModelVertex {
-                // bottom right
-                position: [1.0, -1.0, 0.0],
-                tex_coords: [1.0, 1.0],
-            },
-            model::ModelVertex {
-                // top right
-                position: [1.0, 1.0, 0.0],
-                tex_coords: [1.0, 0.0],
-            },
-        ];
-        let indices: [u32; 6] = [0, 1, 2, 2, 3, 0];
-        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some(&format!("{} vertex buffer", label)),
-            contents: bytemuck::cast_slice(&vertices),
-            usage: wgpu::BufferUsages::VERTEX,
-        });
-        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some(&format!("{} index buffer", label)),
-            contents: bytemuck::cast_slice(&indices),
-            usage: wgpu::BufferUsages::INDEX,
-        });
-        let mesh = model::Mesh {
-            label: format!("{} mesh", label),
-            vertex_buffer,
-            index_buffer,
-            num_elements: indices.len() as u32,
-            material: 0,
-        };
+pub struct ElementBuilder {
+    label: String,
+    texture: texture::Texture,
+    anchor: Anchor,
+    pivot: Anchor,
+    position: cgmath::Point2<f32>,
+    size: Option<Size>,
+    parent: Option<usize>,
+    z: i32,
+    scale: f32,
+    rotation: cgmath::Deg<f32>,
+    opacity: f32,
+    tint: [f32; 4],
+    clip: bool,
+    region: Option<Rect>,
+    slice: Option<Slice>,
+}
 
-        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some(&format!("{} bind group", label)),
-            layout: &texture::Texture::layout(device),
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&texture.view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: wgpu::BindingResource::Sampler(&texture.sampler),
-                },
-            ],
-        });
-        let material = model::Material {
-            label: format!("{} material", label),
-            texture,
-            bind_group,
-        };
+impl ElementBuilder {
+    pub fn position<P: Into<cgmath::Point2<f32>>>(mut self, position: P) -> Self {
+        self.position = position.into();
+        self
+    }
 
-        let transformation_uniform = Matrix4::identity().into();
+    pub fn anchor(mut self, anchor: Anchor) -> Self {
+        self.anchor = anchor;
+        self
+    }
 
-        let transformation_buffer = device.create_buffer(&wgpu::BufferDescriptor {
-            label: Some(&format!("{} element buffer", label)),
-            size: size_of::<[[f32; 4]; 4]>() as u64,
-            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-            mapped_at_creation: false,
-        });
+    pub fn pivot(mut self, pivot: Anchor) -> Self {
+        self.pivot = pivot;
+        self
+    }
+
+    pub fn size(mut self, size: Size) -> Self {
+        self.size = Some(size);
+        self
+    }
+
+    // the parent has to come before the element in the state's ui elements.
+    pub fn parent(mut self, parent: usize) -> Self {
+        self.parent = Some(parent);
+        self
+    }
+
+    pub fn z(mut self, z: i32) -> Self {
+        self.z = z;
+        self
+    }
+
+    pub fn scale(mut self, scale: f32) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    pub fn rotation<A: Into<cgmath::Deg<f32>>>(mut self, rotation: A) -> Self {
+        self.rotation = rotation.into();
+        self
+    }
 
-        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            label: Some(&format!("{} bind group", &label)),
-            layout: &Element::layout(&device),
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: transformation_buffer.as_entire_binding(),
-            }],
+    pub fn opacity(mut self, opacity: f32) -> Self {
+        self.opacity = opacity;
+        self
+    }
+
+    pub fn tint(mut self, tint: [f32; 4]) -> Self {
+        self.tint = tint;
+        self
+    }
+
+    pub fn clip(mut self, clip: bool) -> Self {
+        self.clip = clip;
+        self
+    }
+
+    // the default size becomes the region's.
+    pub fn region(mut self, region: Rect) -> Self {
+        self.region = Some(region);
+        self
+    }
+
+    pub fn slice(mut self, slice: Slice) -> Self {
+        self.slice = Some(slice);
+        self
+    }
+
+    pub fn build(self, device: &wgpu::Device) -> Result<Element, String> {
+        let ElementBuilder {
+            label,
+            texture,
+            anchor,
+            pivot,
+            position,
+            size,
+            parent,
+            z,
+            scale,
+            rotation,
+            opacity,
+            tint,
+            clip,
+            region,
+            slice,
+        } = self;
+        let texture_size = texture.texture.size();
+        let whole = Rect {
+            width: texture_size.width as f32,
+            height: texture_size.height as f32,
+            ..Default::default()
+        };
+        if let Some(region) = region {
+            if region.width <= 0.0 || region.height <= 0.0 || whole.intersection(&region) != region
+            {
+                return Err(format!("{}: region has to be inside the texture", label));
+            }
+        }
+        let size = size.unwrap_or_else(|| {
+            let region = region.unwrap_or(whole);
+            Size::pixels(region.width, region.height)
         });
-        Self {
+        if !size.width.is_valid() || !size.height.is_valid() {
+            return Err(format!("{}: size has to be finite and positive", label));
+        }
+        if let Some(slice) = slice {
+            let Slice {
+                left,
+                right,
+                top,
+                bottom,
+            } = slice;
+            let region = region.unwrap_or(whole);
+            if [left, right, top, bottom].iter().any(|edge| *edge < 0.0)
+                || left + right > region.width
+                || top + bottom > region.height
+            {
+                return Err(format!("{}: slice has to fit in the texture", label));
+            }
+        }
+
+        let material = resource::create_material(
+            format!("{} material", label),
+            texture,
+            device,
+            &texture::Texture::layout(device),
+        );
+        Ok(Element {
             label,
-            position: position.unwrap_or((0.0, 0.0).into()),
-            scale: scale.unwrap_or((1.0, 1.0)),
-            mesh,
+            anchor,
+            pivot,
+            position,
+            size,
+            parent,
+            z,
+            scale,
+            rotation,
+            opacity,
+            tint,
+            clip,
+            region,
+            slice,
+            previous_position: position,
+            previous_size: size,
+            previous_scale: scale,
+            previous_rotation: rotation,
+            placed: Placed {
+                rect: Rect::default(),
+                clipped_to: None,
+                inherited: Inherited::window(Rect::default()),
+            },
+            vertices: [model::ModelVertex::default(); 16],
             material,
-            transformation_uniform,
-            transformation_buffer,
-            bind_group,
-        }
-    }
-
-    pub fn layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
-        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            label: Some("element bind group layout"),
-            entries: &[wgpu::BindGroupLayoutEntry {
-                binding: 0,
-                visibility: wgpu::ShaderStages::VERTEX,
-                ty: wgpu::BindingType::Buffer {
-                    ty: wgpu::BufferBindingType::Uniform,
-                    has_dynamic_offset: false,
-                    min_binding_size: None,
-                },
-                count: None,
-            }],
         })
     }
+}
 
-    pub fn update(&mut self, queue: &wgpu::Queue) {
-        use cgmath::EuclideanSpace;
-        // つづ: there has to be a way to optimize this...
-        self.transformation_uniform =
-            (cgmath::Matrix4::from_translation(
-                cgmath::Point3::from((self.position.x, self.position.y, 1.0)).to_vec(),
-            ) * cgmath::Matrix4::from_nonuniform_scale(self.scale.0, self.scale.1, 1.0))
-            .into();
+impl Element {
+    pub fn builder<L: Into<String>>(label: L, texture: texture::Texture) -> ElementBuilder {
+        ElementBuilder {
+            label: label.into(),
+            texture,
+            anchor: Anchor::TOP_LEFT,
+            pivot: Anchor::TOP_LEFT,
+            position: (0.0, 0.0).into(),
+            size: None,
+            parent: None,
+            z: 0,
+            scale: 1.0,
+            rotation: cgmath::Deg(0.0),
+            opacity: 1.0,
+            tint: [1.0; 4],
+            clip: false,
+            region: None,
+            slice: None,
+        }
+    }
+
+    // shows an image out of an atlas, at its own size unless given another.
+    pub fn from_region<L: Into<String>>(label: L, region: &atlas::Region) -> ElementBuilder {
+        Self::builder(label, region.texture.clone()).region(region.rect)
+    }
 
-        queue.write_buffer(
-            &self.transformation_buffer,
-            0,
-            bytemuck::cast_slice(&[self.transformation_uniform]),
+    // same as object::Object::snap().
+    pub fn snap(&mut self) {
+        self.previous_position = self.position;
+        self.previous_size = self.size;
+        self.previous_scale = self.scale;
+        self.previous_rotation = self.rotation;
+    }
+
+    pub fn inherited(&self) -> Inherited {
+        self.placed.inherited
+    }
+
+    pub fn clipped_to(&self) -> Option<Rect> {
+        self.placed.clipped_to
+    }
+
+    // shows a different image, keeping everything else. cheap to call with the same texture
+    // over and over; the material is only rebuilt when the texture actually changes.
+    pub fn set_texture(&mut self, device: &wgpu::Device, texture: &texture::Texture) {
+        if std::sync::Arc::ptr_eq(&self.material.texture.texture, &texture.texture) {
+            return;
+        }
+        self.material = resource::create_material(
+            format!("{} material", self.label),
+            texture.clone(),
+            device,
+            &texture::Texture::layout(device),
         );
     }
+
+    // see inherited().
+    pub fn update(&mut self, blend: f32, parent: Inherited) {
+        self.placed = self.place(blend, parent);
+        self.cut(
+            self.placed.inherited.transform,
+            self.placed.inherited.opacity,
+        );
+    }
+
+    // where it goes `blend` of the way from the last tick to this one.
+    fn place(&self, blend: f32, parent: Inherited) -> Placed {
+        let position = self.previous_position + (self.position - self.previous_position) * blend;
+        let width = self.previous_size.width.lerp(self.size.width, blend);
+        let height = self.previous_size.height.lerp(self.size.height, blend);
+        let scale = self.previous_scale + (self.scale - self.previous_scale) * blend;
+        let rotation = self.previous_rotation + (self.rotation - self.previous_rotation) * blend;
+        let rect = parent.rect.place(
+            self.anchor,
+            self.pivot,
+            position,
+            (
+                width.resolve(parent.rect.width),
+                height.resolve(parent.rect.height),
+            ),
+        );
+
+        let (x, y) = rect.point(self.pivot);
+        let transform = parent.transform
+            * cgmath::Matrix4::from_translation(cgmath::Vector3::new(x, y, 0.0))
+            * cgmath::Matrix4::from_angle_z(rotation)
+            * cgmath::Matrix4::from_scale(scale)
+            * cgmath::Matrix4::from_translation(cgmath::Vector3::new(-x, -y, 0.0));
+        let bounds = rect.bounds(transform);
+        Placed {
+            rect,
+            clipped_to: parent.clip,
+            inherited: Inherited {
+                rect,
+                transform,
+                opacity: parent.opacity * self.opacity.clamp(0.0, 1.0),
+                clip: match self.clip {
+                    true => Some(
+                        parent
+                            .clip
+                            .map_or(bounds, |clip| clip.intersection(&bounds)),
+                    ),
+                    false => parent.clip,
+                },
+            },
+        }
+    }
+
+    pub fn vertices(&self) -> &[model::ModelVertex] {
+        &self.vertices
+    }
+
+    // lays the vertices out over the rectangle, moving the slices (and texture coordinates) to
+    // fit the region and slice. the corners stay the same number of pixels across.
+    fn cut(&mut self, transform: cgmath::Matrix4<f32>, opacity: f32) {
+        let texture_size = self.material.texture.texture.size();
+        let texture_size = (texture_size.width as f32, texture_size.height as f32);
+        let region = self.region.unwrap_or(Rect {
+            width: texture_size.0,
+            height: texture_size.1,
+            ..Default::default()
+        });
+        let slice = self.slice.unwrap_or_default();
+        // corners that don't fit get squashed, keeping their proportions to each other.
+        let cuts = |near: f32, far: f32, length: f32| {
+            let fit = match near + far > length {
+                true => length / (near + far),
+                false => 1.0,
+            };
+            [0.0, near * fit, length - far * fit, length]
+        };
+        let xs = cuts(slice.left, slice.right, self.placed.rect.width);
+        let ys = cuts(slice.top, slice.bottom, self.placed.rect.height);
+        let us = [0.0, slice.left, region.width - slice.right, region.width]
+            .map(|x| (region.x + x) / texture_size.0);
+        let vs = [0.0, slice.top, region.height - slice.bottom, region.height]
+            .map(|y| (region.y + y) / texture_size.1);
+
+        let [r, g, b, a] = self.tint;
+        let colour = [r, g, b, a * opacity];
+        let (left, top) = (self.placed.rect.x, self.placed.rect.y);
+        self.vertices = std::array::from_fn(|i| {
+            let (column, row) = (i % 4, i / 4);
+            let position =
+                transform * cgmath::Vector4::new(left + xs[column], top + ys[row], 0.0, 1.0);
+            model::ModelVertex {
+                position: [position.x, position.y, 0.0],
+                tex_coords: [us[column], vs[row]],
+                colour,
+                ..Default::default()
+            }
+        });
+    }
 }
+
+// the three by three grid of quads in Element::vertices(), a row at a time from the top, each
+// wound top left, bottom left, bottom right, top right like everything else.
+pub const INDICES: [u32; 54] = {
+    let mut indices = [0; 54];
+    let mut cell = 0;
+    while cell < 9 {
+        let top_left = (cell / 3 * 4 + cell % 3) as u32;
+        let (bottom_left, top_right) = (top_left + 4, top_left + 1);
+        let quad = [
+            top_left,
+            bottom_left,
+            bottom_left + 1,
+            bottom_left + 1,
+            top_right,
+            top_left,
+        ];
+        let mut corner = 0;
+        while corner < 6 {
+            indices[cell * 6 + corner] = quad[corner];
+            corner += 1;
+        }
+        cell += 1;
+    }
+    indices
+};
@@ -0,0 +1,308 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::input;
+
+// recordings are json lines: a header, then one input::Tick per update. replaying one only gives
+// back the same session if Game::update gets nothing from outside but the ticks, the seed and the
+// window (which the ui is laid out in, and the cursor is checked against), so no clocks, no live
+// input and no unseeded randomness in there.
+#[derive(Serialize, Deserialize)]
+struct Header {
+    seed: u64,
+    updates_per_second: u32,
+    window: Window,
+}
+
+// what the window was like while recording. it has to stay the same the whole way through, both
+// while recording and while replaying, or the cursor ends up over different things.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Window {
+    pub size: (u32, u32), // in physical pixels.
+    pub scale_factor: f64,
+}
+
+pub struct Recorder {
+    writer: BufWriter<File>,
+    window: Window,
+    ticks: u64,
+}
+
+impl Recorder {
+    pub fn create(
+        path: &Path,
+        seed: u64,
+        updates_per_second: u32,
+        window: Window,
+    ) -> Result<Self, String> {
+        let file = File::create(path)
+            .map_err(|error| format!("couldn't create {}: {}", path.display(), error))?;
+        let mut recorder = Self {
+            writer: BufWriter::new(file),
+            window,
+            ticks: 0,
+        };
+        recorder.write_line(&Header {
+            seed,
+            updates_per_second,
+            window,
+        })?;
+        Ok(recorder)
+    }
+
+    // `window` is what it's like as of this tick; a recording can't carry on past a change, since
+    // it wouldn't play back the same.
+    pub fn record(&mut self, tick: &input::Tick, window: Window) -> Result<(), String> {
+        check_window(self.window, window)?;
+        self.write_line(tick)?;
+        self.ticks += 1;
+        Ok(())
+    }
+
+    pub fn ticks(&self) -> u64 {
+        self.ticks
+    }
+
+    // the event loop exits the process without dropping anything, so this has to be called by
+    // hand before then.
+    pub fn flush(&mut self) -> Result<(), String> {
+        self.writer
+            .flush()
+            .map_err(|error| format!("couldn't write the recording: {}", error))
+    }
+
+    fn write_line(&mut self, value: &impl Serialize) -> Result<(), String> {
+        serde_json::to_writer(&mut self.writer, value)
+            .map_err(|error| format!("couldn't write the recording: {}", error))?;
+        self.writer
+            .write_all(b"\n")
+            .map_err(|error| format!("couldn't write the recording: {}", error))
+    }
+}
+
+pub struct Replay {
+    pub seed: u64,
+    pub window: Window, // the one it was recorded in.
+    ticks: std::vec::IntoIter<input::Tick>,
+    played: u64,
+}
+
+impl Replay {
+    // a recording made at a different update rate would play back at the wrong speed (and come
+    // out different anyway), so that's an error rather than something to paper over.
+    pub fn open(path: &Path, updates_per_second: u32) -> Result<Self, String> {
+        let file = File::open(path)
+            .map_err(|error| format!("couldn't open {}: {}", path.display(), error))?;
+        let mut lines = BufReader::new(file).lines();
+        let header: Header = match lines.next() {
+            Some(line) => {
+                let line =
+                    line.map_err(|error| format!("couldn't read the recording: {}", error))?;
+                serde_json::from_str(&line)
+                    .map_err(|error| format!("couldn't read the recording's header: {}", error))?
+            }
+            None => return Err(format!("{} is empty", path.display())),
+        };
+        if header.updates_per_second != updates_per_second {
+            return Err(format!(
+                "the recording was made at {} updates per second, not {}",
+                header.updates_per_second, updates_per_second
+            ));
+        }
+
+        let mut ticks = Vec::new();
+        for (number, line) in lines.enumerate() {
+            let line = line.map_err(|error| format!("couldn't read the recording: {}", error))?;
+            // a recording cut off partway through a line (a crash, say) still plays up to there.
+            match serde_json::from_str(&line) {
+                Ok(tick) => ticks.push(tick),
+                Err(error) => {
+                    log::warn!("recording stops early at tick {}: {}", number, error);
+                    break;
+                }
+            }
+        }
+
+        Ok(Self {
+            seed: header.seed,
+            window: header.window,
+            ticks: ticks.into_iter(),
+            played: 0,
+        })
+    }
+
+    // None once the recording runs out. `window` is what it's like as of this tick, and it has to
+    // be the one it was recorded in.
+    pub fn next_tick(&mut self, window: Window) -> Result<Option<input::Tick>, String> {
+        check_window(self.window, window)?;
+        let Some(tick) = self.ticks.next() else {
+            return Ok(None);
+        };
+        self.played += 1;
+        Ok(Some(tick))
+    }
+
+    pub fn played(&self) -> u64 {
+        self.played
+    }
+}
+
+fn check_window(recorded: Window, now: Window) -> Result<(), String> {
+    if recorded != now {
+        return Err(format!(
+            "the recording was made in a {}x{} window at a scale factor of {}, not {}x{} at {}",
+            recorded.size.0,
+            recorded.size.1,
+            recorded.scale_factor,
+            now.size.0,
+            now.size.1,
+            now.scale_factor
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use winit::event::{MouseButton, VirtualKeyCode};
+
+    use super::*;
+    use crate::input::{Button, MouseAxis, Tick};
+
+    const WINDOW: Window = Window {
+        size: (800, 600),
+        scale_factor: 1.5,
+    };
+
+    // somewhere of its own for each test, since they run at the same time.
+    fn path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("hekox-{}-{}.jsonl", std::process::id(), name))
+    }
+
+    fn ticks() -> Vec<Tick> {
+        vec![
+            Tick::default(),
+            Tick {
+                down: vec![Button::Key(VirtualKeyCode::W)],
+                pressed: vec![Button::Key(VirtualKeyCode::W)],
+                mouse: vec![(MouseAxis::X, 3.5), (MouseAxis::Y, -1.25)],
+                cursor: Some((10.0, 20.5)),
+                text: "w".to_string(),
+                ..Default::default()
+            },
+            Tick {
+                released: vec![
+                    Button::Key(VirtualKeyCode::W),
+                    Button::Mouse(MouseButton::Left),
+                ],
+                ..Default::default()
+            },
+        ]
+    }
+
+    fn record(path: &Path, ticks: &[Tick]) {
+        let mut recorder = Recorder::create(path, 99, 96, WINDOW).unwrap();
+        for tick in ticks {
+            recorder.record(tick, WINDOW).unwrap();
+        }
+        assert_eq!(recorder.ticks(), ticks.len() as u64);
+        recorder.flush().unwrap();
+    }
+
+    fn play(replay: &mut Replay) -> Vec<Tick> {
+        std::iter::from_fn(|| replay.next_tick(WINDOW).unwrap()).collect()
+    }
+
+    #[test]
+    fn round_trip() {
+        let path = path("round-trip");
+        record(&path, &ticks());
+        let text = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(
+            text.lines().next().unwrap(),
+            r#"{"seed":99,"updates_per_second":96,"window":{"size":[800,600],"scale_factor":1.5}}"#
+        );
+        // empty ticks are empty lines of json, not nothing.
+        assert_eq!(text.lines().nth(1), Some("{}"));
+
+        let mut replay = Replay::open(&path, 96).unwrap();
+        assert_eq!((replay.seed, replay.window), (99, WINDOW));
+        assert_eq!(play(&mut replay), ticks());
+        assert_eq!(replay.played(), 3);
+        assert_eq!(replay.next_tick(WINDOW), Ok(None));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn the_window_has_to_match() {
+        let path = path("window");
+        let mut recorder = Recorder::create(&path, 0, 96, WINDOW).unwrap();
+        let resized = Window {
+            size: (801, 600),
+            ..WINDOW
+        };
+        assert!(recorder.record(&Tick::default(), resized).is_err());
+        recorder.record(&Tick::default(), WINDOW).unwrap();
+        recorder.flush().unwrap();
+
+        let mut replay = Replay::open(&path, 96).unwrap();
+        let rescaled = Window {
+            scale_factor: 2.0,
+            ..WINDOW
+        };
+        assert!(replay.next_tick(rescaled).is_err());
+        assert_eq!(replay.next_tick(WINDOW), Ok(Some(Tick::default())));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn bad_headers() {
+        let path = path("headers");
+        record(&path, &ticks());
+        let error = Replay::open(&path, 60).err().unwrap();
+        assert!(error.contains("96 updates per second"), "{}", error);
+
+        std::fs::write(&path, "").unwrap();
+        assert!(Replay::open(&path, 96).err().unwrap().contains("is empty"));
+        std::fs::write(&path, "not json\n{}\n").unwrap();
+        assert!(Replay::open(&path, 96).err().unwrap().contains("header"));
+        // the header's cut off partway through.
+        std::fs::write(&path, r#"{"seed":99,"updates_per"#).unwrap();
+        assert!(Replay::open(&path, 96).err().unwrap().contains("header"));
+        std::fs::remove_file(&path).unwrap();
+        assert!(Replay::open(&path, 96).is_err());
+    }
+
+    // a crash partway through writing leaves half a line at the end, and a corrupted file leaves
+    // who knows what; either way it plays up to there.
+    #[test]
+    fn truncated_and_garbage_ticks() {
+        let path = path("truncated");
+        record(&path, &ticks());
+        let whole = std::fs::read_to_string(&path).unwrap();
+
+        let truncated = &whole[..whole.len() - 5];
+        std::fs::write(&path, truncated).unwrap();
+        let mut replay = Replay::open(&path, 96).unwrap();
+        assert_eq!(play(&mut replay), ticks()[..2]);
+
+        let mut lines: Vec<&str> = whole.lines().collect();
+        lines.insert(2, "garbage");
+        std::fs::write(&path, lines.join("\n")).unwrap();
+        let mut replay = Replay::open(&path, 96).unwrap();
+        assert_eq!(play(&mut replay), ticks()[..1]);
+
+        // a tick with something in it that isn't a button.
+        let mut lines: Vec<&str> = whole.lines().collect();
+        lines[2] = r#"{"pressed":[{"key":"NotAKey"}]}"#;
+        std::fs::write(&path, lines.join("\n")).unwrap();
+        let mut replay = Replay::open(&path, 96).unwrap();
+        assert_eq!(play(&mut replay), ticks()[..1]);
+        std::fs::remove_file(path).unwrap();
+    }
+}
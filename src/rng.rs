@@ -0,0 +1,87 @@
+// a small seeded random number generator (splitmix64). everything random in Game::update has to
+// come from one of these, since a replay only brings back the seed.
+#[derive(Clone, Debug)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    // something different every run, for when nothing's being replayed.
+    pub fn seed_from_time() -> u64 {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|since| since.as_nanos() as u64)
+            .unwrap_or(0)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    // from 0.0 up to (but not including) 1.0.
+    pub fn next_f32(&mut self) -> f32 {
+        // the top 24 bits are all an f32 can hold exactly.
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    pub fn range(&mut self, min: f32, max: f32) -> f32 {
+        min + (max - min) * self.next_f32()
+    }
+
+    // from 0 up to (but not including) n. None for 0, which there's nothing below.
+    pub fn below(&mut self, n: u64) -> Option<u64> {
+        (n != 0).then(|| self.next_u64() % n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splitmix64() {
+        // the reference implementation's first few from a seed of 0.
+        let mut rng = Rng::new(0);
+        assert_eq!(rng.next_u64(), 0xe220_a839_7b1d_cdaf);
+        assert_eq!(rng.next_u64(), 0x6e78_9e6a_a1b9_65f4);
+        assert_eq!(rng.next_u64(), 0x06c4_5d18_8009_454f);
+    }
+
+    #[test]
+    fn same_seed_same_numbers() {
+        let (mut a, mut b) = (Rng::new(1234), Rng::new(1234));
+        for _ in 0..1000 {
+            assert_eq!(a.next_u64(), b.next_u64());
+        }
+        // and a copy carries on from where it was taken.
+        let mut c = a.clone();
+        assert_eq!(a.next_f32(), c.next_f32());
+        let (mut a, mut b) = (Rng::new(1234), Rng::new(1235));
+        assert_ne!(
+            (0..8).map(|_| a.next_u64()).collect::<Vec<_>>(),
+            (0..8).map(|_| b.next_u64()).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn ranges() {
+        let mut rng = Rng::new(42);
+        for _ in 0..10_000 {
+            let f = rng.next_f32();
+            assert!((0.0..1.0).contains(&f), "{}", f);
+            let f = rng.range(-2.0, 3.0);
+            assert!((-2.0..3.0).contains(&f), "{}", f);
+            assert!(rng.below(7).unwrap() < 7);
+        }
+        assert_eq!(rng.below(1), Some(0));
+        assert_eq!(rng.below(0), None);
+    }
+}
@@ -0,0 +1,697 @@
+use winit::event::{MouseButton, VirtualKeyCode};
+
+use crate::input::{Button, Input, MouseAxis};
+use crate::renderer::{self, text, texture, ui};
+
+// gaps between a widget's edge and what's inside it, in logical pixels.
+const INSET: f32 = 8.0;
+
+pub type WidgetId = usize; // index into Widgets.
+
+// what happened to the widgets over a tick, in the order it happened.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Event {
+    Clicked(WidgetId),
+    Toggled(WidgetId, bool),
+    Changed(WidgetId, f32),      // a slider's new value.
+    Edited(WidgetId, String),    // a text field's new text.
+    Submitted(WidgetId, String), // enter was pressed in a text field.
+    Selected(WidgetId, usize),   // an index into a list's items.
+}
+
+// what the widgets look like. the textures are stretched over whole widgets (or parts of them),
+// so plain colours work best for now.
+pub struct Theme {
+    pub font: text::FontId,
+    pub text_size: f32,
+    pub text_colour: [f32; 4],
+    pub row_height: f32, // for lists.
+    pub normal: texture::Texture,
+    pub hovered: texture::Texture,
+    pub pressed: texture::Texture,
+    pub focused: texture::Texture,
+    // toggles that are on, slider handles, selected list rows and the text field's caret.
+    pub accent: texture::Texture,
+    pub clear: texture::Texture, // for parts that are hidden.
+}
+
+impl Theme {
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        font: text::FontId,
+    ) -> Result<Self, String> {
+        let colour = |label: &str, rgba: [u8; 4]| {
+            texture::Texture::from_raw_data(Some(label), device, queue, &rgba, (1, 1))
+                .map_err(|error| format!("{}: {}", label, error))
+        };
+        Ok(Self {
+            font,
+            text_size: 16.0,
+            text_colour: [1.0; 4],
+            row_height: 24.0,
+            normal: colour("widget normal", [48, 52, 64, 230])?,
+            hovered: colour("widget hovered", [64, 70, 88, 240])?,
+            pressed: colour("widget pressed", [32, 34, 44, 240])?,
+            focused: colour("widget focused", [56, 64, 96, 240])?,
+            accent: colour("widget accent", [96, 160, 255, 255])?,
+            clear: colour("widget clear", [0, 0, 0, 0])?,
+        })
+    }
+}
+
+// where a widget goes; same as the fields on ui::Element.
+#[derive(Copy, Clone, Debug)]
+pub struct Placement {
+    pub anchor: ui::Anchor,
+    pub pivot: ui::Anchor,
+    pub position: cgmath::Point2<f32>,
+    pub size: ui::Size,
+    pub parent: Option<usize>,
+}
+
+impl Default for Placement {
+    fn default() -> Self {
+        Self {
+            anchor: ui::Anchor::TOP_LEFT,
+            pivot: ui::Anchor::TOP_LEFT,
+            position: (0.0, 0.0).into(),
+            size: ui::Size::pixels(160.0, 32.0),
+            parent: None,
+        }
+    }
+}
+
+enum Kind {
+    Button,
+    Toggle {
+        check: usize,
+        on: bool,
+    },
+    Slider {
+        handle: usize,
+        value: f32,
+        range: (f32, f32),
+        step: f32, // 0.0 for none.
+    },
+    TextField {
+        text: usize,
+        caret: usize,
+        value: String,
+    },
+    List {
+        rows: Vec<(usize, usize)>, // the highlight element and text for each visible row.
+        items: Vec<String>,
+        selected: Option<usize>,
+        offset: usize, // the first item showing.
+    },
+}
+
+struct Widget {
+    background: usize, // index into the state's ui elements; the other parts are its children.
+    kind: Kind,
+}
+
+// a retained set of widgets, made out of ordinary ui elements and texts in the state. they hold
+// on to indices into those, so nothing before them should be taken out.
+//
+// everything goes by input::Input, and the pointer is checked against the ui as it was laid out
+// at the start of the tick (see ui::Layout), so widgets act the same in a replay as they did when
+// it was recorded. the mouse (or a finger) clicks and drags, tab and shift+tab move the focus,
+// and the ui_* actions do the rest; see input.toml.
+// つづ: backspace doesn't repeat, and the caret is always at the end of the text.
+pub struct Widgets {
+    pub theme: Theme,
+    widgets: Vec<Widget>,
+    focus: Option<WidgetId>,
+    hovered: Option<WidgetId>,
+    pressed: Option<WidgetId>, // where the pointer went down, until it comes back up.
+}
+
+impl Widgets {
+    pub fn new(theme: Theme) -> Self {
+        Self {
+            theme,
+            widgets: Vec::new(),
+            focus: None,
+            hovered: None,
+            pressed: None,
+        }
+    }
+
+    pub fn button(
+        &mut self,
+        state: &mut renderer::State,
+        placement: Placement,
+        label: &str,
+    ) -> Result<WidgetId, String> {
+        let background = self.element(state, "button", placement)?;
+        self.text(state, label, background, ui::Anchor::CENTRE, 0.0)?;
+        Ok(self.push(background, Kind::Button))
+    }
+
+    // a box that's filled in while it's on, with the label to the right of it. the placement's
+    // size is the box's.
+    pub fn toggle(
+        &mut self,
+        state: &mut renderer::State,
+        placement: Placement,
+        label: &str,
+        on: bool,
+    ) -> Result<WidgetId, String> {
+        let background = self.element(state, "toggle", placement)?;
+        let check = self.element(
+            state,
+            "toggle check",
+            Placement {
+                anchor: ui::Anchor::CENTRE,
+                pivot: ui::Anchor::CENTRE,
+                size: ui::Size::relative(0.6, 0.6),
+                parent: Some(background),
+                ..Default::default()
+            },
+        )?;
+        let label = self.text(state, label, background, ui::Anchor::RIGHT, INSET)?;
+        // outside the box rather than in it.
+        state.texts[label].pivot = ui::Anchor::LEFT;
+        state.texts[label].position.x = INSET;
+        Ok(self.push(background, Kind::Toggle { check, on }))
+    }
+
+    // `step` rounds the value to multiples of itself (counting from the bottom of the range);
+    // 0.0 lets it be anything.
+    pub fn slider(
+        &mut self,
+        state: &mut renderer::State,
+        placement: Placement,
+        range: (f32, f32),
+        value: f32,
+        step: f32,
+    ) -> Result<WidgetId, String> {
+        if range.0 >= range.1 || step < 0.0 {
+            return Err("sliders need a range that goes up and a step that isn't".to_string());
+        }
+        let background = self.element(state, "slider", placement)?;
+        let handle = self.element(
+            state,
+            "slider handle",
+            Placement {
+                pivot: ui::Anchor::CENTRE,
+                size: ui::Size {
+                    width: ui::Length::Pixels(12.0),
+                    height: ui::Length::Relative(1.0),
+                },
+                parent: Some(background),
+                ..Default::default()
+            },
+        )?;
+        let value = snap(value, range, step);
+        Ok(self.push(
+            background,
+            Kind::Slider {
+                handle,
+                value,
+                range,
+                step,
+            },
+        ))
+    }
+
+    pub fn text_field(
+        &mut self,
+        state: &mut renderer::State,
+        placement: Placement,
+        value: &str,
+    ) -> Result<WidgetId, String> {
+        let background = self.element(state, "text field", placement)?;
+        let text = self.text(state, value, background, ui::Anchor::LEFT, INSET)?;
+        let caret = self.element(
+            state,
+            "text field caret",
+            Placement {
+                anchor: ui::Anchor::LEFT,
+                pivot: ui::Anchor::LEFT,
+                size: ui::Size::pixels(2.0, self.theme.text_size),
+                parent: Some(background),
+                ..Default::default()
+            },
+        )?;
+        Ok(self.push(
+            background,
+            Kind::TextField {
+                text,
+                caret,
+                value: value.to_string(),
+            },
+        ))
+    }
+
+    // shows `rows` items at a time, each theme.row_height tall; the placement's height is
+    // ignored. the mouse wheel scrolls it.
+    pub fn list(
+        &mut self,
+        state: &mut renderer::State,
+        placement: Placement,
+        items: Vec<String>,
+        rows: usize,
+    ) -> Result<WidgetId, String> {
+        let row_height = self.theme.row_height;
+        let placement = Placement {
+            size: ui::Size {
+                height: ui::Length::Pixels(row_height * rows as f32),
+                ..placement.size
+            },
+            ..placement
+        };
+        let background = self.element(state, "list", placement)?;
+        // long items get cut off at the edge rather than spilling out.
+        state.ui_elements[background].clip = true;
+        let mut row_parts = Vec::with_capacity(rows);
+        for row in 0..rows {
+            let highlight = self.element(
+                state,
+                "list row",
+                Placement {
+                    position: (0.0, row as f32 * row_height).into(),
+                    size: ui::Size {
+                        width: ui::Length::Relative(1.0),
+                        height: ui::Length::Pixels(row_height),
+                    },
+                    parent: Some(background),
+                    ..Default::default()
+                },
+            )?;
+            state.ui_elements[highlight].set_texture(&state.device, &self.theme.clear);
+            let text = self.text(state, "", highlight, ui::Anchor::LEFT, INSET)?;
+            row_parts.push((highlight, text));
+        }
+        Ok(self.push(
+            background,
+            Kind::List {
+                rows: row_parts,
+                items,
+                selected: None,
+                offset: 0,
+            },
+        ))
+    }
+
+    pub fn is_on(&self, widget: WidgetId) -> Option<bool> {
+        match self.widgets.get(widget)?.kind {
+            Kind::Toggle { on, .. } => Some(on),
+            _ => None,
+        }
+    }
+
+    pub fn value(&self, widget: WidgetId) -> Option<f32> {
+        match self.widgets.get(widget)?.kind {
+            Kind::Slider { value, .. } => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn text_of(&self, widget: WidgetId) -> Option<&str> {
+        match &self.widgets.get(widget)?.kind {
+            Kind::TextField { value, .. } => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn selected(&self, widget: WidgetId) -> Option<usize> {
+        match self.widgets.get(widget)?.kind {
+            Kind::List { selected, .. } => selected,
+            _ => None,
+        }
+    }
+
+    pub fn focus(&self) -> Option<WidgetId> {
+        self.focus
+    }
+
+    pub fn set_focus(&mut self, widget: Option<WidgetId>) {
+        self.focus = widget.filter(|widget| *widget < self.widgets.len());
+    }
+
+    // whether a text field has the keyboard, in which case update() keeps the keys from the game.
+    pub fn wants_text(&self) -> bool {
+        self.focus
+            .is_some_and(|focus| matches!(self.widgets[focus].kind, Kind::TextField { .. }))
+    }
+
+    // call this every update, after input.tick() and before anything else looks at the keys:
+    // while a text field has the keyboard, it takes the keys for the tick (see
+    // Input::take_keyboard()). the widgets are hit-tested against State::ui_layout().
+    pub fn update(&mut self, input: &mut Input, state: &mut renderer::State) -> Vec<Event> {
+        let mut events = Vec::new();
+        let scale_factor = state.scale_factor() as f32;
+        let cursor = input
+            .cursor()
+            .map(|(x, y)| (x / scale_factor, y / scale_factor));
+        self.hovered = cursor.and_then(|cursor| self.hit(state, cursor));
+
+        let left = Button::Mouse(MouseButton::Left);
+        if input.button_pressed(left) {
+            self.pressed = self.hovered;
+            self.focus = self.hovered; // clicking on nothing lets go of the focus.
+            if let (Some(widget), Some(cursor)) = (self.hovered, cursor) {
+                self.point(widget, cursor, state, &mut events);
+            }
+        } else if input.button_held(left) {
+            // only sliders care about dragging.
+            if let (Some(widget), Some(cursor)) = (self.pressed, cursor) {
+                if matches!(self.widgets[widget].kind, Kind::Slider { .. }) {
+                    self.point(widget, cursor, state, &mut events);
+                }
+            }
+        }
+        if input.button_released(left) {
+            if let Some(widget) = self.pressed.take() {
+                if self.hovered == Some(widget) {
+                    self.activate(widget, &mut events);
+                }
+            }
+        }
+
+        let wheel = input.mouse(MouseAxis::Wheel);
+        if let (Some(widget), true) = (self.hovered, wheel != 0.0) {
+            if let Kind::List {
+                rows,
+                items,
+                offset,
+                ..
+            } = &mut self.widgets[widget].kind
+            {
+                let last = items.len().saturating_sub(rows.len());
+                // scrolling up (positive) goes back towards the first item.
+                *offset = (*offset as i64 - wheel.round() as i64).clamp(0, last as i64) as usize;
+            }
+        }
+
+        // the tick that lets go of the field (with ui_back, say) still counts as typing.
+        let typing = self.wants_text();
+        self.keyboard(input, &mut events);
+        if typing || self.wants_text() {
+            input.take_keyboard();
+        }
+        self.paint(state);
+        events
+    }
+
+    fn keyboard(&mut self, input: &Input, events: &mut Vec<Event>) {
+        let shift = input.button_held(Button::Key(VirtualKeyCode::LShift))
+            || input.button_held(Button::Key(VirtualKeyCode::RShift));
+        if input.button_pressed(Button::Key(VirtualKeyCode::Tab)) {
+            self.move_focus(if shift { -1 } else { 1 });
+        }
+
+        let Some(widget) = self.focus else {
+            if input.pressed("ui_down") {
+                self.move_focus(1);
+            } else if input.pressed("ui_up") {
+                self.move_focus(-1);
+            }
+            return;
+        };
+        if input.pressed("ui_back") {
+            self.focus = None;
+            return;
+        }
+
+        match &mut self.widgets[widget].kind {
+            Kind::TextField { value, .. } => {
+                let before = value.len();
+                value.push_str(input.text());
+                let erased = input.button_pressed(Button::Key(VirtualKeyCode::Back))
+                    && value.pop().is_some();
+                if value.len() != before || erased {
+                    events.push(Event::Edited(widget, value.clone()));
+                }
+                if input.button_pressed(Button::Key(VirtualKeyCode::Return)) {
+                    events.push(Event::Submitted(widget, value.clone()));
+                }
+            }
+            Kind::Slider {
+                value, range, step, ..
+            } => {
+                let direction = input.pressed("ui_right") as i32 - input.pressed("ui_left") as i32;
+                if direction != 0 {
+                    // a twentieth of the way along if it doesn't have steps.
+                    let amount = match *step > 0.0 {
+                        true => *step,
+                        false => (range.1 - range.0) / 20.0,
+                    };
+                    let moved = snap(*value + amount * direction as f32, *range, *step);
+                    if moved != *value {
+                        *value = moved;
+                        events.push(Event::Changed(widget, moved));
+                    }
+                }
+            }
+            Kind::List {
+                rows,
+                items,
+                selected,
+                offset,
+                ..
+            } if !items.is_empty() => {
+                let direction = input.pressed("ui_down") as i64 - input.pressed("ui_up") as i64;
+                if direction != 0 {
+                    let next = match *selected {
+                        Some(selected) => {
+                            (selected as i64 + direction).clamp(0, items.len() as i64 - 1) as usize
+                        }
+                        None => 0,
+                    };
+                    if *selected != Some(next) {
+                        *selected = Some(next);
+                        events.push(Event::Selected(widget, next));
+                    }
+                    // keeps the selection in view.
+                    if next < *offset {
+                        *offset = next;
+                    } else if next >= *offset + rows.len() {
+                        *offset = next + 1 - rows.len();
+                    }
+                }
+                return;
+            }
+            _ => {}
+        }
+
+        // up and down move between widgets unless the widget has a use for them.
+        if !matches!(self.widgets[widget].kind, Kind::TextField { .. })
+            && input.pressed("ui_activate")
+        {
+            self.activate(widget, events);
+        }
+        if input.pressed("ui_down") {
+            self.move_focus(1);
+        } else if input.pressed("ui_up") {
+            self.move_focus(-1);
+        }
+    }
+
+    fn move_focus(&mut self, by: i64) {
+        let count = self.widgets.len() as i64;
+        if count == 0 {
+            return;
+        }
+        let next = match self.focus {
+            Some(focus) => (focus as i64 + by).rem_euclid(count),
+            None if by > 0 => 0,
+            None => count - 1,
+        };
+        self.focus = Some(next as usize);
+    }
+
+    // a click (or tap) coming back up on the widget it went down on, or the activate action.
+    fn activate(&mut self, widget: WidgetId, events: &mut Vec<Event>) {
+        match &mut self.widgets[widget].kind {
+            Kind::Button => events.push(Event::Clicked(widget)),
+            Kind::Toggle { on, .. } => {
+                *on = !*on;
+                events.push(Event::Toggled(widget, *on));
+            }
+            _ => {}
+        }
+    }
+
+    // whatever's drawn on top at a point gets it, even if that isn't a widget; a widget's own
+    // parts count as the widget.
+    fn hit(&self, state: &renderer::State, point: (f32, f32)) -> Option<WidgetId> {
+        let elements = &state.ui_elements;
+        let mut element = Some(state.ui_layout().element_at(point)?);
+        while let Some(i) = element {
+            if let Some(widget) = self
+                .widgets
+                .iter()
+                .position(|widget| widget.background == i)
+            {
+                return Some(widget);
+            }
+            element = elements[i].parent.filter(|parent| *parent < i);
+        }
+        None
+    }
+
+    // the pointer going down on (or dragging over) a widget, on the window in logical pixels.
+    fn point(
+        &mut self,
+        widget: WidgetId,
+        point: (f32, f32),
+        state: &renderer::State,
+        events: &mut Vec<Event>,
+    ) {
+        let Some(background) = state.ui_layout().get(self.widgets[widget].background) else {
+            return;
+        };
+        let rect = background.rect;
+        // in the same space as the rectangle, so it still works once it's scaled.
+        let Some((x, y)) = background.to_local(point) else {
+            return;
+        };
+        match &mut self.widgets[widget].kind {
+            Kind::Slider {
+                value, range, step, ..
+            } => {
+                let along = ((x - rect.x) / rect.width.max(1.0)).clamp(0.0, 1.0);
+                let moved = snap(range.0 + (range.1 - range.0) * along, *range, *step);
+                if moved != *value {
+                    *value = moved;
+                    events.push(Event::Changed(widget, moved));
+                }
+            }
+            Kind::List {
+                items,
+                selected,
+                offset,
+                ..
+            } => {
+                let item = ((y - rect.y) / self.theme.row_height) as usize + *offset;
+                if item < items.len() && *selected != Some(item) {
+                    *selected = Some(item);
+                    events.push(Event::Selected(widget, item));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // brings the elements and texts in line with the widgets.
+    fn paint(&self, state: &mut renderer::State) {
+        let theme = &self.theme;
+        for (id, widget) in self.widgets.iter().enumerate() {
+            let look = if self.pressed == Some(id) && self.hovered == Some(id) {
+                &theme.pressed
+            } else if self.hovered == Some(id) {
+                &theme.hovered
+            } else if self.focus == Some(id) {
+                &theme.focused
+            } else {
+                &theme.normal
+            };
+            state.ui_elements[widget.background].set_texture(&state.device, look);
+
+            match &widget.kind {
+                Kind::Button => {}
+                Kind::Toggle { check, on } => {
+                    let look = if *on { &theme.accent } else { &theme.clear };
+                    state.ui_elements[*check].set_texture(&state.device, look);
+                }
+                Kind::Slider {
+                    handle,
+                    value,
+                    range,
+                    ..
+                } => {
+                    let along = (value - range.0) / (range.1 - range.0);
+                    state.ui_elements[*handle].anchor = ui::Anchor { x: along, y: 0.5 };
+                    state.ui_elements[*handle].set_texture(&state.device, &theme.accent);
+                }
+                Kind::TextField { text, caret, value } => {
+                    state.texts[*text].set_text(value);
+                    let width = state.texts[*text].dimensions().0;
+                    let caret = &mut state.ui_elements[*caret];
+                    caret.position.x = INSET + width;
+                    let look = match self.focus == Some(id) {
+                        true => &theme.accent,
+                        false => &theme.clear,
+                    };
+                    caret.set_texture(&state.device, look);
+                }
+                Kind::List {
+                    rows,
+                    items,
+                    selected,
+                    offset,
+                } => {
+                    for (row, (highlight, text)) in rows.iter().enumerate() {
+                        let item = offset + row;
+                        state.texts[*text].set_text(items.get(item).map_or("", String::as_str));
+                        let look = match *selected == Some(item) {
+                            true => &theme.accent,
+                            false => &theme.clear,
+                        };
+                        state.ui_elements[*highlight].set_texture(&state.device, look);
+                    }
+                }
+            }
+        }
+    }
+
+    fn push(&mut self, background: usize, kind: Kind) -> WidgetId {
+        self.widgets.push(Widget { background, kind });
+        self.widgets.len() - 1
+    }
+
+    fn element(
+        &self,
+        state: &mut renderer::State,
+        label: &str,
+        placement: Placement,
+    ) -> Result<usize, String> {
+        let mut builder = ui::Element::builder(label, self.theme.normal.clone())
+            .anchor(placement.anchor)
+            .pivot(placement.pivot)
+            .position(placement.position)
+            .size(placement.size);
+        if let Some(parent) = placement.parent {
+            builder = builder.parent(parent);
+        }
+        state.ui_elements.push(builder.build(&state.device)?);
+        Ok(state.ui_elements.len() - 1)
+    }
+
+    // a line of text inside `parent`, `inset` pixels in from the anchor's side.
+    fn text(
+        &self,
+        state: &mut renderer::State,
+        value: &str,
+        parent: usize,
+        anchor: ui::Anchor,
+        inset: f32,
+    ) -> Result<usize, String> {
+        let text = text::Text::builder(
+            format!("{} text", state.ui_elements[parent].label),
+            self.theme.font,
+        )
+        .text(value)
+        .anchor(anchor)
+        .pivot(anchor)
+        .position((inset * (1.0 - anchor.x * 2.0), 0.0))
+        .size(self.theme.text_size)
+        .colour(self.theme.text_colour)
+        .parent(parent)
+        .build()?;
+        state.texts.push(text);
+        Ok(state.texts.len() - 1)
+    }
+}
+
+fn snap(value: f32, (min, max): (f32, f32), step: f32) -> f32 {
+    let value = match step > 0.0 {
+        true => min + ((value - min) / step).round() * step,
+        false => value,
+    };
+    value.clamp(min, max)
+}